@@ -0,0 +1,50 @@
+//! Compares the strategies reading the files to verify them:
+//! `cargo bench --bench verify --features mmap`, the size of the file in MB can be set with
+//! `DWLDUTIL_BENCH_MB`, DEFAULT = 256
+
+use std::{
+    fs,
+    hint::black_box,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use dwldutil::{DLHashType, DLHashes, VerifyStrategy};
+
+/// Runs of every strategy, the fastest one is reported
+const RUNS: usize = 5;
+
+fn main() {
+    let megabytes: usize = std::env::var("DWLDUTIL_BENCH_MB")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(256);
+    let data: Vec<u8> = (0..megabytes * 1024 * 1024)
+        .map(|i| (i as u32).wrapping_mul(2654435761) as u8)
+        .collect();
+    let path = std::env::temp_dir().join(format!("dwldutil-bench-{}", std::process::id()));
+    fs::write(&path, &data).unwrap();
+    let hashes = DLHashes::new().sha256(&DLHashType::SHA256.compute(&data));
+    drop(data);
+
+    for (name, strategy) in [
+        ("buffered", VerifyStrategy::Buffered),
+        ("mmap", VerifyStrategy::Mmap),
+    ] {
+        let best = (0..RUNS)
+            .map(|_| run(&hashes, &path, strategy))
+            .min()
+            .unwrap_or_default();
+        let throughput = megabytes as f64 / best.as_secs_f64();
+        println!("{:>8}: {:>10.2?} {:>8.1} MB/s", name, best, throughput);
+    }
+    let _ = fs::remove_file(&path);
+}
+
+fn run(hashes: &DLHashes, path: &Path, strategy: VerifyStrategy) -> Duration {
+    let started = Instant::now();
+    let valid = hashes.verify_file_with(black_box(path), strategy).unwrap();
+    let elapsed = started.elapsed();
+    assert!(valid);
+    elapsed
+}
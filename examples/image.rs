@@ -13,5 +13,5 @@ fn main() {
                 .with_hashes(DLHashes::new().sha1("379f5137831350c900e757b39e525b9db1426d53")),
         );
     // Start download
-    dl.start();
+    dl.start().unwrap();
 }
@@ -0,0 +1,50 @@
+/* C API of dwldutil, built with the `capi` feature. See src/capi.rs */
+#ifndef DWLDUTIL_H
+#define DWLDUTIL_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DWLD_OK 0
+#define DWLD_ERR_NULL -1
+#define DWLD_ERR_UTF8 -2
+#define DWLD_ERR_STARTED -3
+#define DWLD_ERR_NOT_STARTED -4
+#define DWLD_ERR_FAILED -5
+#define DWLD_ERR_CANCELLED -6
+
+typedef struct DwldDownloader DwldDownloader;
+
+typedef struct DwldProgress {
+    uint64_t downloaded;
+    uint64_t total;
+    uint32_t files_total;
+    uint32_t files_done;
+    uint32_t files_failed;
+    bool finished;
+} DwldProgress;
+
+DwldDownloader *dwld_downloader_new(void);
+void dwld_downloader_free(DwldDownloader *downloader);
+/* sha256 may be NULL */
+int dwld_downloader_add_file(DwldDownloader *downloader, const char *url, const char *path,
+                             const char *sha256);
+int dwld_downloader_set_max_concurrent(DwldDownloader *downloader,
+                                       uint32_t max_concurrent_downloads);
+int dwld_downloader_start(DwldDownloader *downloader);
+/* poll, cancel, wait and error can be called from any thread once started */
+int dwld_downloader_poll(const DwldDownloader *downloader, DwldProgress *progress);
+int dwld_downloader_cancel(const DwldDownloader *downloader);
+int dwld_downloader_wait(const DwldDownloader *downloader);
+/* valid until the downloader is freed, NULL if nothing failed */
+const char *dwld_downloader_error(const DwldDownloader *downloader);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
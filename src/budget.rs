@@ -0,0 +1,30 @@
+use smol::lock::{Mutex, Semaphore, SemaphoreGuard};
+
+/// Budget of the file handles open at once by the downloads of a session
+pub(crate) struct HandleBudget {
+    size: usize,
+    permits: Semaphore,
+    // only one download takes permits at a time, so two of them never wait for each other
+    acquiring: Mutex<()>,
+}
+
+impl HandleBudget {
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            permits: Semaphore::new(size),
+            acquiring: Mutex::new(()),
+        }
+    }
+    /// Takes the handles of a download, released when the guards are dropped.
+    /// Downloads needing more than the whole budget take all of it
+    pub async fn acquire(&self, handles: usize) -> Vec<SemaphoreGuard<'_>> {
+        let _acquiring = self.acquiring.lock().await;
+        let mut guards = Vec::with_capacity(handles.min(self.size));
+        for _ in 0..handles.clamp(1, self.size) {
+            guards.push(self.permits.acquire().await);
+        }
+        guards
+    }
+}
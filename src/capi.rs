@@ -0,0 +1,398 @@
+//! C API, to embed the downloader in launchers written in other languages. Build the crate
+//! as a `cdylib` or a `staticlib` with the `capi` feature and include `include/dwldutil.h`.
+//!
+//! The downloader runs in its own thread after [dwld_downloader_start], the caller polls its
+//! progress and waits for it or cancels it. Every function returns one of the `DWLD_*`
+//! codes, the message of the last failure is given by [dwld_downloader_error]. Once
+//! started, the downloader can be polled, cancelled and waited from any thread.
+
+use std::{
+    ffi::{CStr, CString, c_char, c_int},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use crate::{
+    DLFile, DLHandle, DownloadPlan, DownloadReport, Downloader, DwldError,
+    indicator::{IndicateSignal, Indicator, IndicatorFactory},
+};
+
+/// The operation succeeded
+pub const DWLD_OK: c_int = 0;
+/// A pointer argument is null
+pub const DWLD_ERR_NULL: c_int = -1;
+/// A string argument isn't valid UTF-8
+pub const DWLD_ERR_UTF8: c_int = -2;
+/// The downloader was already started
+pub const DWLD_ERR_STARTED: c_int = -3;
+/// The downloader wasn't started
+pub const DWLD_ERR_NOT_STARTED: c_int = -4;
+/// Some files failed, see [dwld_downloader_error]
+pub const DWLD_ERR_FAILED: c_int = -5;
+/// The download was cancelled
+pub const DWLD_ERR_CANCELLED: c_int = -6;
+
+/// Downloader of the C API, created by [dwld_downloader_new]
+pub struct DwldDownloader {
+    plan: DownloadPlan,
+    handle: DLHandle,
+    progress: Arc<Progress>,
+    started: bool,
+    run: Mutex<Run>,
+}
+
+/// Execution of the started downloader
+#[derive(Default)]
+struct Run {
+    worker: Option<thread::JoinHandle<Result<DownloadReport, DwldError>>>,
+    /// Code of the finished download
+    result: Option<c_int>,
+    /// Message of the failure, only set once so the pointers given to the caller are valid
+    error: Option<CString>,
+}
+
+/// Progress of the downloads, filled by [dwld_downloader_poll]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DwldProgress {
+    /// Bytes written by the downloads
+    pub downloaded: u64,
+    /// Size of the files, 0 until the size of the files is known
+    pub total: u64,
+    /// Files added to the downloader
+    pub files_total: u32,
+    /// Files downloaded, cached or skipped
+    pub files_done: u32,
+    /// Files failed or cancelled
+    pub files_failed: u32,
+    /// Every file finished, [dwld_downloader_wait] returns without blocking
+    pub finished: bool,
+}
+
+/// Counters updated by the indicators of the files
+#[derive(Debug, Default)]
+struct Progress {
+    files: AtomicU32,
+    downloaded: AtomicU64,
+    total: AtomicU64,
+    files_done: AtomicU32,
+    files_failed: AtomicU32,
+    finished: AtomicBool,
+}
+
+/// Indicator counting the progress of every file
+#[derive(Default)]
+struct Counter(Arc<Progress>);
+
+impl IndicatorFactory for Counter {
+    fn create_task(&self, _name: &str, size: u64) -> impl Indicator {
+        self.0.total.fetch_add(size, Ordering::Relaxed);
+        CounterChild {
+            progress: Arc::clone(&self.0),
+            position: 0,
+            sized: size > 0,
+            finished: false,
+        }
+    }
+}
+
+struct CounterChild {
+    progress: Arc<Progress>,
+    position: u64,
+    sized: bool,
+    finished: bool,
+}
+
+impl Indicator for CounterChild {
+    fn effect(&mut self, position: u64) {
+        // the position goes back after a reset or a rewind
+        let downloaded = &self.progress.downloaded;
+        match position.checked_sub(self.position) {
+            Some(advance) => downloaded.fetch_add(advance, Ordering::Relaxed),
+            None => downloaded.fetch_sub(self.position - position, Ordering::Relaxed),
+        };
+        self.position = position;
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        let counter = match signal {
+            IndicateSignal::Size(size) if !self.sized => {
+                self.sized = true;
+                self.progress.total.fetch_add(size, Ordering::Relaxed);
+                return;
+            }
+            IndicateSignal::Success() | IndicateSignal::Cached() | IndicateSignal::Skipped(_) => {
+                &self.progress.files_done
+            }
+            IndicateSignal::Fail(_) | IndicateSignal::Cancelled(_) => &self.progress.files_failed,
+            // the bytes discarded by a retry don't count
+            IndicateSignal::Reset() => return self.effect(0),
+            IndicateSignal::Rewind(position) => return self.effect(position),
+            _ => return,
+        };
+        // the companions and the extraction may signal the end again
+        if !self.finished {
+            self.finished = true;
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl DwldDownloader {
+    fn run(&self) -> std::sync::MutexGuard<'_, Run> {
+        self.run.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Run {
+    fn fail(&mut self, error: &str, result: c_int) -> c_int {
+        self.error = CString::new(error.replace('\0', " ")).ok();
+        self.result = Some(result);
+        result
+    }
+}
+
+/// String of a C argument
+///
+/// # Safety
+///
+/// `string` must be null or a valid nul-terminated string
+unsafe fn argument<'a>(string: *const c_char) -> Result<&'a str, c_int> {
+    if string.is_null() {
+        return Err(DWLD_ERR_NULL);
+    }
+    // SAFETY: checked non-null, the caller guarantees it's nul-terminated
+    unsafe { CStr::from_ptr(string) }
+        .to_str()
+        .map_err(|_| DWLD_ERR_UTF8)
+}
+
+/// Creates a downloader without files, free it with [dwld_downloader_free]
+#[unsafe(no_mangle)]
+pub extern "C" fn dwld_downloader_new() -> *mut DwldDownloader {
+    Box::into_raw(Box::new(DwldDownloader {
+        plan: DownloadPlan::default(),
+        handle: DLHandle::new(),
+        progress: Arc::default(),
+        started: false,
+        run: Mutex::default(),
+    }))
+}
+
+/// Frees the downloader, cancelling and waiting for its downloads
+///
+/// # Safety
+///
+/// `downloader` must be null or returned by [dwld_downloader_new] and not freed yet
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_free(downloader: *mut DwldDownloader) {
+    if downloader.is_null() {
+        return;
+    }
+    // SAFETY: the caller gives the ownership of a downloader of dwld_downloader_new
+    let downloader = unsafe { Box::from_raw(downloader) };
+    if let Some(worker) = downloader.run().worker.take() {
+        downloader.handle.cancel();
+        let _ = worker.join();
+    }
+}
+
+/// Adds the file of the URL downloaded to the path. The SHA-256 hash is optional, null to
+/// download the file without verifying it
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new], not used by
+/// other threads, the strings must be null or valid nul-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_add_file(
+    downloader: *mut DwldDownloader,
+    url: *const c_char,
+    path: *const c_char,
+    sha256: *const c_char,
+) -> c_int {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_mut() }) else {
+        return DWLD_ERR_NULL;
+    };
+    if downloader.started {
+        return DWLD_ERR_STARTED;
+    }
+    // SAFETY: the caller guarantees the strings are null or nul-terminated
+    let (url, path) = match unsafe { (argument(url), argument(path)) } {
+        (Ok(url), Ok(path)) => (url, path),
+        (Err(code), _) | (_, Err(code)) => return code,
+    };
+    let mut file = DLFile::new().with_url(url).with_path(path);
+    if !sha256.is_null() {
+        // SAFETY: checked non-null, the caller guarantees it's nul-terminated
+        match unsafe { argument(sha256) } {
+            Ok(sha256) => file = file.with_hashes(crate::DLHashes::new().sha256(sha256)),
+            Err(code) => return code,
+        }
+    }
+    downloader.plan.files.push(file);
+    DWLD_OK
+}
+
+/// Sets the maximum number of concurrent downloads
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new], not used by
+/// other threads
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_set_max_concurrent(
+    downloader: *mut DwldDownloader,
+    max_concurrent_downloads: u32,
+) -> c_int {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_mut() }) else {
+        return DWLD_ERR_NULL;
+    };
+    if downloader.started {
+        return DWLD_ERR_STARTED;
+    }
+    downloader.plan.max_concurrent_downloads = (max_concurrent_downloads as usize).max(1);
+    DWLD_OK
+}
+
+/// Starts downloading the files in a new thread, without blocking
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new], not used by
+/// other threads
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_start(downloader: *mut DwldDownloader) -> c_int {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_mut() }) else {
+        return DWLD_ERR_NULL;
+    };
+    if downloader.started {
+        return DWLD_ERR_STARTED;
+    }
+    downloader.started = true;
+    let plan = downloader.plan.clone();
+    let (handle, progress) = (downloader.handle.clone(), Arc::clone(&downloader.progress));
+    let files = plan.files.len() as u32;
+    progress.files.store(files, Ordering::Relaxed);
+    let worker = thread::Builder::new()
+        .name("dwldutil-capi".to_string())
+        .spawn(move || {
+            let result = Downloader::from_plan(plan)
+                .with_indicator(Counter(Arc::clone(&progress)))
+                .with_handle(handle)
+                .start();
+            progress.finished.store(true, Ordering::Release);
+            result
+        });
+    let mut run = downloader.run();
+    match worker {
+        Ok(worker) => {
+            run.worker = Some(worker);
+            DWLD_OK
+        }
+        Err(e) => {
+            downloader.progress.finished.store(true, Ordering::Release);
+            run.fail(&e.to_string(), DWLD_ERR_FAILED)
+        }
+    }
+}
+
+/// Fills the progress of the downloads, it can be called from any thread
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new], `progress` null
+/// or a valid pointer to a `DwldProgress`
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_poll(
+    downloader: *const DwldDownloader,
+    progress: *mut DwldProgress,
+) -> c_int {
+    // SAFETY: the caller guarantees the pointers are null or valid
+    let (Some(downloader), Some(progress)) =
+        (unsafe { downloader.as_ref() }, unsafe { progress.as_mut() })
+    else {
+        return DWLD_ERR_NULL;
+    };
+    let counters = &downloader.progress;
+    *progress = DwldProgress {
+        downloaded: counters.downloaded.load(Ordering::Relaxed),
+        total: counters.total.load(Ordering::Relaxed),
+        files_total: counters.files.load(Ordering::Relaxed),
+        files_done: counters.files_done.load(Ordering::Relaxed),
+        files_failed: counters.files_failed.load(Ordering::Relaxed),
+        finished: counters.finished.load(Ordering::Acquire),
+    };
+    DWLD_OK
+}
+
+/// Cancels the downloads, the running files are aborted. It can be called from any thread
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_cancel(downloader: *const DwldDownloader) -> c_int {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_ref() }) else {
+        return DWLD_ERR_NULL;
+    };
+    downloader.handle.cancel();
+    DWLD_OK
+}
+
+/// Waits for the downloads to finish, returns [DWLD_OK] if every file succeeded. It can be
+/// called from any thread, the concurrent calls wait for the first one
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_wait(downloader: *const DwldDownloader) -> c_int {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_ref() }) else {
+        return DWLD_ERR_NULL;
+    };
+    let mut run = downloader.run();
+    if let Some(result) = run.result {
+        return result;
+    }
+    let Some(worker) = run.worker.take() else {
+        return DWLD_ERR_NOT_STARTED;
+    };
+    match worker.join() {
+        Ok(Ok(_)) => {
+            run.result = Some(DWLD_OK);
+            DWLD_OK
+        }
+        Ok(Err(e)) => match downloader.handle.is_cancelled() {
+            true => run.fail(&e.to_string(), DWLD_ERR_CANCELLED),
+            false => run.fail(&e.to_string(), DWLD_ERR_FAILED),
+        },
+        Err(_) => run.fail("the download thread panicked", DWLD_ERR_FAILED),
+    }
+}
+
+/// Message of the last failure of the downloader, null if nothing failed. It's valid until
+/// the downloader is freed
+///
+/// # Safety
+///
+/// `downloader` must be null or a live downloader of [dwld_downloader_new]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dwld_downloader_error(downloader: *const DwldDownloader) -> *const c_char {
+    // SAFETY: the caller guarantees the pointer is null or a live downloader
+    let Some(downloader) = (unsafe { downloader.as_ref() }) else {
+        return std::ptr::null();
+    };
+    // the message is never replaced, its buffer lives as long as the downloader
+    match &downloader.run().error {
+        Some(error) => error.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
@@ -1,53 +1,177 @@
 use std::{
+    collections::HashSet,
     fs::{self, File},
-    path::{Path, PathBuf},
+    io,
+    path::{Component, Path, PathBuf},
 };
 
 use symlink::symlink_auto;
 
+use crate::{DownloadPlan, storage, validation};
+
+/// Removes a previous destination, it may be a link to the blob and writing over it would
+/// truncate the blob
+fn unlink(file_path: &str) -> io::Result<()> {
+    if fs::symlink_metadata(file_path).is_ok() {
+        storage::remove_file(file_path)?;
+    }
+    Ok(())
+}
+
+/// How the blobs are made available in the destinations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DLLinkMode {
+    /// Symbolic links to the blobs
+    #[default]
+    Symlink,
+    /// Real files sharing the blob, copies when the storage is in another device
+    Hardlink,
+    /// Copies of the blobs, for consumers modifying the files
+    Copy,
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub struct DLStorage {
     pub path: PathBuf,
+    /// How the blobs are made available in the destinations
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub link_mode: DLLinkMode,
+    /// Key used to keep the blobs encrypted at rest
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub encryption_key: Option<crate::encryption::DLEncryptionKey>,
 }
 
 impl DLStorage {
     pub fn new(path: &str) -> Self {
         let path = Path::new(path);
-        if !path.exists() {
-            fs::create_dir_all(path).unwrap();
-        }
+        // failures show up when the blobs are created
+        let _ = fs::create_dir_all(path);
         Self {
             path: path.to_path_buf(),
+            link_mode: DLLinkMode::default(),
+            #[cfg(feature = "encryption")]
+            encryption_key: None,
         }
     }
+    /// Sets how the blobs are made available in the destinations
+    pub fn with_link_mode(mut self, mode: DLLinkMode) -> Self {
+        self.link_mode = mode;
+        self
+    }
+    /// Keeps the blobs encrypted, files are decrypted when materialized
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, key: crate::encryption::DLEncryptionKey) -> Self {
+        self.encryption_key = Some(key);
+        self
+    }
 }
 
 impl DLStorage {
-    pub fn new_file(&self, hash: &str, file_path: &str) -> File {
-        let file = self.file(hash);
-        self.symlink(hash, file_path);
-        file
+    pub fn new_file(&self, hash: &str, file_path: &str) -> io::Result<File> {
+        let file = self.file(hash)?;
+        if self.links_early() {
+            self.symlink(hash, file_path)?;
+        }
+        Ok(file)
+    }
+    /// Checks if the destination links to the blob while it's downloaded, the other
+    /// destinations are materialized once the blob is complete
+    fn links_early(&self) -> bool {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return false;
+        }
+        self.link_mode == DLLinkMode::Symlink
+    }
+    /// Size of the partial blob kept by a failed download
+    pub fn partial_size(&self, hash: &str) -> Option<u64> {
+        fs::metadata(format!("{}.part", self.path(hash)))
+            .ok()
+            .map(|metadata| metadata.len())
     }
-    pub fn symlink(&self, hash: &str, link: &str) -> File {
+    /// Reopens the partial blob to continue writing it
+    pub fn resume_file(&self, hash: &str, file_path: &str) -> io::Result<File> {
+        let blob = self.path(hash);
+        storage::rename(format!("{}.part", blob), &blob)?;
+        let file = fs::OpenOptions::new().append(true).open(&blob)?;
+        if self.links_early() && fs::symlink_metadata(file_path).is_err() {
+            self.symlink(hash, file_path)?;
+        }
+        Ok(file)
+    }
+    /// Makes the blob available in `file_path`, decrypting it if needed
+    pub fn materialize(&self, hash: &str, file_path: &str) -> io::Result<()> {
+        #[cfg(feature = "encryption")]
+        if let Some(key) = &self.encryption_key {
+            unlink(file_path)?;
+            return key.decrypt_file(Path::new(&self.path(hash)), Path::new(file_path));
+        }
+        if fs::symlink_metadata(file_path).is_err() {
+            self.place(hash, file_path)?;
+        }
+        Ok(())
+    }
+    /// Makes the freshly downloaded blob available in `file_path`, the destinations
+    /// linked while downloading are kept
+    pub(crate) fn finish(&self, hash: &str, file_path: &str) -> io::Result<()> {
+        #[cfg(feature = "encryption")]
+        if self.encryption_key.is_some() {
+            return self.seal(hash, file_path);
+        }
+        if self.links_early() {
+            return Ok(());
+        }
+        unlink(file_path)?;
+        self.place(hash, file_path)
+    }
+    /// Links or copies the blob to `file_path` with the link mode
+    fn place(&self, hash: &str, file_path: &str) -> io::Result<()> {
+        let blob = self.path(hash);
+        match self.link_mode {
+            DLLinkMode::Symlink => self.symlink(hash, file_path).map(|_| ()),
+            // hard links can't cross devices, the consumers get a real file anyway
+            DLLinkMode::Hardlink => match storage::retry_locked(Path::new(file_path), || {
+                fs::hard_link(&blob, file_path)
+            }) {
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                    fs::copy(&blob, file_path).map(|_| ())
+                }
+                result => result,
+            },
+            DLLinkMode::Copy => fs::copy(&blob, file_path).map(|_| ()),
+        }
+    }
+    /// Encrypts a freshly downloaded blob, leaving a plain copy in `file_path`
+    #[cfg(feature = "encryption")]
+    pub fn seal(&self, hash: &str, file_path: &str) -> io::Result<()> {
+        let Some(key) = &self.encryption_key else {
+            return Ok(());
+        };
+        let blob = self.path(hash);
+        unlink(file_path)?;
+        fs::copy(&blob, file_path)?;
+        let sealed = format!("{}.enc", blob);
+        key.encrypt_file(Path::new(&blob), Path::new(&sealed))?;
+        storage::rename(sealed, blob)
+    }
+    pub fn symlink(&self, hash: &str, link: &str) -> io::Result<File> {
         let file = self.path(hash);
         let file = Path::new(file.as_str());
         let link = Path::new(link);
 
-        match symlink_auto(file, link) {
-            Ok(_) => {}
-            Err(e) => panic!("Failed to create symlink: {}", e),
-        }
+        storage::retry_locked(link, || symlink_auto(file, link))?;
 
-        File::open(file).unwrap()
+        File::open(file)
     }
-    pub fn file(&self, hash: &str) -> File {
+    pub fn file(&self, hash: &str) -> io::Result<File> {
         let hash_path = self.path.join(&hash[0..2]);
-        if !hash_path.exists() {
-            fs::create_dir_all(&hash_path).unwrap();
-        }
-        File::create(hash_path.join(hash)).unwrap()
+        crate::storage::create_dir_all(&hash_path)?;
+        File::create(hash_path.join(hash))
     }
     pub fn path(&self, hash: &str) -> String {
         self.path
@@ -69,3 +193,129 @@ impl DLStorage {
         }
     }
 }
+
+/// What [cleanup_links] does with the links to missing blobs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum StaleLinkPolicy {
+    /// Only report them
+    #[default]
+    Report,
+    /// Remove them
+    Remove,
+}
+
+/// Link of an install directory to a blob missing in the storage
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLStaleLink {
+    pub path: PathBuf,
+    /// Hash of the missing blob
+    pub hash: String,
+    /// The link was removed
+    pub removed: bool,
+}
+
+/// Links found by [cleanup_links]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLStaleLinks {
+    /// Links to missing blobs
+    pub stale: Vec<DLStaleLink>,
+    /// Links to blobs still in the storage
+    pub valid: usize,
+}
+
+impl DLStaleLinks {
+    /// Plan downloading again the files of the manifest linked by the stale links, matched
+    /// by destination. Their links are removed so the blobs are linked again
+    pub fn redownload(&self, manifest: &DownloadPlan) -> io::Result<DownloadPlan> {
+        let stale: HashSet<PathBuf> = self
+            .stale
+            .iter()
+            .map(|link| normalize(&link.path))
+            .collect::<io::Result<_>>()?;
+        let mut plan = manifest.clone();
+        plan.files.clear();
+        for file in &manifest.files {
+            let path = normalize(&validation::target(&file.path))?;
+            if !stale.contains(&path) {
+                continue;
+            }
+            // the link may be removed already, or replaced since the scan
+            if fs::symlink_metadata(&path).is_ok_and(|m| m.is_symlink()) {
+                storage::remove_file(&path)?;
+            }
+            plan.files.push(file.clone());
+        }
+        Ok(plan)
+    }
+}
+
+/// Scans the install directory for symbolic links into the storage whose blob doesn't
+/// exist anymore, e.g. after cleaning the storage, and applies the policy to them. The
+/// linked directories aren't followed
+pub fn cleanup_links(
+    dir: impl AsRef<Path>,
+    storage: &DLStorage,
+    policy: StaleLinkPolicy,
+) -> io::Result<DLStaleLinks> {
+    let root = normalize(&storage.path)?;
+    let mut links = DLStaleLinks::default();
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+                continue;
+            }
+            if !file_type.is_symlink() {
+                continue;
+            }
+            let path = entry.path();
+            // the relative targets start at the directory of the link
+            let target = normalize(&dir.join(fs::read_link(&path)?))?;
+            if !target.starts_with(&root) {
+                continue;
+            }
+            if target.exists() {
+                links.valid += 1;
+                continue;
+            }
+            let removed = policy == StaleLinkPolicy::Remove;
+            if removed {
+                storage::remove_file(&path)?;
+            }
+            trace_event!(DEBUG, link = %path.display(), removed, "stale CAS link");
+            links.stale.push(DLStaleLink {
+                path,
+                hash: target
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+                removed,
+            });
+        }
+    }
+    Ok(links)
+}
+
+/// Absolute path without `.` and `..`, resolved without following the links
+fn normalize(path: &Path) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in std::path::absolute(path)?.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    Ok(normalized)
+}
@@ -0,0 +1,84 @@
+use crate::{
+    DLFile,
+    indicator::{IndicateSignal, Indicator},
+};
+
+/// Point of the progress of a file where its checkpoint callback is fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DLCheckpoint {
+    /// Bytes of the file downloaded
+    Bytes(u64),
+    /// Percent of the file downloaded, ignored if the size of the file is unknown and the
+    /// server doesn't send it
+    Percent(u8),
+}
+
+impl DLCheckpoint {
+    /// Position of the checkpoint in a file of `size` bytes
+    fn position(&self, size: u64) -> Option<u64> {
+        match self {
+            DLCheckpoint::Bytes(bytes) => Some(*bytes),
+            DLCheckpoint::Percent(_) if size == 0 => None,
+            DLCheckpoint::Percent(percent) => Some(size * u64::from(*percent).min(100) / 100),
+        }
+    }
+}
+
+/// Indicator firing the checkpoints of the file when its progress crosses them
+pub(crate) struct Checkpoints<'a, I> {
+    indicator: I,
+    file: &'a DLFile,
+    /// Checkpoints not crossed yet, sorted by position
+    pending: Vec<(u64, DLCheckpoint)>,
+    /// The size of the file was known or found in a response
+    sized: bool,
+}
+
+impl<'a, I: Indicator> Checkpoints<'a, I> {
+    pub fn new(indicator: I, file: &'a DLFile) -> Self {
+        let mut pending: Vec<_> = file
+            .checkpoints
+            .iter()
+            .filter_map(|checkpoint| Some((checkpoint.position(file.size)?, *checkpoint)))
+            .collect();
+        // the last ones are popped first
+        pending.sort_by_key(|(position, _)| std::cmp::Reverse(*position));
+        Self {
+            indicator,
+            file,
+            pending,
+            sized: file.size > 0,
+        }
+    }
+}
+
+impl<I: Indicator> Indicator for Checkpoints<'_, I> {
+    fn effect(&mut self, position: u64) {
+        self.indicator.effect(position);
+        while let Some(&(at, checkpoint)) = self.pending.last() {
+            if at > position {
+                break;
+            }
+            self.pending.pop();
+            (self.file.on_checkpoint)(self.file.path.clone(), checkpoint);
+        }
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        // the percents can be placed once the size is known
+        if let (IndicateSignal::Size(size), false) = (&signal, self.sized) {
+            self.sized = true;
+            self.pending.extend(
+                self.file
+                    .checkpoints
+                    .iter()
+                    .filter(|checkpoint| matches!(checkpoint, DLCheckpoint::Percent(_)))
+                    .filter_map(|checkpoint| Some((checkpoint.position(*size)?, *checkpoint))),
+            );
+            self.pending
+                .sort_by_key(|(position, _)| std::cmp::Reverse(*position));
+        }
+        self.indicator.signal(signal);
+    }
+}
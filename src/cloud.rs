@@ -0,0 +1,113 @@
+use std::{error::Error, fmt};
+
+use surf::{StatusCode, Url};
+
+/// The URL of a cloud storage doesn't name an object
+#[derive(Debug)]
+pub(crate) struct InvalidObject {
+    pub url: String,
+    pub message: &'static str,
+}
+
+impl fmt::Display for InvalidObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid object URL {}, {}", self.url, self.message)
+    }
+}
+
+impl Error for InvalidObject {}
+
+/// Error of the request of the URL, which doesn't name an object
+pub(crate) fn invalid_object(url: &Url, message: &'static str) -> surf::Error {
+    let url = url.to_string();
+    surf::Error::new(StatusCode::BadRequest, InvalidObject { url, message })
+}
+
+/// The credentials of a cloud storage can't be loaded or exchanged for a token
+#[cfg(any(feature = "gcs", feature = "azure"))]
+#[derive(Debug)]
+pub(crate) struct CredentialsError(pub std::io::Error);
+
+#[cfg(any(feature = "gcs", feature = "azure"))]
+impl fmt::Display for CredentialsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(any(feature = "gcs", feature = "azure"))]
+impl Error for CredentialsError {}
+
+/// Error of the request authenticated with the credentials
+#[cfg(any(feature = "gcs", feature = "azure"))]
+pub(crate) fn credentials_error(message: impl Into<String>) -> surf::Error {
+    let error = std::io::Error::new(std::io::ErrorKind::PermissionDenied, message.into());
+    surf::Error::new(StatusCode::Unauthorized, CredentialsError(error))
+}
+
+/// Value of the environment variable, the empty ones are unset
+pub(crate) fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// HMAC-SHA256 of the data, the signatures of S3 and Azure
+#[cfg(any(feature = "s3", feature = "azure"))]
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    use sha2::{Digest, Sha256};
+
+    let mut block = [0u8; 64];
+    match key.len() > block.len() {
+        true => block[..32].copy_from_slice(&Sha256::digest(key)),
+        false => block[..key.len()].copy_from_slice(key),
+    }
+    let pad = |byte: u8| block.map(|b| b ^ byte);
+    let inner = Sha256::new()
+        .chain_update(pad(0x36))
+        .chain_update(data)
+        .finalize();
+    Sha256::new()
+        .chain_update(pad(0x5c))
+        .chain_update(inner)
+        .finalize()
+        .into()
+}
+
+#[cfg(all(test, any(feature = "s3", feature = "azure")))]
+mod tests {
+    use super::hmac_sha256;
+
+    #[test]
+    fn hmac_sha256_rfc4231() {
+        // test cases 1 to 4 and 6 of RFC 4231, the 6th with a key longer than the block
+        let cases: [(Vec<u8>, &[u8], &str); 5] = [
+            (
+                vec![0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                vec![0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                (1..=25).collect(),
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                vec![0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+        ];
+        for (key, data, mac) in cases {
+            assert_eq!(hex::encode(hmac_sha256(&key, data)), mac);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use surf::RequestBuilder;
+
+use crate::url_cache::CachePolicy;
+
+/// Validators of the response that wrote a destination, stored next to it in
+/// `<path>.validators` to ask the server if the destination changed
+#[derive(Debug, Default)]
+pub(crate) struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Validators of the response
+    pub fn from_policy(policy: &CachePolicy) -> Self {
+        Self {
+            etag: policy.etag.clone(),
+            last_modified: policy.last_modified.clone(),
+        }
+    }
+    /// Validators of the existing destination, if they were stored
+    pub fn load(destination: &Path) -> Option<Self> {
+        if !destination.is_file() {
+            return None;
+        }
+        let stored = fs::read_to_string(Self::path(destination)).ok()?;
+        let mut validators = Self::default();
+        for line in stored.lines() {
+            match line.split_once(' ') {
+                Some(("etag", etag)) => validators.etag = Some(etag.to_string()),
+                Some(("last-modified", date)) => validators.last_modified = Some(date.to_string()),
+                _ => {}
+            }
+        }
+        (validators.etag.is_some() || validators.last_modified.is_some()).then_some(validators)
+    }
+    /// Stores the validators of the destination, the stale ones are removed if the response
+    /// doesn't have any
+    pub fn store(&self, destination: &Path) -> io::Result<()> {
+        let path = Self::path(destination);
+        if self.etag.is_none() && self.last_modified.is_none() {
+            return match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            };
+        }
+        let mut stored = Vec::new();
+        if let Some(etag) = &self.etag {
+            writeln!(stored, "etag {}", etag)?;
+        }
+        if let Some(last_modified) = &self.last_modified {
+            writeln!(stored, "last-modified {}", last_modified)?;
+        }
+        fs::write(path, stored)
+    }
+    /// Makes the request conditional, the server answers `304 Not Modified` if the
+    /// destination didn't change
+    pub fn apply(&self, mut request: RequestBuilder) -> RequestBuilder {
+        if let Some(etag) = &self.etag {
+            request = request.header("If-None-Match", etag.as_str());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header("If-Modified-Since", last_modified.as_str());
+        }
+        request
+    }
+    fn path(destination: &Path) -> PathBuf {
+        let mut path = destination.as_os_str().to_owned();
+        path.push(".validators");
+        PathBuf::from(path)
+    }
+}
@@ -0,0 +1,58 @@
+use std::io;
+
+use crate::DwldError;
+
+/// Checks if the URL is a `data:` URL, carrying the file in the URL itself
+pub(crate) fn is_data_url(url: &str) -> bool {
+    url.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+}
+
+/// Data of a `data:` URL, `data:[<media type>][;base64],<data>`. The data without
+/// `;base64` is percent-decoded
+pub(crate) fn decode(url: &str) -> Result<Vec<u8>, DwldError> {
+    let Some((header, data)) = url.get(5..).and_then(|url| url.split_once(',')) else {
+        return Err(invalid("", "missing the comma before the data"));
+    };
+    let base64 = header
+        .rsplit(';')
+        .next()
+        .is_some_and(|param| param.trim().eq_ignore_ascii_case("base64"));
+    let data = percent_decode(data).ok_or_else(|| invalid(header, "invalid percent escape"))?;
+    match base64 {
+        true => {
+            // the whitespace of wrapped payloads isn't part of the data
+            let data: Vec<u8> = data
+                .into_iter()
+                .filter(|byte| !byte.is_ascii_whitespace())
+                .collect();
+            base64::decode(data).map_err(|e| invalid(header, &e.to_string()))
+        }
+        false => Ok(data),
+    }
+}
+
+/// Decodes the `%XX` escapes
+pub(crate) fn percent_decode(data: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut bytes = data.bytes();
+    while let Some(byte) = bytes.next() {
+        match byte {
+            b'%' => {
+                let hex = [bytes.next()?, bytes.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            byte => decoded.push(byte),
+        }
+    }
+    Some(decoded)
+}
+
+/// Error of a malformed URL, identified by its header so the payload isn't repeated
+fn invalid(header: &str, message: &str) -> DwldError {
+    DwldError::Source {
+        path: format!("data:{}", header),
+        source: io::Error::new(io::ErrorKind::InvalidData, message.to_string()),
+    }
+}
@@ -1,9 +1,14 @@
+use std::{fs, path::Path};
+
+use crate::error::DwldError;
+
 /// Decompressor trait for decompressing files.
 pub trait Decompressor {
-    fn decompress(file: &str, path: &str) -> Result<(), String>;
+    fn decompress(file: &str, path: &str) -> Result<(), DwldError>;
 }
 
 /// Methods for decompressing files.
+#[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 pub enum DecompressionMethod {
@@ -15,34 +20,43 @@ pub enum DecompressionMethod {
 
 impl DecompressionMethod {
     /// Decompresses a file using the specified method.
-    pub fn decompress(&self, file: &str, path: &str) -> Result<(), String> {
+    pub fn decompress(&self, file: &str, path: &str) -> Result<(), DwldError> {
         match self {
             #[cfg(feature = "tar")]
             DecompressionMethod::TarGzip => gzip::TarGzipDecompressor::decompress(file, path),
             #[cfg(feature = "zip")]
             DecompressionMethod::Zip => zip::ZipDecompressor::decompress(file, path),
-            _ => return Ok(()),
+            #[allow(unreachable_patterns)]
+            _ => Ok(()),
         }
     }
 }
 
+/// Maps the errors of the decompressors, keeping the failed step
+#[cfg(any(feature = "tar", feature = "zip"))]
+fn failed<E: std::fmt::Display>(step: &'static str) -> impl Fn(E) -> DwldError {
+    move |e| DwldError::Decompression(format!("{}: {}", step, e))
+}
+
 /// Decompress tar.gz files.
 #[cfg(feature = "tar")]
 mod gzip {
     use std::fs::File;
 
-    use super::Decompressor;
+    use super::{Decompressor, DwldError, failed};
     use flate2::read::GzDecoder;
     use tar::Archive;
     pub struct TarGzipDecompressor;
 
     /// Decompressor for tar.gz file.
     impl Decompressor for TarGzipDecompressor {
-        fn decompress(file: &str, path: &str) -> Result<(), String> {
-            let tar_gz = File::open(file).expect("Failed to open archive");
+        fn decompress(file: &str, path: &str) -> Result<(), DwldError> {
+            let tar_gz = File::open(file).map_err(failed("Failed to open archive"))?;
             let tar = GzDecoder::new(tar_gz);
             let mut archive = Archive::new(tar);
-            archive.unpack(path).expect("Failed to extract archive");
+            archive
+                .unpack(path)
+                .map_err(failed("Failed to extract archive"))?;
             Ok(())
         }
     }
@@ -51,38 +65,40 @@ mod gzip {
 /// Decompressor for zip file.
 #[cfg(feature = "zip")]
 mod zip {
-    use std::fs::{File, create_dir_all};
+    use std::fs::File;
     use std::io;
     use std::path::Path;
     use zip::ZipArchive;
 
-    use super::Decompressor;
+    use super::{Decompressor, DwldError, failed};
+    use crate::storage::create_dir_all;
 
     pub struct ZipDecompressor;
 
     /// Decompressor for zip file.
     impl Decompressor for ZipDecompressor {
-        fn decompress(file: &str, path: &str) -> Result<(), String> {
-            let file = File::open(file).expect("Failed to open archive");
-            let mut archive = ZipArchive::new(file).expect("Failed to open archive");
+        fn decompress(file: &str, path: &str) -> Result<(), DwldError> {
+            let file = File::open(file).map_err(failed("Failed to open archive"))?;
+            let mut archive = ZipArchive::new(file).map_err(failed("Failed to open archive"))?;
 
             // Asegurarse que el directorio de destino existe
-            create_dir_all(path).expect("Failed to create directory");
+            create_dir_all(Path::new(path)).map_err(failed("Failed to create directory"))?;
 
             for i in 0..archive.len() {
-                let mut file = archive.by_index(i).expect("Failed to extract file");
+                let mut file = archive
+                    .by_index(i)
+                    .map_err(failed("Failed to extract file"))?;
                 let outpath = Path::new(path).join(file.mangled_name());
 
                 if file.name().ends_with('/') {
-                    create_dir_all(&outpath).expect("Failed to create directory");
+                    create_dir_all(&outpath).map_err(failed("Failed to create directory"))?;
                 } else {
                     if let Some(p) = outpath.parent() {
-                        if !p.exists() {
-                            create_dir_all(p).expect("Failed to create directory");
-                        }
+                        create_dir_all(p).map_err(failed("Failed to create directory"))?;
                     }
-                    let mut outfile = File::create(&outpath).expect("Failed to create file");
-                    io::copy(&mut file, &mut outfile).expect("Failed to copy file");
+                    let mut outfile =
+                        File::create(&outpath).map_err(failed("Failed to create file"))?;
+                    io::copy(&mut file, &mut outfile).map_err(failed("Failed to copy file"))?;
                 }
             }
 
@@ -91,6 +107,7 @@ mod zip {
     }
 }
 /// Decompressor Configuration
+#[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub struct DLDecompressionConfig {
@@ -131,8 +148,27 @@ impl DLDecompressionConfig {
         self
     }
     /// Decompress a file
-    pub fn decompress(&self, file: &str) -> Result<(), String> {
+    pub fn decompress(&self, file: &str) -> Result<(), DwldError> {
         self.method.decompress(file, &self.output)?;
         Ok(())
     }
+    /// Extracts the file in the staging directory and moves the entries to the output
+    /// once the whole archive is extracted
+    pub(crate) fn decompress_staged(&self, file: &str, staging: &Path) -> Result<(), DwldError> {
+        let failed = |e| DwldError::io(staging.to_string_lossy(), e);
+        // leftovers of a failed extraction
+        if staging.exists() {
+            fs::remove_dir_all(staging).map_err(failed)?;
+        }
+        crate::storage::create_dir_all(staging).map_err(failed)?;
+        let extracted = self
+            .method
+            .decompress(file, &staging.to_string_lossy())
+            .and_then(|_| {
+                crate::storage::move_tree(staging, Path::new(&self.output))
+                    .map_err(|e| DwldError::io(&self.output, e))
+            });
+        let _ = fs::remove_dir_all(staging);
+        extracted
+    }
 }
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use futures::StreamExt;
+use surf::Url;
+
+use crate::DLFile;
+
+/// Host names of the URLs and the mirrors of the files, in the order the files start. The
+/// hosts of the first `started` files are left out, their connections resolve them
+fn upcoming_hosts(files: &[&DLFile], started: usize) -> Vec<(String, u16)> {
+    let urls = |file: &DLFile| {
+        let parts = file.parts.iter().map(|part| part.url.clone());
+        std::iter::once(file.url.clone())
+            .chain(file.mirrors.iter().cloned())
+            .chain(parts)
+            .collect::<Vec<_>>()
+    };
+    let host = |url: String| {
+        let url = Url::parse(&url).ok()?;
+        // the IP addresses and the local schemes aren't resolved
+        let Some(surf::http::url::Host::Domain(host)) = url.host() else {
+            return None;
+        };
+        let port = match url.scheme() {
+            "sftp" => url.port().unwrap_or(22),
+            _ => url.port_or_known_default()?,
+        };
+        Some((host.to_ascii_lowercase(), port))
+    };
+    let mut seen: HashSet<(String, u16)> = files[..started.min(files.len())]
+        .iter()
+        .flat_map(|file| urls(file))
+        .filter_map(host)
+        .collect();
+    files
+        .iter()
+        .skip(started)
+        .flat_map(|file| urls(file))
+        .filter_map(host)
+        .filter(|host| seen.insert(host.clone()))
+        .collect()
+}
+
+/// Resolves the hosts of the upcoming files, up to `lookups` at once, while the first
+/// `started` files download. The answers are kept by the cache of the system resolver, so
+/// the files starting later don't wait for the DNS; the failures are ignored, the downloads
+/// report them. The hosts are taken at once, the future doesn't borrow the files
+pub(crate) fn prefetch(
+    files: &[&DLFile],
+    started: usize,
+    lookups: usize,
+) -> impl Future<Output = ()> + use<> {
+    let hosts = upcoming_hosts(files, started);
+    async move {
+        if hosts.is_empty() {
+            return;
+        }
+        trace_event!(DEBUG, hosts = hosts.len(), "prefetching DNS");
+        futures::stream::iter(hosts)
+            .for_each_concurrent(lookups.max(1), |(host, port)| async move {
+                let _resolved = smol::net::resolve((host.as_str(), port)).await;
+                trace_event!(TRACE, host = %host, ok = _resolved.is_ok(), "DNS prefetched");
+            })
+            .await;
+    }
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeMap;
+
+use surf::Client;
+
+use crate::{
+    DLFile, DLTimeouts, DwldError, MeteredPolicy, OverwritePolicy, data_url, file_url,
+    indicator::TaskState,
+    mirror, storage,
+    timeout::{self, DLTimeoutKind},
+};
+
+/// What downloading a file would do, found by a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DLDryRunAction {
+    /// Download the bytes from the network, after the partial data already written. The
+    /// bytes are unknown if neither the file nor the server tell its size
+    Download { bytes: Option<u64>, offset: u64 },
+    /// Take the file from the CAS storage or the URL cache
+    Cached,
+    /// Don't download the file, with the reason
+    Skip(String),
+    /// The download would fail, with the code of the error and its message
+    Fail { code: String, error: String },
+}
+
+impl DLDryRunAction {
+    fn failed(e: &DwldError) -> Self {
+        DLDryRunAction::Fail {
+            code: e.code().to_string(),
+            error: e.to_string(),
+        }
+    }
+}
+
+/// Action of a file found by a dry run
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLDryRunFile {
+    pub path: String,
+    pub url: String,
+    /// Metadata of the file, see [DLFile::with_metadata]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+    pub action: DLDryRunAction,
+}
+
+impl DLDryRunFile {
+    pub(crate) fn new(file: &DLFile, action: DLDryRunAction) -> Self {
+        Self {
+            path: file.path.clone(),
+            url: file.url.clone(),
+            metadata: file.metadata.clone(),
+            action,
+        }
+    }
+    /// Action of a file that can't be checked, e.g. the resolver failed
+    pub(crate) fn failed(file: &DLFile, e: &DwldError) -> Self {
+        Self::new(file, DLDryRunAction::failed(e))
+    }
+}
+
+/// What [crate::Downloader::start] would do, in the order the files would start
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DryRunReport {
+    pub files: Vec<DLDryRunFile>,
+}
+
+impl DryRunReport {
+    /// Bytes that would be downloaded, the files of unknown size aren't counted
+    pub fn bytes(&self) -> u64 {
+        self.files
+            .iter()
+            .map(|file| match file.action {
+                DLDryRunAction::Download { bytes, .. } => bytes.unwrap_or(0),
+                _ => 0,
+            })
+            .sum()
+    }
+    /// Files that would be downloaded from the network
+    pub fn downloads(&self) -> impl Iterator<Item = &DLDryRunFile> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.action, DLDryRunAction::Download { .. }))
+    }
+    /// Files that would fail
+    pub fn failures(&self) -> impl Iterator<Item = &DLDryRunFile> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.action, DLDryRunAction::Fail { .. }))
+    }
+}
+
+/// Settings of the session checked by the dry run
+pub(crate) struct DryRun<'a> {
+    pub client: &'a Client,
+    pub timeouts: DLTimeouts,
+    pub overwrite_policy: OverwritePolicy,
+    pub metered_policy: Option<&'a MeteredPolicy>,
+    #[cfg(feature = "ftp")]
+    pub ftps: &'a async_tls::TlsConnector,
+}
+
+impl DryRun<'_> {
+    /// Finds what the download of the file would do, in the order of the download
+    pub async fn file(&self, file: &DLFile) -> DLDryRunFile {
+        let action = match self.action(file).await {
+            Ok(action) => action,
+            Err(e) => DLDryRunAction::failed(&e),
+        };
+        DLDryRunFile::new(file, action)
+    }
+    async fn action(&self, file: &DLFile) -> Result<DLDryRunAction, DwldError> {
+        if let Some(reason) = file.valid_destination().await {
+            return Ok(DLDryRunAction::Skip(reason.to_string()));
+        }
+        // files in the CAS storage or fresh in the URL cache
+        if let TaskState::Cached = file.initial_state() {
+            return Ok(DLDryRunAction::Cached);
+        }
+        let offset = file.resume_offset();
+        if offset.is_none() && file.backend.exists(&file.path).await {
+            match file.overwrite_policy.unwrap_or(self.overwrite_policy) {
+                OverwritePolicy::Skip => {
+                    return Ok(DLDryRunAction::Skip(
+                        "Destination already exists".to_string(),
+                    ));
+                }
+                OverwritePolicy::Error => {
+                    let path = file.path.as_str().into();
+                    return Err(storage::DLDestinationError::Exists(path).into());
+                }
+                OverwritePolicy::Overwrite | OverwritePolicy::RenameWithSuffix => {}
+            }
+        }
+
+        let timeouts = file.timeouts.or(self.timeouts);
+        let size = match file.parts.is_empty() {
+            true => self.mirrored_length(file, timeouts).await?,
+            false => {
+                let mut size = Some(0);
+                for part in &file.parts {
+                    let len = self.length(file, &part.url, timeouts).await?;
+                    size = size.zip(len).map(|(size, len)| size + len);
+                }
+                size
+            }
+        };
+        let size = size.or((file.size > 0).then_some(file.size));
+        let offset = offset.unwrap_or(0);
+        let bytes = size.map(|size| size.saturating_sub(offset));
+        if let Some(policy) = self.metered_policy {
+            let metered = bytes.unwrap_or(file.size);
+            if !policy(file, metered) {
+                let reason = format!("Deferred, {} bytes not allowed now", metered);
+                return Ok(DLDryRunAction::Skip(reason));
+            }
+        }
+        Ok(DLDryRunAction::Download { bytes, offset })
+    }
+    /// Length of the file, from the mirrors while the servers fail
+    async fn mirrored_length(
+        &self,
+        file: &DLFile,
+        timeouts: DLTimeouts,
+    ) -> Result<Option<u64>, DwldError> {
+        let mut result = self.length(file, &file.url, timeouts).await;
+        for url in &file.mirrors {
+            match &result {
+                Err(e) if mirror::is_mirror_error(e) => {}
+                _ => break,
+            }
+            result = self.length(file, url, timeouts).await;
+        }
+        result
+    }
+    /// Length of the body of a URL of the file, asked with a `HEAD` request, `SIZE` to the
+    /// FTP servers or the attributes of the SFTP file
+    async fn length(
+        &self,
+        file: &DLFile,
+        url: &str,
+        timeouts: DLTimeouts,
+    ) -> Result<Option<u64>, DwldError> {
+        if data_url::is_data_url(url) {
+            return data_url::decode(url).map(|data| Some(data.len() as u64));
+        }
+        if file_url::is_file_url(url) {
+            return file_url::length(&file_url::path(url)?).map(Some);
+        }
+        #[cfg(feature = "ftp")]
+        if crate::ftp::is_ftp_url(url) {
+            let length = crate::ftp::length(url, self.ftps);
+            return timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, length).await?;
+        }
+        #[cfg(feature = "sftp")]
+        if crate::sftp::is_sftp_url(url) {
+            let config = file.sftp.clone().unwrap_or_default();
+            let length = crate::sftp::length(url, config, timeouts);
+            return timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, length).await?;
+        }
+        let request = crate::add_headers(self.client.head(url), &file.headers);
+        let response =
+            timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request).await??;
+        // the responses to HEAD don't have a body to discard
+        if !response.status().is_success() {
+            return Err(DwldError::Status {
+                status: response.status(),
+                body: Vec::new(),
+                retry_after: None,
+            });
+        }
+        Ok(response.len().map(|len| len as u64))
+    }
+}
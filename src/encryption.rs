@@ -0,0 +1,187 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use aes_gcm::{
+    Aes256Gcm, Key, KeyInit, Nonce,
+    aead::{Aead, OsRng, Payload, rand_core::RngCore},
+};
+
+/// Magic bytes at the start of every encrypted file
+const MAGIC: &[u8; 8] = b"DWLDENC1";
+/// Size of the plain chunks, DEFAULT = 64KB
+const CHUNK_SIZE: usize = 64 * 1024;
+/// Size of the authentication tag of every chunk
+const TAG_SIZE: usize = 16;
+/// Size of the random prefix of the nonces
+const PREFIX_SIZE: usize = 8;
+
+/// AES-256-GCM key used to encrypt the files
+#[derive(Clone)]
+pub struct DLEncryptionKey([u8; 32]);
+
+impl DLEncryptionKey {
+    /// Creates a key from raw bytes
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+    /// Creates a key from a 64 characters hexadecimal string
+    pub fn from_hex(key: &str) -> Result<Self, String> {
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(key, &mut bytes).map_err(|e| e.to_string())?;
+        Ok(Self(bytes))
+    }
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.0))
+    }
+    /// Encrypts the file `input` writing the result in `output`
+    pub fn encrypt_file(&self, input: &Path, output: &Path) -> io::Result<()> {
+        let cipher = self.cipher();
+        let mut prefix = [0; PREFIX_SIZE];
+        OsRng.fill_bytes(&mut prefix);
+
+        let mut input = File::open(input)?;
+        let mut output = File::create(output)?;
+        output.write_all(MAGIC)?;
+        output.write_all(&prefix)?;
+
+        let mut buffer = vec![0; CHUNK_SIZE];
+        let mut next = read_chunk(&mut input, &mut buffer)?;
+        let mut counter: u32 = 0;
+        loop {
+            let chunk = buffer[..next].to_vec();
+            next = read_chunk(&mut input, &mut buffer)?;
+            // the last chunk is marked, so truncated files are detected
+            let last = next == 0;
+            let sealed = cipher
+                .encrypt(
+                    &nonce(&prefix, counter),
+                    Payload {
+                        msg: &chunk,
+                        aad: &[last as u8],
+                    },
+                )
+                .map_err(|_| io::Error::other("Failed to encrypt file"))?;
+            output.write_all(&sealed)?;
+            if last {
+                break;
+            }
+            counter = counter
+                .checked_add(1)
+                .ok_or_else(|| io::Error::other("File too big to be encrypted"))?;
+        }
+        output.flush()
+    }
+    /// Decrypts the file `input` writing the result in `output`
+    pub fn decrypt_file(&self, input: &Path, output: &Path) -> io::Result<()> {
+        let cipher = self.cipher();
+        let mut input = File::open(input)?;
+
+        let mut magic = [0; MAGIC.len()];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "File is not encrypted by dwldutil",
+            ));
+        }
+        let mut prefix = [0; PREFIX_SIZE];
+        input.read_exact(&mut prefix)?;
+
+        let mut output = File::create(output)?;
+        let mut buffer = vec![0; CHUNK_SIZE + TAG_SIZE];
+        let mut next = read_chunk(&mut input, &mut buffer)?;
+        let mut counter: u32 = 0;
+        loop {
+            let chunk = buffer[..next].to_vec();
+            next = read_chunk(&mut input, &mut buffer)?;
+            let last = next == 0;
+            let plain = cipher
+                .decrypt(
+                    &nonce(&prefix, counter),
+                    Payload {
+                        msg: &chunk,
+                        aad: &[last as u8],
+                    },
+                )
+                .map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt file")
+                })?;
+            output.write_all(&plain)?;
+            if last {
+                break;
+            }
+            // the encrypted files never have more chunks than the nonces
+            counter = counter.checked_add(1).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Encrypted file too big")
+            })?;
+        }
+        output.flush()
+    }
+}
+
+impl fmt::Debug for DLEncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // never print the key
+        f.write_str("DLEncryptionKey(***)")
+    }
+}
+
+/// Nonce of a chunk, random prefix followed by the chunk counter
+fn nonce(
+    prefix: &[u8; PREFIX_SIZE],
+    counter: u32,
+) -> Nonce<<Aes256Gcm as aes_gcm::AeadCore>::NonceSize> {
+    let mut nonce = [0; 12];
+    nonce[..PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[PREFIX_SIZE..].copy_from_slice(&counter.to_be_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+/// Fills the buffer as much as possible, returns the bytes read
+fn read_chunk(input: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match input.read(&mut buffer[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Encryption configuration of a downloaded file
+#[derive(Debug, Clone)]
+pub struct DLEncryptionConfig {
+    /// Key used to encrypt the file
+    pub key: DLEncryptionKey,
+    /// Keep the plain file after encryption
+    pub keep_plain: bool,
+}
+
+impl DLEncryptionConfig {
+    /// Create a new encryption configuration
+    pub fn new(key: DLEncryptionKey) -> Self {
+        Self {
+            key,
+            keep_plain: false,
+        }
+    }
+    /// Set whether to keep the plain file after encryption
+    pub fn with_keep_plain(mut self, keep_plain: bool) -> Self {
+        self.keep_plain = keep_plain;
+        self
+    }
+    /// Encrypts the file into `<file>.enc`, returns the path of the encrypted file
+    pub fn encrypt(&self, file: &Path) -> io::Result<String> {
+        let output = format!("{}.enc", file.to_string_lossy());
+        self.key.encrypt_file(file, Path::new(&output))?;
+        if !self.keep_plain {
+            crate::storage::remove_file(file)?;
+        }
+        Ok(output)
+    }
+}
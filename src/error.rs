@@ -0,0 +1,308 @@
+use std::{error::Error, fmt, io, time::Duration};
+
+use surf::StatusCode;
+
+use crate::{
+    handle::CancelReason,
+    redirection_middleware::{InvalidLocation, RedirectLimit, RedirectRefused},
+    report::DownloadReport,
+    storage::DLDestinationError,
+    timeout::DLTimeoutKind,
+};
+
+/// Error of a download
+#[derive(Debug)]
+pub enum DwldError {
+    /// The request failed before getting a response
+    Network(surf::Error),
+    /// The download took too long
+    Timeout(DLTimeoutKind),
+    /// The server answered with an error status, with the first bytes of its body, see
+    /// [DwldError::body], and the wait asked by the `Retry-After` of the 429 and 503
+    /// responses
+    Status {
+        status: StatusCode,
+        body: Vec<u8>,
+        retry_after: Option<Duration>,
+    },
+    /// Reading or writing a file failed
+    Io { path: String, source: io::Error },
+    /// The FTP server answered with an error, with its reply code and text
+    Ftp { code: u16, message: String },
+    /// The SFTP session failed, e.g. the authentication or the key of the server
+    Sftp(io::Error),
+    /// The credentials of a cloud storage can't be loaded or exchanged for a token
+    Credentials(io::Error),
+    /// The local file of a `file://` URL can't be read, or the `data:`, `ftp://`,
+    /// `sftp://`, `s3://`, `gs://` or `az://` URL is malformed
+    Source { path: String, source: io::Error },
+    /// The destination of the file can't be written
+    Destination(DLDestinationError),
+    /// The downloaded file doesn't match its hashes, with its first bytes
+    HashMismatch { path: String, body: Vec<u8> },
+    /// The body ended before the `Content-Length` of the response
+    Truncated {
+        path: String,
+        expected: u64,
+        received: u64,
+    },
+    /// The redirections exceeded the limit, with the URLs visited and the status of the
+    /// last response
+    RedirectLimit {
+        chain: Vec<String>,
+        status: StatusCode,
+    },
+    /// The redirect policy refused the redirection to the URL, see
+    /// [crate::Downloader::with_redirect_policy]
+    RedirectRefused { url: String },
+    /// The `Location` of a redirection isn't a valid URL
+    InvalidRedirect { location: String },
+    /// The server sent another range than the requested one, with its `Content-Range`
+    ContentRange {
+        expected: String,
+        received: Option<String>,
+    },
+    /// The transformer of the body failed
+    Transform(io::Error),
+    /// The decompression of the file failed
+    Decompression(String),
+    /// The CAS storage failed
+    Cas(io::Error),
+    /// The download failed and the fallback too, with both errors
+    Fallback(Box<DwldError>, Box<DwldError>),
+    /// A companion of the file failed, with its path
+    Companion {
+        path: String,
+        source: Box<DwldError>,
+    },
+    /// The resolver couldn't find the metadata of the file
+    Resolver(io::Error),
+    /// The download was cancelled with [crate::DLHandle::cancel], with the reason
+    Cancelled(CancelReason),
+    /// The files can't be downloaded together
+    Validation(String),
+    /// The [crate::service::DownloadService] stopped before the batch finished, e.g. its
+    /// thread died after a panic
+    ServiceStopped,
+    /// Some files of the batch failed, with their paths and the report of every file
+    Batch {
+        report: DownloadReport,
+        errors: Vec<(String, DwldError)>,
+    },
+}
+
+impl DwldError {
+    /// Creates an IO error of the file in path
+    pub fn io(path: impl Into<String>, source: io::Error) -> Self {
+        Self::Io {
+            path: path.into(),
+            source,
+        }
+    }
+    /// Stable code of the error, to branch on it or localize it without parsing the message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Network(_) => "E_NETWORK",
+            Self::Timeout(_) => "E_TIMEOUT",
+            Self::Status { .. } => "E_STATUS",
+            Self::Io { .. } => "E_IO",
+            Self::Ftp { .. } => "E_FTP",
+            Self::Sftp(_) => "E_SFTP",
+            Self::Credentials(_) => "E_CREDENTIALS",
+            Self::Source { .. } => "E_SOURCE",
+            Self::Destination(_) => "E_DESTINATION",
+            Self::HashMismatch { .. } => "E_HASH_MISMATCH",
+            Self::Truncated { .. } => "E_TRUNCATED",
+            Self::ContentRange { .. } => "E_CONTENT_RANGE",
+            Self::RedirectLimit { .. } => "E_REDIRECT_LIMIT",
+            Self::RedirectRefused { .. } => "E_REDIRECT_REFUSED",
+            Self::InvalidRedirect { .. } => "E_INVALID_REDIRECT",
+            Self::Transform(_) => "E_TRANSFORM",
+            Self::Decompression(_) => "E_DECOMPRESSION",
+            Self::Cas(_) => "E_CAS",
+            Self::Fallback(..) => "E_FALLBACK",
+            Self::Companion { .. } => "E_COMPANION",
+            Self::Resolver(_) => "E_RESOLVER",
+            Self::Cancelled(_) => "E_CANCELLED",
+            Self::Validation(_) => "E_VALIDATION",
+            Self::ServiceStopped => "E_SERVICE_STOPPED",
+            Self::Batch { .. } => "E_BATCH",
+        }
+    }
+    /// Status of the response that failed the download, if the server answered
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Status { status, .. } | Self::RedirectLimit { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+    /// Wait asked by the server before trying again, from the `Retry-After` header of the
+    /// 429 and 503 responses
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+    /// First bytes of the error page or of the file failing its hashes, often an HTML or
+    /// JSON error served instead of the file
+    pub fn body(&self) -> Option<&[u8]> {
+        match self {
+            Self::Status { body, .. } | Self::HashMismatch { body, .. } if !body.is_empty() => {
+                Some(body)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DwldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "Request failed: {}", e),
+            Self::Timeout(kind) => write!(f, "Timed out {}", kind),
+            Self::Status { status, body, .. } => {
+                write!(f, "Server answered {}", status)?;
+                write_body(f, body)
+            }
+            Self::Io { path, source } => write!(f, "IO error on {}: {}", path, source),
+            Self::Ftp { code, message } => write!(f, "FTP server answered {} {}", code, message),
+            Self::Sftp(e) => write!(f, "SFTP failed: {}", e),
+            Self::Credentials(e) => write!(f, "Credentials failed: {}", e),
+            Self::Source { path, source } => {
+                write!(f, "Source {} can't be read: {}", path, source)
+            }
+            Self::Destination(e) => e.fmt(f),
+            Self::HashMismatch { path, body } => {
+                write!(f, "Hash verification failed for {}", path)?;
+                write_body(f, body)
+            }
+            Self::Truncated {
+                path,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Transfer of {} truncated, received {} of {} bytes",
+                path, received, expected
+            ),
+            Self::RedirectLimit { chain, status } => write!(
+                f,
+                "Too many redirects, {} answered {} after {} URLs",
+                chain.last().map_or("", String::as_str),
+                status,
+                chain.len()
+            ),
+            Self::RedirectRefused { url } => write!(f, "Redirect to {} refused by the policy", url),
+            Self::InvalidRedirect { location } => {
+                write!(f, "Invalid redirect location {:?}", location)
+            }
+            Self::ContentRange { expected, received } => write!(
+                f,
+                "Server sent the range {}, expected {}",
+                received.as_deref().unwrap_or("without Content-Range"),
+                expected
+            ),
+            Self::Transform(e) => write!(f, "Transformation failed: {}", e),
+            Self::Decompression(e) => write!(f, "Decompression failed: {}", e),
+            Self::Cas(e) => write!(f, "CAS storage failed: {}", e),
+            Self::Fallback(download, fallback) => {
+                write!(f, "{}, the fallback failed too: {}", download, fallback)
+            }
+            Self::Companion { path, source } => {
+                write!(f, "Companion file {} failed: {}", path, source)
+            }
+            Self::Resolver(e) => write!(f, "Metadata resolution failed: {}", e),
+            Self::Cancelled(reason) => write!(f, "Download cancelled {}", reason),
+            Self::Validation(e) => e.fmt(f),
+            Self::ServiceStopped => write!(f, "Download service stopped before the batch finished"),
+            Self::Batch { errors, .. } => {
+                write!(f, "{} files failed", errors.len())?;
+                for (path, e) in errors {
+                    write!(f, "\n  {}: {}", path, e)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the first bytes of a body, as text if it's readable
+fn write_body(f: &mut fmt::Formatter<'_>, body: &[u8]) -> fmt::Result {
+    // the first bytes may cut the last character
+    let text = match std::str::from_utf8(body) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&body[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text {
+        _ if body.is_empty() => Ok(()),
+        Some(text) if !text.contains('\0') => write!(f, ", body \"{}\"", text.escape_debug()),
+        _ => write!(f, ", binary body"),
+    }
+}
+
+impl Error for DwldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io { source, .. } | Self::Source { source, .. } => Some(source),
+            Self::Destination(e) => Some(e),
+            Self::Cas(e) | Self::Sftp(e) | Self::Credentials(e) => Some(e),
+            Self::Resolver(e) => Some(e),
+            Self::Transform(e) => Some(e),
+            Self::Fallback(download, _) => Some(download.as_ref()),
+            Self::Companion { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<DLDestinationError> for DwldError {
+    fn from(e: DLDestinationError) -> Self {
+        Self::Destination(e)
+    }
+}
+
+impl From<surf::Error> for DwldError {
+    fn from(e: surf::Error) -> Self {
+        // the errors of the redirect middleware keep their details
+        let e = match e.downcast::<RedirectLimit>() {
+            Ok(limit) => {
+                return Self::RedirectLimit {
+                    chain: limit.chain.iter().map(|url| url.to_string()).collect(),
+                    status: limit.status,
+                };
+            }
+            Err(e) => e,
+        };
+        let e = match e.downcast::<RedirectRefused>() {
+            Ok(refused) => {
+                return Self::RedirectRefused {
+                    url: refused.url.to_string(),
+                };
+            }
+            Err(e) => e,
+        };
+        #[cfg(any(feature = "gcs", feature = "azure"))]
+        let e = match e.downcast::<crate::cloud::CredentialsError>() {
+            Ok(credentials) => return Self::Credentials(credentials.0),
+            Err(e) => e,
+        };
+        #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+        let e = match e.downcast::<crate::cloud::InvalidObject>() {
+            Ok(invalid) => {
+                return Self::Source {
+                    path: invalid.url,
+                    source: io::Error::new(io::ErrorKind::InvalidInput, invalid.message),
+                };
+            }
+            Err(e) => e,
+        };
+        match e.downcast::<InvalidLocation>() {
+            Ok(invalid) => Self::InvalidRedirect {
+                location: invalid.location,
+            },
+            Err(e) => Self::Network(e),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::BTreeMap, fmt};
+
+use crate::{
+    DLFile, DLHashType, DLTimeouts, DwldError, MeteredPolicy, OverwritePolicy, PartialPolicy,
+    storage,
+};
+
+/// Where a file would come from, predicted without any request
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DLSource {
+    /// Downloaded from its URL or its mirrors
+    Network,
+    /// Downloaded continuing the partial data, from the byte
+    Resume(u64),
+    /// Read from a `file://` or a `data:` URL
+    Local,
+    /// Taken from the CAS storage
+    Cas,
+    /// Served by the URL cache
+    UrlCache,
+    /// Not downloaded, with the reason
+    Skip(String),
+    /// The download would fail, with the code of the error and its message
+    Fail { code: String, error: String },
+}
+
+impl fmt::Display for DLSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network => write!(f, "network"),
+            Self::Resume(offset) => write!(f, "network, resuming from byte {}", offset),
+            Self::Local => write!(f, "local"),
+            Self::Cas => write!(f, "CAS storage"),
+            Self::UrlCache => write!(f, "URL cache"),
+            Self::Skip(reason) => write!(f, "skip, {}", reason),
+            Self::Fail { code, error } => write!(f, "fail, {}: {}", code, error),
+        }
+    }
+}
+
+/// Settings of a file once the ones of the downloader are applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLFileSettings {
+    pub size: u64,
+    /// Types of the hashes checked
+    pub hashes: Vec<DLHashType>,
+    pub mirrors: Vec<String>,
+    pub timeouts: DLTimeouts,
+    pub overwrite_policy: OverwritePolicy,
+    pub partial_policy: PartialPolicy,
+    /// The file or the downloader has a retry policy
+    pub retries: bool,
+    pub segments: usize,
+    pub atomic: bool,
+    pub resume: bool,
+    pub conditional: bool,
+    pub skip_if_valid: bool,
+    pub preverified: bool,
+    pub priority: u8,
+    pub group: Option<String>,
+    pub temp_dir: Option<String>,
+    pub quarantine_dir: Option<String>,
+    /// Names of the headers sent, their values may be credentials
+    pub headers: Vec<String>,
+    pub cas: bool,
+    pub url_cache: bool,
+}
+
+/// Explanation of a file, see [crate::Downloader::explain]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLExplainedFile {
+    pub path: String,
+    pub url: String,
+    /// Metadata of the file, see [DLFile::with_metadata]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+    pub source: DLSource,
+    pub settings: DLFileSettings,
+}
+
+/// What [crate::Downloader::start] would do with the files, in the order they would start
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLExplanation {
+    pub files: Vec<DLExplainedFile>,
+}
+
+impl fmt::Display for DLExplanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (position, file) in (1..).zip(&self.files) {
+            writeln!(
+                f,
+                "{}. {} <- {}: {}",
+                position, file.path, file.url, file.source
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the session explained
+pub(crate) struct Explain<'a> {
+    pub timeouts: DLTimeouts,
+    pub overwrite_policy: OverwritePolicy,
+    pub metered_policy: Option<&'a MeteredPolicy>,
+    pub retries: bool,
+}
+
+impl Explain<'_> {
+    /// Explains the file with the settings of the downloader already applied
+    pub async fn file(&self, file: &DLFile) -> DLExplainedFile {
+        let source = match self.source(file).await {
+            Ok(source) => source,
+            Err(e) => DLSource::Fail {
+                code: e.code().to_string(),
+                error: e.to_string(),
+            },
+        };
+        DLExplainedFile {
+            path: file.path.clone(),
+            url: file.url.clone(),
+            metadata: file.metadata.clone(),
+            source,
+            settings: self.settings(file),
+        }
+    }
+    /// Source of the file, checked in the order of the download
+    async fn source(&self, file: &DLFile) -> Result<DLSource, DwldError> {
+        if let Some(reason) = file.valid_destination().await {
+            return Ok(DLSource::Skip(reason.to_string()));
+        }
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some((_, hash))) = (&file.cas, file.hashes.hashes.first()) {
+            if storage.find(hash).is_some() {
+                return Ok(DLSource::Cas);
+            }
+        }
+        let cached = file
+            .url_cache
+            .as_ref()
+            .and_then(|cache| cache.lookup(&file.url));
+        if cached.is_some_and(|entry| entry.is_fresh()) {
+            return Ok(DLSource::UrlCache);
+        }
+        let offset = file.resume_offset();
+        if offset.is_none() && file.backend.exists(&file.path).await {
+            match file.overwrite_policy.unwrap_or(self.overwrite_policy) {
+                OverwritePolicy::Skip => {
+                    return Ok(DLSource::Skip("Destination already exists".to_string()));
+                }
+                OverwritePolicy::Error => {
+                    let path = file.path.as_str().into();
+                    return Err(storage::DLDestinationError::Exists(path).into());
+                }
+                OverwritePolicy::Overwrite | OverwritePolicy::RenameWithSuffix => {}
+            }
+        }
+        // the size of the file is the one declared, the server isn't asked
+        if let Some(policy) = self.metered_policy {
+            let bytes = file.size.saturating_sub(offset.unwrap_or(0));
+            if !policy(file, bytes) {
+                let reason = format!("Deferred, {} bytes not allowed now", bytes);
+                return Ok(DLSource::Skip(reason));
+            }
+        }
+        Ok(match offset {
+            _ if file.has_local_source() => DLSource::Local,
+            Some(offset) => DLSource::Resume(offset),
+            None => DLSource::Network,
+        })
+    }
+    fn settings(&self, file: &DLFile) -> DLFileSettings {
+        DLFileSettings {
+            size: file.size,
+            hashes: file.hashes.hashes.iter().map(|(t, _)| t.clone()).collect(),
+            mirrors: file.mirrors.clone(),
+            timeouts: file.timeouts.or(self.timeouts),
+            overwrite_policy: file.overwrite_policy.unwrap_or(self.overwrite_policy),
+            partial_policy: file.partial_policy,
+            retries: file.retry_policy.is_some() || self.retries,
+            segments: file.segments,
+            atomic: file.atomic,
+            resume: file.resume,
+            conditional: file.conditional,
+            skip_if_valid: file.skip_if_valid,
+            preverified: file.preverified,
+            priority: file.priority,
+            group: file.group.clone(),
+            temp_dir: file.temp_dir.clone(),
+            quarantine_dir: file.quarantine_dir.clone(),
+            headers: file.headers.iter().map(|(name, _)| name.clone()).collect(),
+            #[cfg(feature = "cas")]
+            cas: file.cas.is_some(),
+            #[cfg(not(feature = "cas"))]
+            cas: false,
+            url_cache: file.url_cache.is_some(),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    fs::{self, File},
+    io::{self, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use surf::Url;
+
+use crate::DwldError;
+
+/// Checks if the URL is a `file://` URL, read from the local filesystem
+pub(crate) fn is_file_url(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("file://"))
+}
+
+/// Local path of a `file://` URL
+pub(crate) fn path(url: &str) -> Result<PathBuf, DwldError> {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| DwldError::Source {
+            path: url.to_string(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, "not a local file path"),
+        })
+}
+
+fn source_error(path: &Path, source: io::Error) -> DwldError {
+    DwldError::Source {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+/// Size of the local file
+pub(crate) fn length(path: &Path) -> Result<u64, DwldError> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|e| source_error(path, e))
+}
+
+/// Body reading the local file from the offset, with the offset it starts at. The file is
+/// read from the beginning if it's shorter than the offset
+pub(crate) fn body(path: &Path, offset: u64) -> Result<(surf::Body, u64), DwldError> {
+    let error = |e| source_error(path, e);
+    let mut file = File::open(path).map_err(error)?;
+    let len = file.metadata().map_err(error)?.len();
+    let offset = if offset < len { offset } else { 0 };
+    file.seek(SeekFrom::Start(offset)).map_err(error)?;
+    let reader = smol::io::BufReader::new(smol::io::AssertAsync::new(file));
+    let body = surf::Body::from_reader(reader, Some((len - offset) as usize));
+    Ok((body, offset))
+}
@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+/// Units of the byte sizes, in powers of 1024
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Formats a size in bytes, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Formats a speed in bytes per second, e.g. `1.5 MiB/s`
+pub fn format_speed(bytes_per_sec: f64) -> String {
+    format!("{}/s", format_bytes(bytes_per_sec.max(0.0) as u64))
+}
+
+/// Formats a remaining time, e.g. `1h 02m`, `2m 05s` or `5s`
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
@@ -0,0 +1,274 @@
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use async_tls::TlsConnector;
+use smol::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use surf::Url;
+
+use crate::{DwldError, data_url};
+
+/// Checks if the URL is an `ftp://` or an `ftps://` URL, downloaded with FTP
+pub(crate) fn is_ftp_url(url: &str) -> bool {
+    ["ftp://", "ftps://"].iter().any(|scheme| {
+        url.get(..scheme.len())
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(scheme))
+    })
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send + Sync {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + Sync> Stream for T {}
+
+fn network(e: io::Error) -> DwldError {
+    DwldError::Network(e.into())
+}
+
+/// Server, credentials and path of an FTP URL
+struct Location {
+    host: String,
+    port: u16,
+    user: String,
+    password: String,
+    /// Path relative to the directory of the login, `ftp://host//srv/file` is absolute
+    path: String,
+    /// The connections are encrypted, implicit FTPS
+    secure: bool,
+}
+
+impl Location {
+    fn parse(url: &str) -> Result<Self, DwldError> {
+        let invalid = |message: &str| DwldError::Source {
+            path: url.to_string(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, message.to_string()),
+        };
+        let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+        let secure = parsed.scheme() == "ftps";
+        let decode = |text: &str| {
+            data_url::percent_decode(text)
+                .and_then(|text| String::from_utf8(text).ok())
+                .ok_or_else(|| invalid("invalid percent escape"))
+        };
+        let path = decode(parsed.path().strip_prefix('/').unwrap_or(parsed.path()))?;
+        if path.is_empty() || path.ends_with('/') {
+            return Err(invalid("not the path of a file"));
+        }
+        Ok(Self {
+            host: parsed
+                .host_str()
+                .ok_or_else(|| invalid("missing the host"))?
+                .to_string(),
+            port: parsed.port().unwrap_or(if secure { 990 } else { 21 }),
+            user: match parsed.username() {
+                "" => "anonymous".to_string(),
+                user => decode(user)?,
+            },
+            password: decode(parsed.password().unwrap_or("anonymous@"))?,
+            path,
+            secure,
+        })
+    }
+}
+
+/// Control connection logged in the server
+struct Control {
+    stream: BufReader<Box<dyn Stream>>,
+    location: Location,
+    /// Address of the server, the data connections are opened to it
+    server: SocketAddr,
+    tls: Option<TlsConnector>,
+}
+
+impl Control {
+    /// Connects to the server of the URL and logs in, transferring binary data
+    async fn login(url: &str, connector: &TlsConnector) -> Result<Self, DwldError> {
+        let location = Location::parse(url)?;
+        let tcp = TcpStream::connect((location.host.as_str(), location.port))
+            .await
+            .map_err(network)?;
+        let server = tcp.peer_addr().map_err(network)?;
+        let tls = location.secure.then(|| connector.clone());
+        let stream = secure(tcp, tls.as_ref(), &location.host).await?;
+        let mut control = Self {
+            stream: BufReader::new(stream),
+            location,
+            server,
+            tls,
+        };
+        control.expect(None, 220).await?;
+        let user = format!("USER {}", control.location.user);
+        // the servers without password log in after the user
+        if control.command(&user).await?.0 != 230 {
+            let password = format!("PASS {}", control.location.password);
+            control.expect(Some(&password), 230).await?;
+        }
+        // the data connections are encrypted too
+        if control.tls.is_some() {
+            control.expect(Some("PBSZ 0"), 200).await?;
+            control.expect(Some("PROT P"), 200).await?;
+        }
+        control.expect(Some("TYPE I"), 200).await?;
+        Ok(control)
+    }
+    /// Sends the command and reads the reply of the server, with its code and its text
+    async fn command(&mut self, command: &str) -> Result<(u16, String), DwldError> {
+        let stream = self.stream.get_mut();
+        let line = format!("{}\r\n", command);
+        stream.write_all(line.as_bytes()).await.map_err(network)?;
+        stream.flush().await.map_err(network)?;
+        self.reply().await
+    }
+    /// Reads the reply of the server, the last line of the multiline replies starts with
+    /// the code and a space
+    async fn reply(&mut self) -> Result<(u16, String), DwldError> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stream.read_line(&mut line).await.map_err(network)? == 0 {
+                let closed = io::Error::new(io::ErrorKind::UnexpectedEof, "FTP server closed");
+                return Err(network(closed));
+            }
+            let line = line.trim_end();
+            let code = line.get(..3).and_then(|code| code.parse().ok());
+            if let (Some(code), None | Some(b' ')) = (code, line.as_bytes().get(3)) {
+                return Ok((code, line.get(4..).unwrap_or_default().to_string()));
+            }
+        }
+    }
+    /// Sends the command, if any, and fails if the reply doesn't have the code
+    async fn expect(&mut self, command: Option<&str>, code: u16) -> Result<String, DwldError> {
+        let (replied, message) = match command {
+            Some(command) => self.command(command).await?,
+            None => self.reply().await?,
+        };
+        match replied == code {
+            true => Ok(message),
+            false => Err(DwldError::Ftp {
+                code: replied,
+                message,
+            }),
+        }
+    }
+    /// Size of the file, if the server supports `SIZE`
+    async fn size(&mut self) -> Result<Option<u64>, DwldError> {
+        let command = format!("SIZE {}", self.location.path);
+        match self.command(&command).await? {
+            (213, size) => Ok(size.trim().parse().ok()),
+            // a missing file fails at the transfer
+            _ => Ok(None),
+        }
+    }
+    /// Opens a passive data connection, `EPSV` or `PASV` for the older servers. The
+    /// address in the reply of `PASV` is ignored, it's often private behind a NAT
+    async fn data(&mut self) -> Result<TcpStream, DwldError> {
+        let port = match self.command("EPSV").await? {
+            (229, message) => message
+                .split('|')
+                .nth(3)
+                .and_then(|port| port.parse::<u16>().ok()),
+            _ => match self.command("PASV").await? {
+                (227, message) => {
+                    let start = message.find('(').map_or(0, |start| start + 1);
+                    let numbers: Vec<u16> = message[start..]
+                        .split(|c: char| !c.is_ascii_digit())
+                        .take(6)
+                        .filter_map(|number| number.parse().ok())
+                        .collect();
+                    match numbers[..] {
+                        [_, _, _, _, high, low] => Some(high * 256 + low),
+                        _ => None,
+                    }
+                }
+                (code, message) => return Err(DwldError::Ftp { code, message }),
+            },
+        };
+        let port = port.ok_or_else(|| {
+            let invalid = io::Error::new(io::ErrorKind::InvalidData, "invalid passive port");
+            network(invalid)
+        })?;
+        let address = SocketAddr::new(self.server.ip(), port);
+        TcpStream::connect(address).await.map_err(network)
+    }
+}
+
+/// Encrypts the connection to the host if there's a connector
+async fn secure(
+    tcp: TcpStream,
+    tls: Option<&TlsConnector>,
+    host: &str,
+) -> Result<Box<dyn Stream>, DwldError> {
+    match tls {
+        Some(connector) => Ok(Box::new(
+            connector.connect(host, tcp).await.map_err(network)?,
+        )),
+        None => Ok(Box::new(tcp)),
+    }
+}
+
+/// Size of the file of an FTP URL, if the server tells it
+pub(crate) async fn length(url: &str, connector: &TlsConnector) -> Result<Option<u64>, DwldError> {
+    Control::login(url, connector).await?.size().await
+}
+
+/// Body of the file of an FTP URL from the offset, with the offset it starts at. The file
+/// is downloaded from the beginning if the server doesn't restart the transfers
+pub(crate) async fn body(
+    url: &str,
+    offset: u64,
+    connector: &TlsConnector,
+) -> Result<(surf::Body, u64), DwldError> {
+    let mut control = Control::login(url, connector).await?;
+    let size = control.size().await?;
+    let offset = match size {
+        // the partial data is already the whole file
+        Some(size) if offset == size => return Ok((surf::Body::empty(), offset)),
+        Some(size) if offset > size => 0,
+        _ => offset,
+    };
+    let data = control.data().await?;
+    let offset = match offset {
+        0 => 0,
+        offset => match control.command(&format!("REST {}", offset)).await? {
+            (350, _) => offset,
+            _ => 0,
+        },
+    };
+    let command = format!("RETR {}", control.location.path);
+    match control.command(&command).await? {
+        (125 | 150, _) => {}
+        (code, message) => return Err(DwldError::Ftp { code, message }),
+    }
+    // the servers start the TLS of the data connection with the transfer
+    let data = secure(data, control.tls.as_ref(), &control.location.host).await?;
+    let transfer = Transfer {
+        data,
+        _control: control,
+    };
+    let len = size.map(|size| (size - offset) as usize);
+    Ok((
+        surf::Body::from_reader(BufReader::new(transfer), len),
+        offset,
+    ))
+}
+
+/// Data of a transfer, the control connection is kept open until it ends
+struct Transfer {
+    data: Box<dyn Stream>,
+    _control: Control,
+}
+
+impl AsyncRead for Transfer {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.data).poll_read(cx, buf)
+    }
+}
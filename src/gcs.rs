@@ -0,0 +1,351 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use ring::{rand::SystemRandom, signature};
+use serde_json::{Value, json};
+use surf::middleware::{Middleware, Next};
+use surf::{Client, Request, Response, Result, Url};
+
+use crate::cloud::{self, credentials_error, env};
+
+/// Scope of the tokens of the downloads, the objects are only read
+const READ_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_only";
+/// Scope of the tokens of the uploads of [crate::storage::gcs::GcsBackend]
+const WRITE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// Token endpoint of the credentials that don't tell theirs
+const TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Checks if the URL is a `gs://bucket/object` URL of Google Cloud Storage
+pub(crate) fn is_gcs_url(url: &str) -> bool {
+    url.get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("gs://"))
+}
+
+/// Authentication and endpoint of the `gs://bucket/object` URLs. Without credentials, the
+/// Application Default Credentials are used like the Google Cloud libraries do
+#[derive(Debug, Clone, Default)]
+pub struct DLGcsConfig {
+    /// Key of a service account or credentials of an authorized user, in the JSON of the
+    /// Google Cloud console or of `gcloud auth application-default login`
+    pub credentials_file: Option<PathBuf>,
+    /// Access token sent as is, e.g. of `gcloud auth print-access-token`
+    pub access_token: Option<String>,
+    /// Endpoint of the XML API, `STORAGE_EMULATOR_HOST` or `https://storage.googleapis.com`
+    /// if unset
+    pub endpoint: Option<String>,
+    /// Sends the requests without token, for the public objects
+    pub anonymous: bool,
+}
+
+impl DLGcsConfig {
+    /// Settings using the Application Default Credentials
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Authenticates with the JSON credentials of a service account or an authorized user
+    pub fn with_credentials_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.credentials_file = Some(path.into());
+        self
+    }
+    /// Authenticates with the access token, it isn't refreshed
+    pub fn with_access_token(mut self, token: &str) -> Self {
+        self.access_token = Some(token.to_string());
+        self
+    }
+    /// Downloads from another endpoint of the XML API, e.g. an emulator
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = Some(endpoint.to_string());
+        self
+    }
+    /// Sends the requests without token, for the public objects
+    pub fn anonymous(mut self) -> Self {
+        self.anonymous = true;
+        self
+    }
+    /// Middleware sending the requests of the `gs://` URLs with the resolved credentials
+    pub(crate) fn middleware(&self) -> GcsMiddleware {
+        self.middleware_with_scope(READ_SCOPE)
+    }
+    /// Middleware of the uploads, its tokens can write the objects
+    pub(crate) fn write_middleware(&self) -> GcsMiddleware {
+        self.middleware_with_scope(WRITE_SCOPE)
+    }
+    fn middleware_with_scope(&self, scope: &'static str) -> GcsMiddleware {
+        let credentials = match (&self.access_token, &self.credentials_file) {
+            _ if self.anonymous => Credentials::Anonymous,
+            (Some(token), _) => Credentials::Token(token.clone()),
+            (None, Some(path)) => Credentials::load(path),
+            (None, None) => Credentials::application_default(),
+        };
+        let endpoint = self
+            .endpoint
+            .clone()
+            .or_else(|| {
+                let host = env("STORAGE_EMULATOR_HOST")?;
+                match host.contains("://") {
+                    true => Some(host),
+                    false => Some(format!("http://{}", host)),
+                }
+            })
+            .unwrap_or_else(|| "https://storage.googleapis.com".to_string());
+        GcsMiddleware {
+            credentials,
+            scope,
+            endpoint,
+            token: smol::lock::Mutex::new(None),
+        }
+    }
+}
+
+/// Source of the access tokens
+enum Credentials {
+    Anonymous,
+    Token(String),
+    ServiceAccount {
+        email: String,
+        key: Box<signature::RsaKeyPair>,
+        token_uri: String,
+    },
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+        token_uri: String,
+    },
+    /// Metadata server of the Google Cloud instances, from its host
+    Metadata(String),
+    /// The credentials can't be loaded, every request fails with the message
+    Invalid(String),
+}
+
+impl Credentials {
+    /// Credentials of `GOOGLE_APPLICATION_CREDENTIALS`, of `gcloud` or of the metadata server
+    fn application_default() -> Self {
+        if let Some(path) = std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+            return Self::load(Path::new(&path));
+        }
+        let gcloud = std::env::var_os("CLOUDSDK_CONFIG")
+            .map(PathBuf::from)
+            .or_else(|| {
+                let home = std::env::var_os("HOME")?;
+                Some(PathBuf::from(home).join(".config").join("gcloud"))
+            })
+            .map(|dir| dir.join("application_default_credentials.json"));
+        if let Some(path) = gcloud.filter(|path| path.is_file()) {
+            return Self::load(&path);
+        }
+        let host = env("GCE_METADATA_HOST");
+        Self::Metadata(host.unwrap_or_else(|| "metadata.google.internal".to_string()))
+    }
+    /// Credentials of the JSON file, of a service account or of an authorized user
+    fn load(path: &Path) -> Self {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => return Self::Invalid(format!("{} can't be read: {}", path.display(), e)),
+        };
+        let invalid = |message: &str| Self::Invalid(format!("{} {}", path.display(), message));
+        let Ok(json) = serde_json::from_str::<Value>(&text) else {
+            return invalid("isn't JSON");
+        };
+        let field = |name: &str| json[name].as_str().map(str::to_string);
+        let token_uri = field("token_uri").unwrap_or_else(|| TOKEN_URI.to_string());
+        match json["type"].as_str() {
+            Some("service_account") => {
+                let (Some(email), Some(pem)) = (field("client_email"), field("private_key")) else {
+                    return invalid("misses the email or the key of the service account");
+                };
+                let Some(key) = private_key(&pem) else {
+                    return invalid("has an invalid private key");
+                };
+                Self::ServiceAccount {
+                    email,
+                    key: Box::new(key),
+                    token_uri,
+                }
+            }
+            Some("authorized_user") => {
+                let user = (
+                    field("client_id"),
+                    field("client_secret"),
+                    field("refresh_token"),
+                );
+                let (Some(client_id), Some(client_secret), Some(refresh_token)) = user else {
+                    return invalid("misses the client or the refresh token of the user");
+                };
+                Self::AuthorizedUser {
+                    client_id,
+                    client_secret,
+                    refresh_token,
+                    token_uri,
+                }
+            }
+            _ => invalid("isn't a service account nor an authorized user"),
+        }
+    }
+    /// New access token of the scope, with its lifetime
+    async fn token(&self, client: &Client, scope: &str) -> Result<Option<(String, Duration)>> {
+        let request = match self {
+            Self::Anonymous => return Ok(None),
+            // the static tokens are never refreshed
+            Self::Token(token) => return Ok(Some((token.clone(), Duration::MAX))),
+            Self::Invalid(message) => return Err(credentials_error(message.clone())),
+            Self::ServiceAccount {
+                email,
+                key,
+                token_uri,
+            } => {
+                let assertion = assertion(email, key, token_uri, scope)?;
+                let form = [
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                    ("assertion", assertion.as_str()),
+                ];
+                surf::post(token_uri).body(surf::Body::from_form(&form)?)
+            }
+            Self::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+                token_uri,
+            } => {
+                let form = [
+                    ("grant_type", "refresh_token"),
+                    ("client_id", client_id.as_str()),
+                    ("client_secret", client_secret.as_str()),
+                    ("refresh_token", refresh_token.as_str()),
+                ];
+                surf::post(token_uri).body(surf::Body::from_form(&form)?)
+            }
+            Self::Metadata(host) => {
+                let path = "computeMetadata/v1/instance/service-accounts/default/token";
+                surf::get(format!("http://{}/{}", host, path)).header("Metadata-Flavor", "Google")
+            }
+        };
+        let mut response = client
+            .send(request)
+            .await
+            .map_err(|e| credentials_error(format!("token request failed: {}", e)))?;
+        let body: Value = match response.status().is_success() {
+            true => response.body_json().await?,
+            false => {
+                let status = response.status();
+                let body = response.body_string().await.unwrap_or_default();
+                let message = format!("token request answered {}: {}", status, body.trim());
+                return Err(credentials_error(message));
+            }
+        };
+        let token = body["access_token"]
+            .as_str()
+            .ok_or_else(|| credentials_error("token response without access token"))?;
+        let lifetime = body["expires_in"].as_u64().unwrap_or(3600);
+        Ok(Some((token.to_string(), Duration::from_secs(lifetime))))
+    }
+}
+
+/// Key of the PEM of a service account, in PKCS#8
+fn private_key(pem: &str) -> Option<signature::RsaKeyPair> {
+    let base64: String = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = base64::decode(base64.trim()).ok()?;
+    signature::RsaKeyPair::from_pkcs8(&der).ok()
+}
+
+/// JWT of the service account asking for a token of the scope, signed with its key
+fn assertion(
+    email: &str,
+    key: &signature::RsaKeyPair,
+    token_uri: &str,
+    scope: &str,
+) -> Result<String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let header = json!({"alg": "RS256", "typ": "JWT"});
+    let claims = json!({
+        "iss": email,
+        "scope": scope,
+        "aud": token_uri,
+        "iat": now,
+        "exp": now + 3600,
+    });
+    let encode = |data: &[u8]| base64::encode_config(data, base64::URL_SAFE_NO_PAD);
+    let message = format!(
+        "{}.{}",
+        encode(header.to_string().as_bytes()),
+        encode(claims.to_string().as_bytes())
+    );
+    let mut signature = vec![0; key.public_modulus_len()];
+    key.sign(
+        &signature::RSA_PKCS1_SHA256,
+        &SystemRandom::new(),
+        message.as_bytes(),
+        &mut signature,
+    )
+    .map_err(|_| credentials_error("the key of the service account can't sign"))?;
+    Ok(format!("{}.{}", message, encode(&signature)))
+}
+
+/// Sends the requests of the `gs://bucket/object` URLs to the XML API of Google Cloud
+/// Storage, with an access token. The other requests are sent unchanged
+pub(crate) struct GcsMiddleware {
+    credentials: Credentials,
+    /// Scope of the tokens of the service accounts
+    scope: &'static str,
+    endpoint: String,
+    /// Current token, with the instant it expires at
+    token: smol::lock::Mutex<Option<(String, Instant)>>,
+}
+
+impl GcsMiddleware {
+    /// Token of the requests, refreshed a minute before it expires
+    async fn token(&self, client: &Client) -> Result<Option<String>> {
+        // the concurrent requests wait for the same refresh
+        let mut current = self.token.lock().await;
+        if let Some((token, expires)) = current.as_ref() {
+            if Instant::now() + Duration::from_secs(60) < *expires {
+                return Ok(Some(token.clone()));
+            }
+        }
+        let Some((token, lifetime)) = self.credentials.token(client, self.scope).await? else {
+            return Ok(None);
+        };
+        trace_event!(DEBUG, lifetime = ?lifetime, "GCS token refreshed");
+        let expires = Instant::now().checked_add(lifetime);
+        let expires = expires.unwrap_or_else(|| Instant::now() + Duration::from_secs(1 << 40));
+        *current = Some((token.clone(), expires));
+        Ok(Some(token))
+    }
+}
+
+#[surf::utils::async_trait]
+impl Middleware for GcsMiddleware {
+    async fn handle(&self, req: Request, client: Client, next: Next<'_>) -> Result<Response> {
+        if req.url().scheme() != "gs" {
+            return next.run(req, client).await;
+        }
+        let invalid = |message| cloud::invalid_object(req.url(), message);
+        let object = req.url().path();
+        let bucket = req
+            .url()
+            .host_str()
+            .ok_or_else(|| invalid("missing the bucket"))?;
+        if object.len() <= 1 || object.ends_with('/') {
+            return Err(invalid("not the name of an object"));
+        }
+        let endpoint = self.endpoint.trim_end_matches('/');
+        let mut url = Url::parse(&format!("{}/{}{}", endpoint, bucket, object))
+            .map_err(|_| invalid("invalid endpoint"))?;
+        url.set_query(req.url().query());
+        let mut req = req;
+        *AsMut::<surf::http::Request>::as_mut(&mut req).url_mut() = url;
+        if let Some(token) = self.token(&client).await? {
+            req.insert_header("Authorization", format!("Bearer {}", token));
+        }
+        next.run(req, client).await
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use smol::channel::{self, Receiver, Sender};
+
+use crate::{DLFile, DwldError, report::DownloadEvent};
+
+/// Gate closed to wake up the waiting downloads
+type Gate = Option<(Sender<()>, Receiver<()>)>;
+/// Channel of the events of the session, opened by the first subscriber
+type Events = Option<(Sender<DownloadEvent>, Receiver<DownloadEvent>)>;
+
+/// Why a session was cancelled, given to the files it aborted
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum CancelReason {
+    /// The application cancelled it, see [DLHandle::cancel]
+    User,
+    /// The session exceeded its deadline, see [crate::Downloader::with_deadline]
+    Deadline,
+    /// A file failed and the error policy aborted the rest, with the path of the file, see
+    /// [crate::ErrorPolicy::Abort]
+    Failure(String),
+    /// The process received a signal, e.g. Ctrl-C, see
+    /// [crate::Downloader::with_cancel_on_signal]
+    Signal,
+}
+
+impl fmt::Display for CancelReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::User => write!(f, "by the user"),
+            Self::Deadline => write!(f, "at the deadline of the session"),
+            Self::Failure(path) => write!(f, "after {} failed", path),
+            Self::Signal => write!(f, "by a signal"),
+        }
+    }
+}
+
+/// What happens to the rest of the session when a file fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum ErrorPolicy {
+    /// The other files keep downloading
+    #[default]
+    Continue,
+    /// The session is cancelled with [CancelReason::Failure]
+    Abort,
+}
+
+/// Handle controlling a running download session, it can be used from other threads
+#[derive(Debug, Clone)]
+pub struct DLHandle {
+    // gate closed to wake up every waiting download, replaced by a reset
+    cancel: Arc<Mutex<(Sender<()>, Receiver<()>)>>,
+    // reason of the first cancellation
+    reason: Arc<Mutex<Option<CancelReason>>>,
+    // gate of the paused session, closed when it's resumed
+    pause: Arc<Mutex<Gate>>,
+    // files added while the session runs
+    added_tx: Sender<DLFile>,
+    added_rx: Receiver<DLFile>,
+    events: Arc<Mutex<Events>>,
+}
+
+impl Default for DLHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DLHandle {
+    pub fn new() -> Self {
+        let (added_tx, added_rx) = channel::unbounded();
+        Self {
+            cancel: Arc::new(Mutex::new(channel::bounded(1))),
+            reason: Arc::new(Mutex::new(None)),
+            pause: Arc::new(Mutex::new(None)),
+            added_tx,
+            added_rx,
+            events: Arc::new(Mutex::new(None)),
+        }
+    }
+    /// Cancels the session, no new files are started and the running ones are aborted. The
+    /// handle stays cancelled until [DLHandle::reset], the sessions started with it
+    /// meanwhile are cancelled at once
+    pub fn cancel(&self) {
+        self.cancel_with(CancelReason::User);
+    }
+    /// Cancels the session with the reason given to the aborted files, e.g.
+    /// [CancelReason::Signal] from a Ctrl-C handler. Only the first reason is kept
+    pub fn cancel_with(&self, reason: CancelReason) {
+        let mut current = self.reason.lock().unwrap_or_else(|e| e.into_inner());
+        current.get_or_insert(reason);
+        self.lock_cancel().0.close();
+    }
+    /// Clears the cancellation once the session finished, so the handle can run another one
+    pub fn reset(&self) {
+        let mut reason = self.reason.lock().unwrap_or_else(|e| e.into_inner());
+        let mut cancel = self.lock_cancel();
+        if cancel.0.is_closed() {
+            *cancel = channel::bounded(1);
+        }
+        *reason = None;
+    }
+    /// Checks if the session has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.lock_cancel().0.is_closed()
+    }
+    /// Why the session was cancelled, if it was
+    pub fn cancel_reason(&self) -> Option<CancelReason> {
+        self.reason
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+    /// Error of the files aborted by the cancellation
+    pub(crate) fn cancelled_error(&self) -> DwldError {
+        DwldError::Cancelled(self.cancel_reason().unwrap_or(CancelReason::User))
+    }
+    /// Completes when the session is cancelled
+    pub(crate) async fn cancelled(&self) {
+        let gate = self.lock_cancel().1.clone();
+        let _ = gate.recv().await;
+    }
+    /// Pauses the session, the transfers stop reading keeping their connections open
+    pub fn pause(&self) {
+        let mut pause = self.lock_pause();
+        if pause.is_none() {
+            *pause = Some(channel::bounded(1));
+        }
+    }
+    /// Resumes the paused session
+    pub fn resume(&self) {
+        if let Some((tx, _)) = self.lock_pause().take() {
+            tx.close();
+        }
+    }
+    /// Checks if the session is paused
+    pub fn is_paused(&self) -> bool {
+        self.lock_pause().is_some()
+    }
+    /// Completes when the session is not paused, returns if it had to wait
+    pub(crate) async fn resumed(&self) -> bool {
+        let gate = self.lock_pause().as_ref().map(|(_, rx)| rx.clone());
+        match gate {
+            Some(gate) => {
+                let _ = gate.recv().await;
+                true
+            }
+            None => false,
+        }
+    }
+    /// Adds a file to the running session, e.g. the assets listed by a downloaded index.
+    /// The files added after the session finishes are downloaded by the next one
+    pub fn add_file(&self, file: DLFile) {
+        let _ = self.added_tx.try_send(file);
+    }
+    /// Completes with the next file added to the session
+    pub(crate) async fn added(&self) -> DLFile {
+        match self.added_rx.recv().await {
+            Ok(file) => file,
+            // the handle keeps the sender open
+            Err(_) => std::future::pending().await,
+        }
+    }
+    /// File added to the session, if there is any waiting
+    pub(crate) fn try_added(&self) -> Option<DLFile> {
+        self.added_rx.try_recv().ok()
+    }
+    /// Receives an event as each file finishes, so the completed files can be processed
+    /// before the session ends. The channel is closed when the session finishes, the
+    /// receivers taken before a session starts get its events
+    pub fn events(&self) -> Receiver<DownloadEvent> {
+        let mut events = self.lock_events();
+        events.get_or_insert_with(channel::unbounded).1.clone()
+    }
+    /// Sends the event to the receivers, if there is any
+    pub(crate) fn emit(&self, event: DownloadEvent) {
+        if let Some((tx, _)) = self.lock_events().as_ref() {
+            let _ = tx.try_send(event);
+        }
+    }
+    /// Closes the channel of the events after the session
+    pub(crate) fn close_events(&self) {
+        if let Some((tx, _)) = self.lock_events().take() {
+            tx.close();
+        }
+    }
+    fn lock_events(&self) -> std::sync::MutexGuard<'_, Events> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn lock_cancel(&self) -> std::sync::MutexGuard<'_, (Sender<()>, Receiver<()>)> {
+        self.cancel.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn lock_pause(&self) -> std::sync::MutexGuard<'_, Gate> {
+        self.pause.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
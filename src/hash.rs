@@ -0,0 +1,450 @@
+use std::{
+    fs::{File, Metadata},
+    hash::BuildHasher,
+    io::{self, Read},
+    path::Path,
+    time::Duration,
+};
+
+use md5::Md5;
+use sha1::{Digest, Sha1};
+use sha2::{Sha224, Sha256, Sha384, Sha512};
+
+/// Size of the buffer used to hash files, DEFAULT = 64KB
+const HASH_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DLHashes {
+    pub hashes: Vec<(DLHashType, String)>,
+}
+impl Default for DLHashes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl DLHashes {
+    pub fn new() -> Self {
+        Self { hashes: Vec::new() }
+    }
+    pub fn add_hash(mut self, hash_type: DLHashType, hash_value: String) -> Self {
+        self.hashes.push((hash_type, hash_value));
+        self
+    }
+    pub fn sha1(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::SHA1, hash.to_string()));
+        self
+    }
+    pub fn sha256(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::SHA256, hash.to_string()));
+        self
+    }
+    pub fn sha384(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::SHA384, hash.to_string()));
+        self
+    }
+    pub fn sha512(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::SHA512, hash.to_string()));
+        self
+    }
+    pub fn sha224(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::SHA224, hash.to_string()));
+        self
+    }
+    /// MD5 is broken against forged files, only to check accidental corruption, e.g. with
+    /// the digests of the servers
+    pub fn md5(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::MD5, hash.to_string()));
+        self
+    }
+    /// CRC-32C of the data in big-endian hexadecimal, e.g. of the objects of Google Cloud
+    /// Storage. It only detects accidental corruption
+    pub fn crc32c(mut self, hash: &str) -> Self {
+        self.hashes.push((DLHashType::CRC32C, hash.to_string()));
+        self
+    }
+    pub fn verify_data(&self, data: &[u8]) -> bool {
+        self.hashes
+            .iter()
+            .any(|(typ, hash)| typ.verify_data(data, hash))
+    }
+    pub fn verify_str(&self, data: &str) -> bool {
+        self.verify_data(data.as_bytes())
+    }
+    pub fn verify_file(&self, path: &str) -> bool {
+        self.verify_file_with(Path::new(path), VerifyStrategy::default())
+            .unwrap_or(false)
+    }
+    /// Verifies the file reading it with the given strategy
+    pub fn verify_file_with(&self, path: &Path, strategy: VerifyStrategy) -> io::Result<bool> {
+        let digests = self.digest_file_with(path, strategy)?;
+        Ok(digests.iter().any(DLDigest::matches))
+    }
+    /// Hashes of the file read with the given strategy, with the expected ones
+    pub fn digest_file_with(
+        &self,
+        path: &Path,
+        strategy: VerifyStrategy,
+    ) -> io::Result<Vec<DLDigest>> {
+        let mut hasher = self.hasher();
+        strategy.hash_file(path, &mut hasher)?;
+        Ok(hasher.digests())
+    }
+    /// Hashes of the data, with the expected ones
+    pub fn digest_data(&self, data: &[u8]) -> Vec<DLDigest> {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.digests()
+    }
+    /// Streaming hasher computing all the hashes at once
+    pub fn hasher(&self) -> DLHasher {
+        DLHasher {
+            hashers: self
+                .hashes
+                .iter()
+                .map(|(typ, hash)| (typ.hasher(), hash.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DLHashType {
+    SHA1,
+    SHA256,
+    SHA224,
+    SHA384,
+    SHA512,
+    /// Only protects against accidental corruption, see [DLHashes::md5]
+    MD5,
+    /// Only protects against accidental corruption, see [DLHashes::crc32c]
+    CRC32C,
+}
+
+impl DLHashType {
+    /// Función genérica que crea el hasher, actualiza con los datos y devuelve el hash en hexadecimal.
+    fn compute_hash<D: Digest + Default>(data: &[u8]) -> String {
+        let mut hasher = D::default();
+        hasher.update(data);
+        let result = hasher.finalize();
+        hex::encode(result)
+    }
+
+    /// Calcula el hash usando el algoritmo seleccionado.
+    pub fn compute(&self, data: &[u8]) -> String {
+        match self {
+            DLHashType::SHA1 => Self::compute_hash::<Sha1>(data),
+            DLHashType::SHA256 => Self::compute_hash::<Sha256>(data),
+            DLHashType::SHA224 => Self::compute_hash::<Sha224>(data),
+            DLHashType::SHA384 => Self::compute_hash::<Sha384>(data),
+            DLHashType::SHA512 => Self::compute_hash::<Sha512>(data),
+            DLHashType::MD5 => Self::compute_hash::<Md5>(data),
+            DLHashType::CRC32C => {
+                let mut hasher = Crc32c::default();
+                hasher.update(data);
+                hex::encode(hasher.finalize())
+            }
+        }
+    }
+    pub fn verify_str(&self, data: &str, hash: &str) -> bool {
+        self.compute(data.to_string().as_bytes()) == hash
+    }
+    pub fn verify_data(&self, data: &[u8], hash: &str) -> bool {
+        self.compute(data) == hash
+    }
+    /// Checks the hash of the file, a file that can't be read doesn't match
+    pub fn verify_file(&self, path: &Path, hash: &str) -> bool {
+        self.try_verify_file(path, hash).unwrap_or(false)
+    }
+    /// Checks the hash of the file, the errors reading it are returned
+    pub fn try_verify_file(&self, path: &Path, hash: &str) -> io::Result<bool> {
+        let mut hasher = DLHasher {
+            hashers: vec![(self.hasher(), hash.to_string())],
+        };
+        VerifyStrategy::default().hash_file(path, &mut hasher)?;
+        Ok(hasher.verify())
+    }
+    /// Streaming hasher of the algorithm
+    pub fn hasher(&self) -> DLTypeHasher {
+        match self {
+            DLHashType::SHA1 => DLTypeHasher::SHA1(Sha1::default()),
+            DLHashType::SHA256 => DLTypeHasher::SHA256(Sha256::default()),
+            DLHashType::SHA224 => DLTypeHasher::SHA224(Sha224::default()),
+            DLHashType::SHA384 => DLTypeHasher::SHA384(Sha384::default()),
+            DLHashType::SHA512 => DLTypeHasher::SHA512(Sha512::default()),
+            DLHashType::MD5 => DLTypeHasher::MD5(Md5::default()),
+            DLHashType::CRC32C => DLTypeHasher::CRC32C(Crc32c::default()),
+        }
+    }
+}
+
+/// Streaming hasher of one algorithm
+#[derive(Clone)]
+pub enum DLTypeHasher {
+    SHA1(Sha1),
+    SHA256(Sha256),
+    SHA224(Sha224),
+    SHA384(Sha384),
+    SHA512(Sha512),
+    MD5(Md5),
+    CRC32C(Crc32c),
+}
+
+impl DLTypeHasher {
+    /// Feeds a chunk of data
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            DLTypeHasher::SHA1(h) => h.update(data),
+            DLTypeHasher::SHA256(h) => h.update(data),
+            DLTypeHasher::SHA224(h) => h.update(data),
+            DLTypeHasher::SHA384(h) => h.update(data),
+            DLTypeHasher::SHA512(h) => h.update(data),
+            DLTypeHasher::MD5(h) => h.update(data),
+            DLTypeHasher::CRC32C(h) => h.update(data),
+        }
+    }
+    /// Algorithm of the hasher
+    pub fn hash_type(&self) -> DLHashType {
+        match self {
+            DLTypeHasher::SHA1(_) => DLHashType::SHA1,
+            DLTypeHasher::SHA256(_) => DLHashType::SHA256,
+            DLTypeHasher::SHA224(_) => DLHashType::SHA224,
+            DLTypeHasher::SHA384(_) => DLHashType::SHA384,
+            DLTypeHasher::SHA512(_) => DLHashType::SHA512,
+            DLTypeHasher::MD5(_) => DLHashType::MD5,
+            DLTypeHasher::CRC32C(_) => DLHashType::CRC32C,
+        }
+    }
+    /// Hexadecimal hash of all the data fed
+    pub fn finalize(self) -> String {
+        match self {
+            DLTypeHasher::SHA1(h) => hex::encode(h.finalize()),
+            DLTypeHasher::SHA256(h) => hex::encode(h.finalize()),
+            DLTypeHasher::SHA224(h) => hex::encode(h.finalize()),
+            DLTypeHasher::SHA384(h) => hex::encode(h.finalize()),
+            DLTypeHasher::SHA512(h) => hex::encode(h.finalize()),
+            DLTypeHasher::MD5(h) => hex::encode(h.finalize()),
+            DLTypeHasher::CRC32C(h) => hex::encode(h.finalize()),
+        }
+    }
+}
+
+/// Lookup table of the reflected Castagnoli polynomial
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0x82f6_3b78,
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Streaming CRC-32C (Castagnoli)
+#[derive(Clone)]
+pub struct Crc32c {
+    crc: u32,
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self { crc: !0 }
+    }
+}
+
+impl Crc32c {
+    /// Feeds a chunk of data
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            let index = (self.crc ^ byte as u32) & 0xff;
+            self.crc = (self.crc >> 8) ^ CRC32C_TABLE[index as usize];
+        }
+    }
+    /// Big-endian bytes of the CRC of all the data fed
+    pub fn finalize(self) -> [u8; 4] {
+        (!self.crc).to_be_bytes()
+    }
+}
+
+/// Streaming hasher of a set of hashes
+#[derive(Clone)]
+pub struct DLHasher {
+    hashers: Vec<(DLTypeHasher, String)>,
+}
+
+impl DLHasher {
+    /// Feeds a chunk of data
+    pub fn update(&mut self, data: &[u8]) {
+        for (hasher, _) in self.hashers.iter_mut() {
+            hasher.update(data);
+        }
+    }
+    /// Checks if any of the hashes matches the data fed
+    pub fn verify(self) -> bool {
+        self.digests().iter().any(DLDigest::matches)
+    }
+    /// Hashes of the data fed, with the expected ones
+    pub fn digests(self) -> Vec<DLDigest> {
+        self.hashers
+            .into_iter()
+            .map(|(hasher, expected)| DLDigest {
+                hash_type: hasher.hash_type(),
+                expected,
+                actual: hasher.finalize(),
+            })
+            .collect()
+    }
+}
+
+/// Hash computed by a [DLHasher], with the expected one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DLDigest {
+    pub hash_type: DLHashType,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl DLDigest {
+    /// Checks if the data has the expected hash
+    pub fn matches(&self) -> bool {
+        self.actual == self.expected
+    }
+}
+
+/// Strategy used to read the files when verifying them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum VerifyStrategy {
+    /// Reads the file in chunks
+    #[default]
+    Buffered,
+    /// Maps the file in memory, falls back to buffered if the file can't be mapped
+    #[cfg(feature = "mmap")]
+    Mmap,
+}
+
+impl VerifyStrategy {
+    /// Feeds the whole file to the hasher
+    pub fn hash_file(&self, path: &Path, hasher: &mut DLHasher) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        match self {
+            VerifyStrategy::Buffered => hash_buffered(&mut file, hasher),
+            #[cfg(feature = "mmap")]
+            VerifyStrategy::Mmap => {
+                // SAFETY: the file is only read, modifications by other processes
+                // during the verification only make the hashes not match
+                match unsafe { memmap2::Mmap::map(&file) } {
+                    Ok(map) => {
+                        hasher.update(&map);
+                        Ok(())
+                    }
+                    Err(_) => hash_buffered(&mut file, hasher),
+                }
+            }
+        }
+    }
+}
+
+/// Quick check of the destinations kept by [crate::DLFile::with_skip_if_valid], for repair
+/// scans of huge batches. The destinations with the right size are kept without hashing
+/// them, except a random sample and the recently modified ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLSampling {
+    /// Fraction of the destinations hashed, from 0 to 1. With 1 every destination is hashed
+    pub rate: f64,
+    /// The destinations modified more recently are always hashed
+    pub recent: Duration,
+    /// Seed choosing the sample, the same seed hashes the same files. A new sample is
+    /// chosen every time if unset
+    pub seed: Option<u64>,
+}
+
+impl DLSampling {
+    /// Hashes the fraction of the destinations and the ones modified in the last day
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            recent: Duration::from_secs(24 * 60 * 60),
+            seed: None,
+        }
+    }
+    /// Sets the age under which the destinations are always hashed
+    pub fn with_recent(mut self, recent: Duration) -> Self {
+        self.recent = recent;
+        self
+    }
+    /// Chooses the same sample in every scan
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+    /// Checks if the destination with the metadata can be kept without hashing it
+    pub(crate) fn skips_hash(&self, path: &str, metadata: &Metadata) -> bool {
+        // a modification time in the future or unknown isn't trusted
+        let recent = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_none_or(|age| age < self.recent);
+        if recent {
+            return false;
+        }
+        let draw = match self.seed {
+            // SHA-256 doesn't change between the Rust releases like the std hashers
+            Some(seed) => {
+                let mut hasher = Sha256::new();
+                hasher.update(seed.to_le_bytes());
+                hasher.update(path.as_bytes());
+                let digest = hasher.finalize();
+                (digest[..8].iter()).fold(0, |draw, byte| draw << 8 | u64::from(*byte))
+            }
+            None => std::collections::hash_map::RandomState::new().hash_one(path),
+        };
+        // the draw is uniform over the u64, the sample is the fraction below the rate
+        (draw as f64 / u64::MAX as f64) >= self.rate
+    }
+}
+
+/// Feeds the first `len` bytes of the file to the hasher, e.g. the resumed part of a
+/// download
+pub(crate) fn hash_prefix(path: &Path, len: u64, hasher: &mut DLHasher) -> io::Result<()> {
+    let mut prefix = File::open(path)?.take(len);
+    hash_buffered(&mut prefix, hasher)
+}
+
+fn hash_buffered(file: &mut impl Read, hasher: &mut DLHasher) -> io::Result<()> {
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(()),
+            n => hasher.update(&buffer[..n]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Crc32c;
+
+    #[test]
+    fn crc32c_check_value() {
+        let mut crc = Crc32c::default();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(hex::encode(crc.finalize()), "e3069283");
+    }
+}
@@ -1,3 +1,11 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{format::format_bytes, handle::CancelReason};
+
 /// Singals of the file
 pub enum IndicateSignal {
     /// Failed download
@@ -6,14 +14,53 @@ pub enum IndicateSignal {
     State(String),
     /// Success download
     Success(),
+    /// The file was already in the CAS storage, nothing was downloaded
+    Cached(),
+    /// The file was not downloaded, with the reason, e.g. its destination is already valid
+    /// or exists. Every file finishing with [crate::DLStatus::Skipped] sends it
+    Skipped(String),
     /// Start download
     Start(),
+    /// Size of the file, found in the response when it wasn't given
+    Size(u64),
+    /// The size of the file is unknown, neither given nor sent by the server, e.g. chunked
+    /// responses. The progress is only the bytes written
+    UnknownSize(),
+    /// The session was cancelled before the file finished, with the reason
+    Cancelled(CancelReason),
+    /// The file starts again from the beginning, e.g. retried without resuming. The
+    /// progress reported before is discarded
+    Reset(),
+    /// The file goes back to the position, e.g. retried resuming the data kept from the
+    /// failed attempt. The progress after the position is discarded
+    Rewind(u64),
+}
+
+/// State of a file when its indicator is created
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Nothing downloaded yet
+    Fresh,
+    /// Part of the file is already downloaded, with the bytes present
+    Resumed(u64),
+    /// The file is already in the CAS storage, nothing will be downloaded
+    Cached,
 }
 
 /// Trait for creation of indicators
 pub trait IndicatorFactory: Default {
     /// Creates a new indicator for file with size
     fn create_task(&self, name: &str, size: u64) -> impl Indicator;
+    /// Creates a new indicator for file with size, starting from its current state
+    fn create_task_with_state(&self, name: &str, size: u64, state: TaskState) -> impl Indicator {
+        let mut indicator = self.create_task(name, size);
+        match state {
+            TaskState::Fresh => {}
+            TaskState::Resumed(position) => indicator.effect(position),
+            TaskState::Cached => indicator.effect(size),
+        }
+        indicator
+    }
 }
 /// Trait for indicator in one single file
 pub trait Indicator {
@@ -23,57 +70,279 @@ pub trait Indicator {
     fn signal(&mut self, signal: IndicateSignal);
 }
 
+/// Indicator signaling when the position goes back before reporting it, so the
+/// indicators can correct their progress instead of seeing it jump backwards
+pub(crate) struct Rewinding<I> {
+    indicator: I,
+    position: u64,
+}
+
+impl<I: Indicator> Rewinding<I> {
+    /// Wraps the indicator created with the state of the file
+    pub fn new(indicator: I, state: TaskState, size: u64) -> Self {
+        let position = match state {
+            TaskState::Fresh => 0,
+            TaskState::Resumed(position) => position,
+            TaskState::Cached => size,
+        };
+        Self {
+            indicator,
+            position,
+        }
+    }
+}
+
+impl<I: Indicator> Indicator for Rewinding<I> {
+    fn effect(&mut self, position: u64) {
+        match position {
+            0 if self.position > 0 => self.indicator.signal(IndicateSignal::Reset()),
+            position if position < self.position => {
+                self.indicator.signal(IndicateSignal::Rewind(position))
+            }
+            _ => {}
+        }
+        self.position = position;
+        self.indicator.effect(position);
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        self.indicator.signal(signal);
+    }
+}
+
 /// Silent default indicator, don't print any thing
 #[derive(Default)]
 pub struct Silent;
 impl IndicatorFactory for Silent {
-    fn create_task(&self, name: &str, size: u64) -> impl Indicator {
+    fn create_task(&self, _name: &str, _size: u64) -> impl Indicator {
         SilentChild
     }
 }
 // Child of silent indicator
 pub struct SilentChild;
 impl Indicator for SilentChild {
-    fn signal(&mut self, signal: IndicateSignal) {}
-    fn effect(&mut self, position: u64) {}
+    fn signal(&mut self, _signal: IndicateSignal) {}
+    fn effect(&mut self, _position: u64) {}
+}
+
+/// Indicator printing the progress to stdout in a single line rewritten with carriage
+/// returns, and a line with the final status of every file. It doesn't need any
+/// dependency, the concurrent files share the line
+#[derive(Default)]
+pub struct Stdout {
+    /// Width of the progress line printed last, to clear it
+    width: Arc<Mutex<usize>>,
+}
+impl IndicatorFactory for Stdout {
+    fn create_task(&self, name: &str, size: u64) -> impl Indicator {
+        StdoutChild {
+            name: name.to_string(),
+            size,
+            position: 0,
+            state: String::new(),
+            printed: None,
+            finished: false,
+            width: Arc::clone(&self.width),
+        }
+    }
+}
+// Child of stdout indicator
+pub struct StdoutChild {
+    name: String,
+    size: u64,
+    position: u64,
+    state: String,
+    /// Percent printed last, or the time of the last print of the files of unknown size
+    printed: Option<(u64, Instant)>,
+    /// The final status was printed, the extraction may signal the end again
+    finished: bool,
+    width: Arc<Mutex<usize>>,
+}
+impl StdoutChild {
+    /// Time between the prints of the files of unknown size
+    const INTERVAL: Duration = Duration::from_millis(200);
+
+    fn progress(&self) -> String {
+        let progress = match self.size {
+            0 => format_bytes(self.position),
+            size => format!("{:>3}%", self.percent(size)),
+        };
+        match self.state.is_empty() {
+            true => format!("{} {}", self.name, progress),
+            false => format!("{} {} {}", self.name, progress, self.state),
+        }
+    }
+    fn percent(&self, size: u64) -> u64 {
+        (self.position.min(size) * 100)
+            .checked_div(size)
+            .unwrap_or(0)
+    }
+    /// Rewrites the progress line, ending it if the file finished
+    fn print(&mut self, line: &str, finished: bool) {
+        let mut width = self.width.lock().unwrap_or_else(|e| e.into_inner());
+        let padding = width.saturating_sub(line.chars().count());
+        let mut stdout = std::io::stdout().lock();
+        let _ = match finished {
+            true => writeln!(stdout, "\r{}{:padding$}", line, ""),
+            false => write!(stdout, "\r{}{:padding$}", line, ""),
+        };
+        let _ = stdout.flush();
+        *width = match finished {
+            true => 0,
+            false => line.chars().count(),
+        };
+        let mark = match self.size {
+            0 => 0,
+            size => self.percent(size),
+        };
+        self.printed = Some((mark, Instant::now()));
+    }
+}
+impl Indicator for StdoutChild {
+    fn effect(&mut self, position: u64) {
+        self.position = position;
+        let due = match (self.size, self.printed) {
+            (_, None) => true,
+            (0, Some((_, printed))) => printed.elapsed() >= Self::INTERVAL,
+            (size, Some((percent, _))) => self.percent(size) != percent,
+        };
+        if due {
+            self.print(&self.progress(), false);
+        }
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        let status = match signal {
+            IndicateSignal::Success() => format!("done, {}", format_bytes(self.position)),
+            IndicateSignal::Cached() => "cached".to_string(),
+            IndicateSignal::Skipped(reason) => format!("skipped, {}", reason),
+            IndicateSignal::Fail(e) => format!("failed, {}", e),
+            IndicateSignal::Cancelled(reason) => format!("cancelled {}", reason),
+            IndicateSignal::State(state) => {
+                self.state = state;
+                return self.print(&self.progress(), false);
+            }
+            IndicateSignal::Size(size) => return self.size = size,
+            IndicateSignal::UnknownSize() => return self.size = 0,
+            IndicateSignal::Reset() => return self.position = 0,
+            IndicateSignal::Rewind(position) => return self.position = position,
+            IndicateSignal::Start() => return,
+        };
+        if !self.finished {
+            self.finished = true;
+            self.print(&format!("{} {}", self.name, status), true);
+        }
+    }
 }
 
 #[cfg(feature = "indicatif_indicator")]
 pub mod indicatif {
-    use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+    use std::fmt::Write;
+
+    use crate::format::{format_bytes, format_eta, format_speed};
+    use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
     /// Indicatif indicator implementation
     pub struct Indicatif {
         style: ProgressStyle,
+        /// Style of the files of unknown size
+        spinner_style: ProgressStyle,
         multiprogress: MultiProgress,
     }
     impl super::IndicatorFactory for Indicatif {
-        fn create_task(&self, name: &str, size: u64) -> IndicatifChild {
-            let bar = ProgressBar::new(size).with_style(self.style.clone());
+        fn create_task(&self, name: &str, size: u64) -> impl super::Indicator {
+            self.create_task_with_state(name, size, super::TaskState::Fresh)
+        }
+        fn create_task_with_state(
+            &self,
+            name: &str,
+            size: u64,
+            state: super::TaskState,
+        ) -> impl super::Indicator {
+            // unknown sizes don't show a bar already full
+            let bar = match size {
+                0 => ProgressBar::no_length(),
+                size => ProgressBar::new(size),
+            }
+            .with_style(self.style.clone());
             bar.set_draw_target(ProgressDrawTarget::hidden());
             let bar = self.multiprogress.add(bar);
-            bar.set_message(name.to_string());
-            IndicatifChild { bar }
+            match state {
+                super::TaskState::Fresh => bar.set_message(name.to_string()),
+                super::TaskState::Resumed(position) => {
+                    bar.set_position(position);
+                    bar.set_message(format!("{} (resumed)", name));
+                }
+                super::TaskState::Cached => {
+                    bar.set_position(size);
+                    bar.set_message(format!("{} (cached)", name));
+                }
+            }
+            IndicatifChild {
+                bar,
+                spinner_style: self.spinner_style.clone(),
+            }
         }
     }
     impl Default for Indicatif {
         fn default() -> Self {
             Self {
-                style: ProgressStyle::default_bar(),
+                style: Self::default_style(),
+                spinner_style: Self::default_spinner_style(),
                 multiprogress: MultiProgress::new(),
             }
         }
     }
     impl Indicatif {
+        /// Style of the default bars, its sizes, speeds and ETAs are formatted with
+        /// [crate::format], also available as the keys `dl_bytes`, `dl_total`,
+        /// `dl_speed` and `dl_eta` of custom templates
+        pub fn default_style() -> ProgressStyle {
+            Self::with_format_keys(
+                ProgressStyle::with_template(
+                    "{msg} {wide_bar} {dl_bytes}/{dl_total} {dl_speed} {dl_eta}",
+                )
+                .unwrap_or_else(|_| ProgressStyle::default_bar()),
+            )
+        }
+        /// Style of the files of unknown size, a spinner with the bytes written and the
+        /// speed
+        pub fn default_spinner_style() -> ProgressStyle {
+            Self::with_format_keys(
+                ProgressStyle::with_template("{spinner} {msg} {dl_bytes} {dl_speed}")
+                    .unwrap_or_else(|_| ProgressStyle::default_spinner()),
+            )
+        }
+        /// Adds the keys formatted with [crate::format] to a custom style
+        pub fn with_format_keys(style: ProgressStyle) -> ProgressStyle {
+            style
+                .with_key("dl_bytes", |state: &ProgressState, w: &mut dyn Write| {
+                    let _ = w.write_str(&format_bytes(state.pos()));
+                })
+                .with_key("dl_total", |state: &ProgressState, w: &mut dyn Write| {
+                    let _ = w.write_str(&format_bytes(state.len().unwrap_or(0)));
+                })
+                .with_key("dl_speed", |state: &ProgressState, w: &mut dyn Write| {
+                    let _ = w.write_str(&format_speed(state.per_sec()));
+                })
+                .with_key("dl_eta", |state: &ProgressState, w: &mut dyn Write| {
+                    let _ = w.write_str(&format_eta(state.eta()));
+                })
+        }
         pub fn new(style: ProgressStyle) -> Self {
             Self {
                 style,
+                spinner_style: Self::default_spinner_style(),
                 multiprogress: MultiProgress::new(),
             }
         }
+        /// Sets the style of the files of unknown size
+        pub fn with_spinner_style(mut self, style: ProgressStyle) -> Self {
+            self.spinner_style = style;
+            self
+        }
     }
 
     pub struct IndicatifChild {
         bar: ProgressBar,
+        spinner_style: ProgressStyle,
     }
     impl super::Indicator for IndicatifChild {
         fn effect(&mut self, position: u64) {
@@ -88,11 +357,40 @@ pub mod indicatif {
                     self.bar.set_message(s);
                 }
                 super::IndicateSignal::Success() => {
-                    self.bar.finish_with_message(format!("Done!"));
+                    // files of unknown size end with the bytes written, empty ones too
+                    if self.bar.length().is_none() {
+                        self.bar.set_length(self.bar.position());
+                    }
+                    self.bar.finish_with_message("Done!".to_string());
+                }
+                super::IndicateSignal::Cached() => {
+                    self.bar.finish_with_message("Cached".to_string());
+                }
+                super::IndicateSignal::Skipped(reason) => {
+                    self.bar
+                        .finish_with_message(format!("Skipped -- {}", reason));
+                }
+                super::IndicateSignal::Cancelled(reason) => {
+                    self.bar
+                        .finish_with_message(format!("Cancelled {}", reason));
                 }
                 super::IndicateSignal::Start() => {
                     self.bar.set_draw_target(ProgressDrawTarget::stdout());
                 }
+                super::IndicateSignal::Size(size) => {
+                    self.bar.set_length(size);
+                }
+                super::IndicateSignal::UnknownSize() => {
+                    self.bar.set_style(self.spinner_style.clone());
+                }
+                // the speed and the ETA of the discarded progress don't count
+                super::IndicateSignal::Reset() => {
+                    self.bar.reset();
+                }
+                super::IndicateSignal::Rewind(position) => {
+                    self.bar.set_position(position);
+                    self.bar.reset_eta();
+                }
             }
         }
     }
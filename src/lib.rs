@@ -1,36 +1,362 @@
 use std::{
-    fs::{self, File},
+    borrow::Cow,
+    cell::Cell,
+    collections::{BTreeMap, HashMap},
     future::Future,
-    io::{Read, Write},
     path::Path,
     pin::Pin,
+    rc::Rc,
     sync::Arc,
+    time::{Duration, Instant, SystemTime},
 };
 
-use indicator::{IndicateSignal, Indicator, IndicatorFactory};
-use sha1::{Digest, Sha1};
-use sha2::{Sha224, Sha256, Sha384, Sha512};
-use smol::{Executor, io::AsyncReadExt, lock::Semaphore};
-use surf::Client;
+pub use error::DwldError;
+use futures::{StreamExt, stream::FuturesUnordered};
+pub use handle::{CancelReason, DLHandle, ErrorPolicy};
+pub use hash::{DLHashType, DLHashes, DLSampling, VerifyStrategy};
+use indicator::{IndicateSignal, Indicator, IndicatorFactory, TaskState};
+pub use plan::DownloadPlan;
+pub use redirection_middleware::{RedirectPolicy, UrlRewriter};
+pub use report::{DLStatus, DownloadEvent, DownloadReport};
+pub use schedule::DLSchedule;
+pub use sink::DLSink;
+use smol::{io::AsyncReadExt, lock::Semaphore};
+pub use storage::{OverwritePolicy, PartialPolicy};
+pub use surf::{Body, Url};
+use surf::{Client, StatusCode, http::other::RetryAfter};
+use timeout::DLTimeoutKind;
+pub use timeout::DLTimeouts;
+pub use transform::{DLTransformer, HashStage};
+pub use validation::DuplicatePolicy;
+pub use verify::{Verifier, VerifierMode};
+// first, its macros are used by the other modules
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "azure")]
+pub mod azure;
+#[cfg(feature = "capi")]
+pub mod capi;
 #[cfg(feature = "cas")]
 pub mod cas;
+pub mod checkpoint;
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+mod cloud;
+mod conditional;
 pub mod indicator;
+pub mod mirror;
+pub mod multipart;
+pub mod network;
 
+mod budget;
+mod data_url;
 #[cfg(feature = "decompress")]
 pub mod decompress;
+mod dns_prefetch;
+pub mod dry_run;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod error;
+pub mod explain;
+mod file_url;
+pub mod format;
+#[cfg(feature = "ftp")]
+mod ftp;
+#[cfg(feature = "gcs")]
+pub mod gcs;
+pub mod handle;
+pub mod hash;
+#[cfg(feature = "decompress")]
+mod pipeline;
+pub mod plan;
+#[cfg(feature = "python")]
+mod python;
+mod quarantine;
+#[cfg(feature = "queue")]
+pub mod queue;
+pub mod redirect_cache;
 mod redirection_middleware;
+pub mod report;
+pub mod resolver;
+pub mod retry;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod schedule;
+mod segments;
+mod server_digest;
+pub mod service;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+#[cfg(feature = "signal")]
+mod signal;
+pub mod sink;
+pub mod storage;
+mod throttle;
+pub mod timeout;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod transform;
+pub mod url_cache;
+mod validation;
+pub mod verify;
+#[cfg(not(feature = "no_static_client"))]
+mod warm_up;
 
 fn _default_callback() -> Arc<dyn Fn(String) + Send + Sync> {
     Arc::new(|_| {})
 }
+fn _default_checkpoint_callback() -> Arc<dyn Fn(String, checkpoint::DLCheckpoint) + Send + Sync> {
+    Arc::new(|_, _| {})
+}
+fn _default_backend() -> Arc<dyn storage::StorageBackend> {
+    Arc::new(storage::LocalBackend)
+}
+
+/// State shared by the downloads of a session
+struct Session {
+    semaphore: Semaphore,
+    /// Semaphores of the concurrency groups with a limit
+    groups: HashMap<String, Semaphore>,
+    bandwidth: Option<throttle::Throttle>,
+    handles: Option<budget::HandleBudget>,
+    /// Final URLs of the redirections followed by the session
+    #[cfg(feature = "no_static_client")]
+    redirects: redirect_cache::DLRedirectCache,
+    #[cfg(not(feature = "no_static_client"))]
+    client: Client,
+    /// Connector of the FTPS connections, with the TLS settings of the downloader
+    #[cfg(feature = "ftp")]
+    ftps: async_tls::TlsConnector,
+    /// Parent directories created by the downloads of the session
+    dirs: Arc<storage::CreatedDirs>,
+}
+
+/// Outcome of a file with its position in the session and its counters
+type Finished = (
+    usize,
+    Result<DLStatus, DwldError>,
+    Rc<report::TransferStats>,
+);
+
+/// Runs the task of a file, keeping its position in the session
+async fn indexed(
+    index: usize,
+    stats: Rc<report::TransferStats>,
+    task: DownloadFuture<'_>,
+) -> Finished {
+    let result = task.await;
+    stats.finish();
+    (index, result, stats)
+}
+
+/// Bytes of a rejected body read before dropping it
+const MAX_DISCARDED_BODY: u64 = 1024 * 1024;
+
+/// Rejects the response with the error. Its body is read first, the pooled connection
+/// can't be used again while it has unread data
+pub(crate) async fn reject(
+    response: &mut surf::Response,
+    timeouts: DLTimeouts,
+    error: DwldError,
+) -> DwldError {
+    let body = response.take_body().take(MAX_DISCARDED_BODY);
+    let discard = smol::io::copy(body, smol::io::sink());
+    let _ = timeout::timeout(timeouts.read, DLTimeoutKind::Read, discard).await;
+    error
+}
+
+/// Bytes of the bodies kept in the errors, see [DwldError::body]
+const BODY_PEEK: u64 = 512;
+
+/// Error of a response with an error status, with the first bytes of its body. The rest
+/// of the body is discarded like in [reject]
+pub(crate) async fn status_error(response: &mut surf::Response, timeouts: DLTimeouts) -> DwldError {
+    let status = response.status();
+    let retry_after = match status {
+        StatusCode::TooManyRequests | StatusCode::ServiceUnavailable => {
+            RetryAfter::from_headers(&*response)
+                .ok()
+                .flatten()
+                // the header has a precision of seconds, the dates already passed don't wait
+                .map(|retry| retry.duration_since(SystemTime::now()).unwrap_or_default())
+                .map(|wait| Duration::from_secs(wait.as_secs()))
+        }
+        _ => None,
+    };
+    let mut body = response.take_body().take(MAX_DISCARDED_BODY);
+    let mut peek = Vec::new();
+    let read = async {
+        (&mut body).take(BODY_PEEK).read_to_end(&mut peek).await?;
+        smol::io::copy(&mut body, smol::io::sink()).await
+    };
+    let _ = timeout::timeout(timeouts.read, DLTimeoutKind::Read, read).await;
+    DwldError::Status {
+        status,
+        body: peek,
+        retry_after,
+    }
+}
+
+/// First bytes of a downloaded file, see [DwldError::body]
+pub(crate) fn peek_file(path: &Path) -> Vec<u8> {
+    use std::io::Read;
+    let mut peek = Vec::new();
+    let file = std::fs::File::open(path);
+    let _ = file.and_then(|file| file.take(BODY_PEEK).read_to_end(&mut peek));
+    peek
+}
+
+/// Adds the custom headers of a file to one of its requests
+pub(crate) fn add_headers(
+    request: surf::RequestBuilder,
+    headers: &[(String, String)],
+) -> surf::RequestBuilder {
+    headers.iter().fold(request, |request, (name, value)| {
+        request.header(name.as_str(), value.as_str())
+    })
+}
+
+/// `Authorization` header of a bearer token
+fn bearer_auth(token: &str) -> surf::http::auth::Authorization {
+    let scheme = surf::http::auth::AuthenticationScheme::Bearer;
+    surf::http::auth::Authorization::new(scheme, token.to_string())
+}
+
+/// State of the session shared with the downloads
+#[derive(Default)]
+struct DownloadContext<'a> {
+    handle: Option<&'a DLHandle>,
+    timeouts: DLTimeouts,
+    overwrite_policy: OverwritePolicy,
+    /// Bandwidth shared by all the downloads
+    bandwidth: Option<&'a throttle::Throttle>,
+    /// Counters of the file
+    stats: Option<&'a report::TransferStats>,
+    /// Statistics of the mirrors of the session
+    mirrors: Option<&'a mirror::DLMirrorBook>,
+    /// Retry policy of the files without their own
+    retry_policy: Option<&'a dyn retry::RetryPolicy>,
+    /// Connector of the FTPS connections, the public roots are trusted without it
+    #[cfg(feature = "ftp")]
+    ftps: Option<&'a async_tls::TlsConnector>,
+    /// Parent directories created by the session, each download creates its own without it
+    dirs: Option<&'a Arc<storage::CreatedDirs>>,
+}
+
+/// Policy deciding if a file can be downloaded now, with the bytes to download
+pub type MeteredPolicy = Arc<dyn Fn(&DLFile, u64) -> bool + Send + Sync>;
+
+/// Body of a file produced when its download fails
+pub type DLFallback = Arc<
+    dyn Fn(&DLFile) -> Pin<Box<dyn Future<Output = std::io::Result<surf::Body>>>> + Send + Sync,
+>;
+
+/// Result of a request
+enum Fetched {
+    /// The file was served without downloading it
+    Served(DLStatus),
+    /// Body to write, with the offset where it starts, the caching rules, if the server
+    /// compressed it with gzip and the digests announced by the server
+    Body(
+        Box<surf::Body>,
+        u64,
+        Option<url_cache::CachePolicy>,
+        bool,
+        DLHashes,
+    ),
+    /// The file is downloaded in segments, with its size, the digests announced by the
+    /// server and the caching rules
+    Segments(u64, DLHashes, Option<url_cache::CachePolicy>),
+}
+
+type DownloadFuture<'a> = Pin<Box<dyn Future<Output = Result<DLStatus, DwldError>> + 'a>>;
 
 /// Struct in which all the files to be downloaded are set up
 pub struct Downloader<T: IndicatorFactory> {
     pub files: Vec<DLFile>,
     pub max_concurrent_downloads: usize,
     pub max_redirections: usize,
+    /// What to do when several files have the same destination
+    pub duplicate_policy: DuplicatePolicy,
+    /// Hook rewriting the URL of every request
+    pub url_rewriter: Option<UrlRewriter>,
+    /// Apply the URL rewriter also to the redirect targets
+    pub rewrite_redirects: bool,
+    /// Which redirections are followed
+    pub redirect_policy: RedirectPolicy,
+    /// When the files of the batch can start
+    pub schedule: Option<DLSchedule>,
+    /// Timeouts of the files without their own
+    pub timeouts: DLTimeouts,
+    /// Connectivity checked while downloading, pausing the session when offline
+    pub network_monitor: Option<Arc<dyn network::NetworkMonitor>>,
+    /// Interval between the connectivity checks
+    pub network_poll_interval: Duration,
+    /// Consulted before starting every file, the refused ones are skipped
+    pub metered_policy: Option<MeteredPolicy>,
+    /// What happens when the destination of a file already exists
+    pub overwrite_policy: OverwritePolicy,
+    /// Bytes per second shared by all the downloads, unlimited if unset
+    pub max_bandwidth: Option<u64>,
+    /// File handles the downloads keep open at once, only bounded by the concurrency if
+    /// unset
+    pub max_open_files: Option<usize>,
+    /// Directory of the partial files and the decompression staging of the files without
+    /// their own
+    pub temp_dir: Option<String>,
+    /// Directory where the downloads failing their hashes are moved, for the files without
+    /// their own
+    pub quarantine_dir: Option<String>,
+    /// Quick check of the destinations of the files without their own
+    pub verify_sampling: Option<DLSampling>,
+    /// Decides if the URLs of the files without their own policy are tried again
+    pub retry_policy: Option<Arc<dyn retry::RetryPolicy>>,
+    /// Connections opened to every host of the batch before downloading
+    pub warm_up: Option<usize>,
+    /// Lookups at once of the hosts of the upcoming files, while the first files download
+    pub dns_prefetch: Option<usize>,
+    /// Headers sent in every request of the session, the headers of the files override them
+    pub default_headers: Vec<(String, String)>,
+    /// Concurrent downloads of every concurrency group, under the global limit
+    pub group_limits: BTreeMap<String, usize>,
+    /// Time after which the session is cancelled, since its start
+    pub deadline: Option<Duration>,
+    /// Cancels the session with [CancelReason::Signal] on Ctrl-C or `SIGTERM`
+    #[cfg(feature = "signal")]
+    pub cancel_on_signal: bool,
+    /// What happens to the other files when one fails
+    pub error_policy: ErrorPolicy,
+    /// TLS settings of the connections, e.g. private root certificates
+    #[cfg(feature = "tls")]
+    pub tls_config: Option<tls::DLTlsConfig>,
+    /// Accepts invalid server certificates. INSECURE, see
+    /// [Downloader::danger_accept_invalid_certs]
+    #[cfg(feature = "tls")]
+    pub accept_invalid_certs: bool,
+    /// Credentials, region and endpoint of the `s3://` URLs, from the environment and the
+    /// AWS profile if unset
+    #[cfg(feature = "s3")]
+    pub s3_config: Option<s3::DLS3Config>,
+    /// Credentials and endpoint of the `gs://` URLs, the Application Default Credentials if
+    /// unset
+    #[cfg(feature = "gcs")]
+    pub gcs_config: Option<gcs::DLGcsConfig>,
+    /// Credentials and endpoint of the Azure Blob URLs, from the environment if unset
+    #[cfg(feature = "azure")]
+    pub azure_config: Option<azure::DLAzureConfig>,
+    /// Completes the size and the hashes of the files missing them before downloading
+    pub resolver: Option<resolver::DLResolver>,
     indicator_factory: T,
+    handle: DLHandle,
+    mirrors: mirror::DLMirrorBook,
+    redirects: Option<redirect_cache::DLRedirectCache>,
+    /// Client of the sessions, created by every session if unset
+    #[cfg(not(feature = "no_static_client"))]
+    client: Option<Client>,
+    #[cfg(feature = "queue")]
+    queue: Option<queue::DLQueue>,
 }
+#[derive(Clone)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 /// Struct in which they set the data in a file
@@ -41,6 +367,10 @@ pub struct DLFile {
     pub url: String,
     /// Hashes of the file
     pub hashes: DLHashes,
+    /// How the file is read when verifying the hashes, only when they can't be computed
+    /// while downloading, e.g. the segmented downloads
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verify_strategy: VerifyStrategy,
     /// Path to save the file
     pub path: String,
     /// decompression configuration
@@ -53,276 +383,1847 @@ pub struct DLFile {
     /// Unsing CAS
     #[cfg(feature = "cas")]
     pub cas: Option<cas::DLStorage>,
+    /// Encryption at rest of the downloaded file
+    #[cfg(feature = "encryption")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub encryption: Option<encryption::DLEncryptionConfig>,
+    /// Continue partial downloads with range requests
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub resume: bool,
+    /// What happens to the written data when the download fails
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub partial_policy: storage::PartialPolicy,
+    /// Backup read-only destinations and replace them instead of failing
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub replace_readonly: bool,
+    /// Backend where the file is written
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "serde", serde(default = "_default_backend"))]
+    pub backend: Arc<dyn storage::StorageBackend>,
+    /// Transformation of the body applied before writing it
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub transform: Option<transform::DLTransformFactory>,
+    /// Data checked by the hashes when the body is transformed
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub hash_stage: HashStage,
+    /// Authentication of the `sftp://` URLs
+    #[cfg(feature = "sftp")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub sftp: Option<sftp::DLSftpConfig>,
+    /// Custom check of the downloaded file
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub verifier: Option<Arc<dyn verify::Verifier>>,
+    /// How the verifier is combined with the hashes
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verifier_mode: VerifierMode,
+    /// Cache of the response by URL
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub url_cache: Option<url_cache::DLUrlCache>,
+    /// When the file can start
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub schedule: Option<DLSchedule>,
+    /// Timeouts of the download, the unset ones are taken from the downloader
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timeouts: DLTimeouts,
+    /// Don't download the file if the destination already matches the hashes
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub skip_if_valid: bool,
+    /// The file was verified elsewhere, e.g. copied from install media: its hashes aren't
+    /// checked and an existing destination of the right size is kept
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub preverified: bool,
+    /// The existing destination is refreshed with a conditional request, keeping it when the
+    /// server answers `304 Not Modified`. The validators are stored in `<path>.validators`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub conditional: bool,
+    /// The files without hashes are checked with the digests announced by the server, in
+    /// the `Digest`, `Repr-Digest`, `Content-Digest` and `x-goog-hash` headers or in an
+    /// `ETag` with the MD5 of the file. An existing destination matching them is kept
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub server_digests: bool,
+    /// The downloaded file takes the modification time of the `Last-Modified` header, for
+    /// the sync tools comparing it. The CAS links set the time of their blob
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub server_mtime: bool,
+    /// Builds the body of the file when the download fails
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub fallback: Option<DLFallback>,
+    /// What happens when the destination already exists, the downloader decides if unset
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub overwrite_policy: Option<OverwritePolicy>,
+    /// Connections used to download big files from servers accepting ranges
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub segments: usize,
+    /// Don't reserve the blocks of the file before writing it, the segments are written
+    /// in a sparse file
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sparse: bool,
+    /// Bytes per second of the file, unlimited if unset
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub speed_limit: Option<u64>,
+    /// Parts joined in order to build the file, downloaded instead of the URL
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub parts: Vec<multipart::DLPart>,
+    /// Files downloaded with this one, like signatures, failing it if they fail
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub companions: Vec<DLFile>,
+    /// Files with higher priority start first, the same priority keeps the order
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: u8,
+    /// Concurrency group of the file, limited by [Downloader::with_group_limit]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub group: Option<String>,
+    /// Points of the progress firing the checkpoint callback
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub checkpoints: Vec<checkpoint::DLCheckpoint>,
+    /// Write the file in `<path>.part` and rename it when it's verified
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub atomic: bool,
+    /// URLs tried in order when the URL fails because of the server
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub mirrors: Vec<String>,
+    /// Directory of the partial files and the decompression staging, next to the file if
+    /// unset. Only used with local backends
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub temp_dir: Option<String>,
+    /// Directory where the downloads failing their hashes are moved instead of deleting
+    /// them, the downloader decides if unset
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub quarantine_dir: Option<String>,
+    /// Quick check of the destination kept by [DLFile::with_skip_if_valid], it's hashed
+    /// if unset and the downloader doesn't sample
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verify_sampling: Option<DLSampling>,
+    /// Headers sent in every request of the file, e.g. API keys or CDN tokens
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub headers: Vec<(String, String)>,
+    /// Identifiers of the application, e.g. `mod_id`, carried to the events, the reports
+    /// and the name of the indicator
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+    /// Decides if the URLs of the file are tried again when they fail, the downloader
+    /// decides if unset
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub retry_policy: Option<Arc<dyn retry::RetryPolicy>>,
+    /// Event on crossing a checkpoint, with the path of the file
+    #[cfg_attr(feature = "serde", serde(skip))]
+    #[cfg_attr(feature = "serde", serde(default = "_default_checkpoint_callback"))]
+    pub on_checkpoint: Arc<dyn Fn(String, checkpoint::DLCheckpoint) + Send + Sync>,
 }
-#[derive(Debug, Clone)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-pub struct DLHashes {
-    pub hashes: Vec<(DLHashType, String)>,
-}
-impl DLHashes {
-    pub fn new() -> Self {
-        Self { hashes: Vec::new() }
-    }
-    pub fn add_hash(mut self, hash_type: DLHashType, hash_value: String) -> Self {
-        self.hashes.push((hash_type, hash_value));
-        self
-    }
-    pub fn sha1(mut self, hash: &str) -> Self {
-        self.hashes.push((DLHashType::SHA1, hash.to_string()));
-        self
-    }
-    pub fn sha256(mut self, hash: &str) -> Self {
-        self.hashes.push((DLHashType::SHA256, hash.to_string()));
-        self
-    }
-    pub fn sha384(mut self, hash: &str) -> Self {
-        self.hashes.push((DLHashType::SHA384, hash.to_string()));
-        self
-    }
-    pub fn sha512(mut self, hash: &str) -> Self {
-        self.hashes.push((DLHashType::SHA512, hash.to_string()));
-        self
-    }
-    pub fn sha224(mut self, hash: &str) -> Self {
-        self.hashes.push((DLHashType::SHA224, hash.to_string()));
-        self
-    }
-    pub fn verify_data(&self, data: &[u8]) -> bool {
-        self.hashes
-            .iter()
-            .find(|hashed| {
-                let (typ, hash) = hashed;
-                typ.verify_data(data, hash)
-            })
-            .is_some()
-    }
-    pub fn verify_str(&self, data: &str) -> bool {
-        self.verify_data(data.as_bytes())
-    }
-    pub fn verify_file(&self, path: &str) -> bool {
-        let data = std::fs::read(path).unwrap();
-        self.verify_data(&data)
+impl DLFile {
+    /// Asynchronous download of the file
+    pub async fn download(
+        &self,
+        indicator: impl Indicator,
+        client: Client,
+    ) -> Result<DLStatus, DwldError> {
+        self.download_with(indicator, client, &DownloadContext::default())
+            .await
     }
-}
-
-#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
-#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
-#[derive(Debug, Clone)]
-pub enum DLHashType {
-    SHA1,
-    SHA256,
-    SHA224,
-    SHA384,
-    SHA512,
-}
-
-impl DLHashType {
-    /// Función genérica que crea el hasher, actualiza con los datos y devuelve el hash en hexadecimal.
-    fn compute_hash<D: Digest + Default>(data: &[u8]) -> String {
-        let mut hasher = D::default();
-        hasher.update(data);
-        let result = hasher.finalize();
-        hex::encode(result)
+    /// Download within a session, aborted when the session is cancelled
+    async fn download_with(
+        &self,
+        indicator: impl Indicator,
+        client: Client,
+        ctx: &DownloadContext<'_>,
+    ) -> Result<DLStatus, DwldError> {
+        let download = self.download_traced(indicator, client, ctx);
+        instrumented!(download, "download", path = %self.path, url = %self.url, metadata = ?self.metadata).await
     }
-
-    /// Calcula el hash usando el algoritmo seleccionado.
-    pub fn compute(&self, data: &[u8]) -> String {
-        match self {
-            DLHashType::SHA1 => Self::compute_hash::<Sha1>(data),
-            DLHashType::SHA256 => Self::compute_hash::<Sha256>(data),
-            DLHashType::SHA224 => Self::compute_hash::<Sha224>(data),
-            DLHashType::SHA384 => Self::compute_hash::<Sha384>(data),
-            DLHashType::SHA512 => Self::compute_hash::<Sha512>(data),
+    /// Download of the file with its mirrors, fallback and companions
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    async fn download_traced(
+        &self,
+        indicator: impl Indicator,
+        client: Client,
+        ctx: &DownloadContext<'_>,
+    ) -> Result<DLStatus, DwldError> {
+        let mut indicator = checkpoint::Checkpoints::new(indicator, self);
+        let mut result = self.mirror_attempt(&mut indicator, &client, ctx, 0).await;
+        // the mirrors are tried in order while the servers fail
+        for (position, url) in (1..).zip(&self.mirrors) {
+            match &result {
+                Err(e) if mirror::is_mirror_error(e) => {}
+                _ => break,
+            }
+            indicator.signal(IndicateSignal::State("Mirror...".to_string()));
+            let mirror = DLFile {
+                url: url.clone(),
+                ..self.clone()
+            };
+            result = mirror
+                .mirror_attempt(&mut indicator, &client, ctx, position)
+                .await;
         }
+        // the fallback builds the file when the download fails
+        if let Err(e) = result {
+            result = match e {
+                DwldError::Cancelled(_) => Err(e),
+                _ if self.fallback.is_some() => {
+                    indicator.signal(IndicateSignal::State("Fallback...".to_string()));
+                    self.attempt(&mut indicator, client.clone(), ctx, true)
+                        .await
+                        .map_err(|fallback| DwldError::Fallback(Box::new(e), Box::new(fallback)))
+                }
+                _ => Err(e),
+            };
+        }
+        // the companions are downloaded with the file, failing it if they fail
+        if result.is_ok() && !self.companions.is_empty() {
+            let companions = self.companions.iter().map(|companion| {
+                let client = client.clone();
+                let companion: DownloadFuture = Box::pin(async move {
+                    companion
+                        .download_with(indicator::SilentChild, client, ctx)
+                        .await
+                        .map_err(|e| DwldError::Companion {
+                            path: companion.path.clone(),
+                            source: Box::new(e),
+                        })
+                });
+                companion
+            });
+            if let Err(e) = futures::future::try_join_all(companions).await {
+                result = Err(e);
+            }
+        }
+        match &result {
+            Ok(status) => trace_event!(INFO, status = ?status, "finished"),
+            Err(DwldError::Cancelled(reason)) => {
+                trace_event!(INFO, reason = %reason, "cancelled");
+                indicator.signal(IndicateSignal::Cancelled(reason.clone()));
+            }
+            Err(e) => {
+                trace_event!(WARN, code = e.code(), error = %e, "failed");
+                indicator.signal(IndicateSignal::Fail(e.to_string()));
+            }
+        }
+        result
     }
-    pub fn verify_str(&self, data: &str, hash: &str) -> bool {
-        self.compute(data.to_string().as_bytes()) == hash
-    }
-    pub fn verify_data(&self, data: &[u8], hash: &str) -> bool {
-        self.compute(data) == hash
-    }
-    pub fn verify_file(&self, path: &Path, hash: &str) -> bool {
-        let mut buffer = Vec::new();
-        File::open(path)
-            .expect("Failed to open file")
-            .read_to_end(&mut buffer)
-            .expect("Failed to read file");
-        self.verify_data(buffer.as_slice(), hash)
+    /// Downloads the file from its URL while the retry policy allows it, accounting every
+    /// attempt in the statistics of the mirrors. The mirror is the position of the URL, 0
+    /// for the URL of the file
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    async fn mirror_attempt(
+        &self,
+        indicator: &mut impl Indicator,
+        client: &Client,
+        ctx: &DownloadContext<'_>,
+        mirror: u32,
+    ) -> Result<DLStatus, DwldError> {
+        let policy = self.retry_policy.as_deref().or(ctx.retry_policy);
+        let transferred = || ctx.stats.map_or(0, |stats| stats.bytes.get());
+        let mut attempt = 1;
+        loop {
+            let (started, bytes) = (Instant::now(), transferred());
+            let run = self.attempt(indicator, client.clone(), ctx, false);
+            let result = instrumented!(run, "attempt", url = %self.url, mirror, attempt).await;
+            let bytes = transferred() - bytes;
+            trace_event!(
+                DEBUG,
+                url = %self.url,
+                attempt,
+                bytes,
+                ok = result.is_ok(),
+                "attempt finished"
+            );
+            if let Some(mirrors) = ctx.mirrors {
+                mirrors.record(&self.url, &result, bytes, started.elapsed());
+            }
+            let delay = match (&result, policy) {
+                (Err(DwldError::Cancelled(_)), _) => return result,
+                (Err(e), Some(policy)) => match policy.retry(e, attempt) {
+                    retry::RetryDecision::Retry(delay) => delay,
+                    retry::RetryDecision::GiveUp => return result,
+                },
+                _ => return result,
+            };
+            trace_event!(INFO, url = %self.url, attempt, delay = ?delay, "retrying");
+            let state = match result.as_ref().err().and_then(DwldError::retry_after) {
+                Some(_) => format!("Waiting {:?} as asked by the server...", delay),
+                None => "Retrying...".to_string(),
+            };
+            indicator.signal(IndicateSignal::State(state));
+            if let Some(stats) = ctx.stats {
+                stats.retries.set(stats.retries.get() + 1);
+            }
+            // the cancellation stops waiting for the retry
+            let wait = smol::Timer::after(delay);
+            if let Some(handle) = ctx.handle {
+                let cancelled = handle.cancelled();
+                futures::pin_mut!(cancelled);
+                if let futures::future::Either::Right(_) =
+                    futures::future::select(wait, cancelled).await
+                {
+                    return Err(handle.cancelled_error());
+                }
+            } else {
+                wait.await;
+            }
+            attempt += 1;
+        }
     }
-}
-
-fn symlink_exists(path: &Path) -> bool {
-    match fs::symlink_metadata(path) {
-        Ok(metadata) => metadata.file_type().is_symlink(),
-        Err(_) => false,
+    /// Runs the download once, handling the data written if it fails
+    async fn attempt(
+        &self,
+        indicator: &mut impl Indicator,
+        client: Client,
+        ctx: &DownloadContext<'_>,
+        fallback: bool,
+    ) -> Result<DLStatus, DwldError> {
+        let timeouts = self.timeouts.or(ctx.timeouts);
+        let mut partial = None;
+        let result = {
+            // the future of the body is big, it's kept in the heap so the debug builds
+            // don't overflow the stack
+            let run = async {
+                timeout::timeout(
+                    timeouts.total,
+                    DLTimeoutKind::Total,
+                    Box::pin(self.run(indicator, client, &mut partial, ctx, timeouts, fallback)),
+                )
+                .await?
+            };
+            match ctx.handle {
+                Some(handle) => {
+                    let cancelled = handle.cancelled();
+                    futures::pin_mut!(run, cancelled);
+                    match futures::future::select(run, cancelled).await {
+                        futures::future::Either::Left((result, _)) => result,
+                        futures::future::Either::Right(_) => Err(handle.cancelled_error()),
+                    }
+                }
+                None => run.await,
+            }
+        };
+        // handle the data written before the failure
+        if let (Err(_), Some(partial)) = (&result, partial) {
+            let _ = partial
+                .apply(self.partial_policy, self.backend.as_ref())
+                .await;
+        }
+        result
     }
-}
-
-impl DLFile {
-    /// Asynchronous download of the file
-    pub async fn download(
+    async fn run(
         &self,
-        mut indicator: impl Indicator,
+        indicator: &mut impl Indicator,
         client: Client,
-    ) -> Result<(), String> {
+        partial: &mut Option<storage::PartialTarget>,
+        ctx: &DownloadContext<'_>,
+        timeouts: DLTimeouts,
+        fallback: bool,
+    ) -> Result<DLStatus, DwldError> {
         // get the values of the file
+        let started = SystemTime::now();
         let url = self.url.clone();
         let path = self.path.clone();
         let hashes = self.hashes.clone();
-        let size = self.size;
         let path_clone = self.path.clone(); // Para el mensaje de progreso
+        // the file is written where it's staged until it's verified
+        let target = self.staging_path();
 
-        // make the request with SURF
-        let mut response = client.get(&url).await.expect("Failed to get response");
-
-        // if the response is successful, write the file
-        let path_hash: String = if response.status().is_success() {
-            // create the parent directory if it doesn't exist
-            let ppath = Path::new(&path);
-            if let Some(parent) = ppath.parent() {
-                fs::create_dir_all(parent).unwrap();
-            }
-
-            // create the file
-            let (mut file, path_hash) = if self.cas.is_some() && hashes.hashes.len() > 0 {
-                let hash = hashes.hashes.get(0).unwrap().clone().1;
-                let storage = self.cas.as_ref().unwrap();
-                if storage.find(hash.as_str()).is_some() {
-                    if !symlink_exists(Path::new(path.clone().as_str())) {
-                        storage.symlink(hash.as_str(), path.clone().as_str());
+        // the archive of a previous session may only miss its extraction
+        #[cfg(feature = "decompress")]
+        if let Some(status) = self.resume_extraction(indicator)? {
+            return Ok(status);
+        }
+
+        // the destination may already have the right content
+        if let Some(reason) = self.valid_destination().await {
+            indicator.effect(self.size);
+            indicator.signal(IndicateSignal::Skipped(reason.to_string()));
+            return Ok(DLStatus::Skipped(reason.to_string()));
+        }
+
+        // the existing destination may match the digests of the server
+        if !fallback
+            && self.trusts_server_digests()
+            && self.matches_server_digests(&client, timeouts).await
+        {
+            let reason = "Destination matches the server digests".to_string();
+            indicator.effect(self.size);
+            indicator.signal(IndicateSignal::Skipped(reason.clone()));
+            return Ok(DLStatus::Skipped(reason));
+        }
+
+        // protect the existing destination, partial data of the file is continued
+        if !fallback && self.resume_offset().is_none() && self.backend.exists(&path).await {
+            match self.overwrite_policy.unwrap_or(ctx.overwrite_policy) {
+                OverwritePolicy::Overwrite => {}
+                OverwritePolicy::Skip => {
+                    let reason = "Destination already exists".to_string();
+                    indicator.signal(IndicateSignal::Skipped(reason.clone()));
+                    return Ok(DLStatus::Skipped(reason));
+                }
+                OverwritePolicy::Error => {
+                    return Err(storage::DLDestinationError::Exists(path.into()).into());
+                }
+                OverwritePolicy::RenameWithSuffix => {
+                    storage::rename_with_suffix(self.backend.as_ref(), &path)
+                        .await
+                        .map_err(|e| storage::DLDestinationError::from_io(&path, e))?;
+                }
+            }
+        }
+
+        // files already in the CAS storage don't need any request
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some((_, hash))) = (&self.cas, hashes.hashes.first()) {
+            if storage.find(hash.as_str()).is_some() {
+                self.prepare_destination(&path, ctx).await?;
+                let (storage, hash, path) = (storage.clone(), hash.clone(), path.clone());
+                smol::unblock(move || storage.materialize(&hash, &path))
+                    .await
+                    .map_err(DwldError::Cas)?;
+                indicator.effect(self.size);
+                indicator.signal(IndicateSignal::Cached());
+                return Ok(DLStatus::Cached);
+            }
+        }
+
+        // obtain the body, from the network or from the fallback
+        // the speed limit of the file, on top of the bandwidth of the session
+        let speed_limit = self
+            .speed_limit
+            .filter(|rate| *rate > 0)
+            .map(throttle::Throttle::new);
+        let part_paths = self.part_paths();
+        let mut transport_gzip = false;
+        let mut server_hashes = DLHashes::new();
+        // time until the headers of the response, the HEAD request of the segmented downloads
+        let mut ttfb = None;
+        let (mut body, offset, cache_policy, ranges_size) =
+            match self.fallback.as_ref().filter(|_| fallback) {
+                Some(fallback) => {
+                    let body = fallback(self).await.map_err(|e| DwldError::io(&path, e))?;
+                    (body, 0, None, None)
+                }
+                None if !self.parts.is_empty() => {
+                    let body = multipart::Multipart {
+                        client: &client,
+                        headers: &self.headers,
+                        timeouts,
+                        handle: ctx.handle,
+                        bandwidth: ctx.bandwidth,
+                        speed_limit: speed_limit.as_ref(),
+                        verify_strategy: self.verify_strategy,
+                        connections: self.segments,
+                        stats: ctx.stats,
+                    }
+                    .download(&self.parts, &part_paths, indicator)
+                    .await?;
+                    indicator.signal(IndicateSignal::State("Joining parts".to_string()));
+                    (body, 0, None, None)
+                }
+                None => {
+                    let requested = Instant::now();
+                    let fetched = self.fetch(indicator, client.clone(), ctx, timeouts).await?;
+                    ttfb = (!self.has_local_source()).then(|| requested.elapsed());
+                    match fetched {
+                        Fetched::Served(status) => return Ok(status),
+                        Fetched::Body(body, offset, cache_policy, gzip, digests) => {
+                            transport_gzip = gzip;
+                            server_hashes = digests;
+                            (*body, offset, cache_policy, None)
+                        }
+                        Fetched::Segments(size, digests, cache_policy) => {
+                            server_hashes = digests;
+                            (surf::Body::empty(), 0, cache_policy, Some(size))
+                        }
                     }
-                    indicator.signal(IndicateSignal::Success());
-                    indicator.effect(size);
-                    return Ok(());
                 }
-                (
-                    storage.new_file(hash.as_str(), path.clone().as_str()),
+            };
+        // the files without size take it from the response
+        let size = ranges_size.or(body.len().map(|len| offset + len as u64));
+        match (self.size, size) {
+            (0, Some(size)) => indicator.signal(IndicateSignal::Size(size)),
+            (0, None) => indicator.signal(IndicateSignal::UnknownSize()),
+            _ => {}
+        }
+
+        // check the local destination before writing anything
+        if self.is_local() {
+            self.prepare_destination(&path, ctx).await?;
+        }
+
+        // the files are opened once the body starts, the downloads waiting for a slow
+        // server don't hold their file handles. The segments open their own files
+        let transferring = Instant::now();
+        // buffer of bytes in a chunk, DEFAULT = 8KB
+        let mut buffer = [0; 8192];
+        let mut first = None;
+        if ranges_size.is_none() {
+            let read = AsyncReadExt::read(&mut body, &mut buffer);
+            match timeout::timeout(timeouts.read, DLTimeoutKind::Read, read).await? {
+                Ok(n) => first = Some(n),
+                Err(e) => return Err(DwldError::Network(e.into())),
+            }
+        }
+
+        // create the file, CAS files are always stored in the local filesystem
+        #[cfg(feature = "cas")]
+        let cas_file = match (&self.cas, hashes.hashes.first()) {
+            (Some(storage), Some((_, hash))) => {
+                let (blob_storage, blob_hash, link) = (storage.clone(), hash.clone(), path.clone());
+                let file = smol::unblock(move || match offset > 0 {
+                    true => blob_storage.resume_file(&blob_hash, &link),
+                    false => blob_storage.new_file(&blob_hash, &link),
+                })
+                .await;
+                Some((
+                    file.map_err(DwldError::Cas)?,
                     storage.path(hash.as_str()),
-                )
-            } else {
-                (File::create(path.clone()).unwrap(), path.clone())
+                    hash.clone(),
+                ))
+            }
+            _ => None,
+        };
+        #[cfg(not(feature = "cas"))]
+        let cas_file: Option<(std::fs::File, String, String)> = None;
+
+        let (mut file, local_hash_path, cas_hash): (Box<dyn storage::StorageWriter>, _, _) =
+            match cas_file {
+                Some((file, blob, hash)) => {
+                    *partial = Some(storage::PartialTarget::Cas {
+                        blob: blob.clone(),
+                        link: path.clone(),
+                    });
+                    (Box::new(storage::LocalWriter(file)), Some(blob), Some(hash))
+                }
+                None => {
+                    let file = if offset > 0 {
+                        self.backend.append(&target).await
+                    } else {
+                        self.backend.create(&target).await
+                    }
+                    .map_err(|e| storage::DLDestinationError::from_io(&target, e))?;
+                    *partial = Some(storage::PartialTarget::Backend(target.clone()));
+                    (file, None, None)
+                }
             };
 
-            // bytes downloaded
-            let mut downloaded = 0;
-            // buffer of bytes in a chunk, DEFAULT = 8KB
-            let mut buffer = [0; 8192];
+        // the files without hashes are checked with the digests of the server, the verifier
+        // replacing the hashes checks the file alone
+        let hashes = match (self.verifier.is_some(), self.verifier_mode) {
+            (true, VerifierMode::Replace) => DLHashes::new(),
+            _ if hashes.hashes.is_empty() => server_hashes,
+            _ => hashes,
+        };
+
+        // the raw body is hashed while it's read when the hashes check it
+        let mut transformer = self.transform.as_ref().map(|factory| factory());
+        // the bodies compressed by the server are written decompressed
+        #[cfg(feature = "gzip")]
+        if transformer.is_none() && transport_gzip {
+            transformer = Some(Box::new(transform::Gunzip::new()));
+        }
+        #[cfg(not(feature = "gzip"))]
+        let _ = transport_gzip;
+        // reserve the space of the file, failing early when the disk is full. The segments
+        // preallocate their file and the size of transformed files is unknown
+        let reserved = size.or((self.size > 0).then_some(self.size));
+        if let (Some(size), None, None, false) = (reserved, ranges_size, &transformer, self.sparse)
+        {
+            file.reserve(size)
+                .await
+                .map_err(|e| DwldError::io(&path, e))?;
+        }
+        let mut raw_hasher = (transformer.is_some()
+            && self.hash_stage == HashStage::Raw
+            && !hashes.hashes.is_empty()
+            && !self.preverified)
+            .then(|| hashes.hasher());
+
+        // local files are read with the strategy, remote ones are fetched back
+        let local = local_hash_path
+            .map(std::path::PathBuf::from)
+            .or_else(|| self.backend.local_path(&target));
+
+        // big files of servers accepting ranges are split in concurrent segments
+        let segmented = match (ranges_size, &local) {
+            (Some(size), Some(local)) => Some(segments::Segmented {
+                client: &client,
+                url: &url,
+                headers: &self.headers,
+                path: local,
+                size,
+                segments: segments::Segmented::count(self.segments, size),
+                timeouts,
+                handle: ctx.handle,
+                bandwidth: ctx.bandwidth,
+                speed_limit: speed_limit.as_ref(),
+                sparse: self.sparse,
+                stats: ctx.stats,
+            }),
+            _ => None,
+        };
+        // the written data is hashed while streaming instead of reading the file back. The
+        // resumed bytes are read once to start the digest, the segments written out of
+        // order and the remote resumed files are read back after the download
+        let resumable = offset == 0 || (local.is_some() && transformer.is_none());
+        let mut written_hasher = (segmented.is_none()
+            && resumable
+            && raw_hasher.is_none()
+            && !hashes.hashes.is_empty()
+            && !self.preverified)
+            .then(|| hashes.hasher());
+        if let (Some(hasher), Some(local), true) = (&mut written_hasher, &local, offset > 0) {
+            hash::hash_prefix(local, offset, hasher).map_err(|e| DwldError::io(&path, e))?;
+        }
+        if let Some(segmented) = segmented {
+            file.finish().await.map_err(|e| DwldError::io(&path, e))?;
+            segmented.download(indicator).await?;
+        } else {
+            // bodies not read from the network aren't limited nor counted
+            let network = !fallback && self.parts.is_empty() && !self.has_local_source();
+            let limits = match network {
+                true => [ctx.bandwidth, speed_limit.as_ref()],
+                false => [None, None],
+            };
+            let stats = ctx.stats.filter(|_| network);
+            // bytes announced by the response, including the resumed ones
+            let expected = body.len().map(|len| offset + len as u64);
+            // bytes downloaded, including the resumed ones
+            let mut downloaded = offset;
+            indicator.effect(downloaded);
 
-            // read the response body
-            let mut body = response.take_body();
+            // read the body
             loop {
-                match AsyncReadExt::read(&mut body, &mut buffer).await {
+                // stop reading while the session is paused
+                if let Some(handle) = ctx.handle.filter(|handle| handle.is_paused()) {
+                    indicator.signal(IndicateSignal::State("Paused".to_string()));
+                    while handle.resumed().await {}
+                    indicator.signal(IndicateSignal::State(String::new()));
+                }
+                let read = match first.take() {
+                    Some(n) => Ok(n),
+                    None => {
+                        let read = AsyncReadExt::read(&mut body, &mut buffer);
+                        timeout::timeout(timeouts.read, DLTimeoutKind::Read, read).await?
+                    }
+                };
+                match read {
                     Ok(0) => break, // EOF
                     Ok(n) => {
+                        if let Some(hasher) = &mut raw_hasher {
+                            hasher.update(&buffer[..n]);
+                        }
                         // write the chunk to the file
-                        file.write_all(&buffer[..n]).unwrap();
+                        let chunk = match &mut transformer {
+                            Some(transformer) => transformer
+                                .transform(&buffer[..n])
+                                .map_err(DwldError::Transform)?,
+                            None => buffer[..n].to_vec(),
+                        };
+                        if let Some(hasher) = &mut written_hasher {
+                            hasher.update(&chunk);
+                        }
+                        file.write(&chunk)
+                            .await
+                            .map_err(|e| DwldError::io(&path, e))?;
                         downloaded += n as u64;
+                        if let Some(stats) = stats {
+                            stats.add(n);
+                        }
+                        throttle::consume(&limits, n).await;
                         // update the progress bar
                         indicator.effect(downloaded);
                     }
-                    Err(e) => return Err(e.to_string()),
+                    Err(e) => return Err(DwldError::Network(e.into())),
                 }
             }
-            path_hash
-        } else {
-            // if the response isn't successful, abandon the download
-            indicator.signal(IndicateSignal::Fail(response.status().to_string()));
-            String::new()
-        };
+            // the connection may be closed before the end of the body
+            if let Some(expected) = expected.filter(|expected| *expected != downloaded) {
+                return Err(DwldError::Truncated {
+                    path: path_clone,
+                    expected,
+                    received: downloaded,
+                });
+            }
+            if let Some(transformer) = &mut transformer {
+                let rest = transformer.finish().map_err(DwldError::Transform)?;
+                if let Some(hasher) = &mut written_hasher {
+                    hasher.update(&rest);
+                }
+                file.write(&rest)
+                    .await
+                    .map_err(|e| DwldError::io(&path, e))?;
+            }
+            file.finish().await.map_err(|e| DwldError::io(&path, e))?;
+        }
+        if let (Some(stats), Some(ttfb)) = (ctx.stats, ttfb) {
+            stats.timings.set(report::DLTimings {
+                ttfb: Some(ttfb),
+                transfer: Some(transferring.elapsed()),
+                ..Default::default()
+            });
+        }
+        // the joined parts aren't needed anymore
+        for part in &part_paths {
+            let _ = std::fs::remove_file(part);
+        }
 
         // check the hashes if they exist
-        if hashes.hashes.len() > 0 && !hashes.verify_file(&path_hash) {
+        let digests = match raw_hasher.or(written_hasher) {
+            Some(hasher) => hasher.digests(),
+            None if !hashes.hashes.is_empty() && !self.preverified => match &local {
+                Some(local) => hashes.digest_file_with(local, self.verify_strategy),
+                None => self
+                    .backend
+                    .read(&target)
+                    .await
+                    .map(|data| hashes.digest_data(&data)),
+            }
+            .map_err(|e| DwldError::io(&path, e))?,
+            None => Vec::new(),
+        };
+        let mut valid = digests.is_empty() || digests.iter().any(hash::DLDigest::matches);
+        if let (true, Some(verifier), false) = (valid, &self.verifier, self.preverified) {
+            valid = self
+                .run_verifier(verifier.as_ref(), local.as_deref(), &target)
+                .await
+                .map_err(|e| DwldError::io(&path, e))?;
+        }
+        if !valid {
+            let body = local.as_deref().map(peek_file).unwrap_or_default();
+            // keep the corrupt download as evidence, a failure of the quarantine isn't
+            // fatal and the cleanup of the partial data ignores the moved file
+            if let (Some(dir), Some(local)) = (&self.quarantine_dir, &local) {
+                let evidence = quarantine::Evidence {
+                    url: &url,
+                    path: &path,
+                    digests: &digests,
+                    started,
+                };
+                let _ = evidence.quarantine(Path::new(dir), local);
+            }
             // if the hash verification fails, abandon the download
-            indicator.signal(IndicateSignal::Fail(format!(
-                "Hash verification failed for {}",
-                path_clone
-            )));
-            return Err("Hash verification failed".to_string());
+            return Err(DwldError::HashMismatch {
+                path: path_clone,
+                body,
+            });
+        }
+
+        // the verified file replaces the destination at once
+        let local = if target != path {
+            self.backend
+                .rename(&target, &path)
+                .await
+                .map_err(|e| DwldError::io(&path, e))?;
+            self.backend.local_path(&path)
+        } else {
+            local
+        };
+
+        // the file is complete, later failures don't touch it
+        *partial = None;
+
+        // keep the response for the next downloads, a failure of the cache isn't fatal
+        if let (Some(cache), Some(policy)) = (&self.url_cache, &cache_policy) {
+            let _ = match &local {
+                Some(local) => {
+                    let (cache, policy, url, local) =
+                        (cache.clone(), policy.clone(), url.clone(), local.clone());
+                    smol::unblock(move || cache.store(&url, &policy, &local)).await
+                }
+                None => match self.backend.read(&path).await {
+                    Ok(data) => cache.store_data(&url, policy, &data),
+                    Err(e) => Err(e),
+                },
+            };
+        }
+        // keep the validators for the next refresh, a failure only makes it download again
+        if let (true, Some(policy)) = (self.conditional, &cache_policy) {
+            if let Some(destination) = self.backend.local_path(&path) {
+                let _ = conditional::Validators::from_policy(policy).store(&destination);
+            }
+        }
+
+        // materialize the CAS blob in the destination, encrypting the blob or copying it
+        // when it isn't linked
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some(hash)) = (&self.cas, &cas_hash) {
+            let (storage, hash, path) = (storage.clone(), hash.clone(), path_clone.clone());
+            smol::unblock(move || storage.finish(&hash, &path))
+                .await
+                .map_err(DwldError::Cas)?;
         }
+        #[cfg(not(feature = "cas"))]
+        let _ = cas_hash;
 
         // call the on_download event
         (self.on_download)(path_clone.clone());
 
         #[cfg(feature = "decompress")]
         {
-            if self.decompression_config.is_some() {
-                indicator.signal(IndicateSignal::State("Decompressing...".to_string()));
-                let config = self.decompression_config.as_ref().unwrap();
-                config.decompress(&path_clone)?;
-
-                if config.delete_after {
-                    indicator.signal(IndicateSignal::State("Cleaning up...".to_string()));
-                    std::fs::remove_file(&path_clone).expect("Failed to delete file");
-                }
+            if let Some(config) = &self.decompression_config {
+                // the decompressors need the file in the local filesystem
+                let local = self.backend.local_path(&path_clone).ok_or_else(|| {
+                    DwldError::Decompression(
+                        "Decompression needs a local storage backend".to_string(),
+                    )
+                })?;
+                self.extract(indicator, config, &local)?;
+            }
+        }
+
+        #[cfg(feature = "encryption")]
+        if let Some(config) = &self.encryption {
+            // the decompression may have removed the file
+            if let Some(local) = self.backend.local_path(&path_clone).filter(|p| p.exists()) {
+                indicator.signal(IndicateSignal::State("Encrypting...".to_string()));
+                config
+                    .encrypt(&local)
+                    .map_err(|e| DwldError::io(&path_clone, e))?;
+            }
+        }
+
+        // the modification time of the server, once nothing else writes the file. A failure
+        // isn't fatal and the decompression may have removed the file
+        let modified = cache_policy.as_ref().and_then(|policy| policy.modified);
+        if let (true, Some(modified)) = (self.server_mtime, modified) {
+            if let Some(local) = self.backend.local_path(&path_clone).filter(|p| p.exists()) {
+                let _ = storage::set_modified(&local, modified);
             }
         }
 
         // if the hash verification succeeds, finish the download
         indicator.signal(IndicateSignal::Success());
-        Ok(())
+        Ok(DLStatus::Downloaded)
     }
-    /// New instance of DLFile with default values
-    pub fn new() -> Self {
-        DLFile {
-            path: String::new(),
-            url: String::new(),
-            size: 0,
-            hashes: DLHashes::new(),
-            #[cfg(feature = "decompress")]
-            decompression_config: None,
-            on_download: Arc::new(|_| {}),
-            cas: None,
+    /// Extracts the downloaded archive, recording the stages so a restarted session
+    /// continues the extraction
+    #[cfg(feature = "decompress")]
+    fn extract(
+        &self,
+        indicator: &mut impl Indicator,
+        config: &decompress::DLDecompressionConfig,
+        local: &Path,
+    ) -> Result<(), DwldError> {
+        // a failure recording the stages only loses the resumption
+        let _ = pipeline::Stage::Downloaded.store(self, config, local);
+        let archive = local.to_string_lossy();
+        indicator.signal(IndicateSignal::State("Decompressing...".to_string()));
+        match self.temp_path(".extract") {
+            Some(staging) => config.decompress_staged(&archive, &staging)?,
+            None => config.decompress(&archive)?,
         }
+
+        if config.delete_after {
+            indicator.signal(IndicateSignal::State("Cleaning up...".to_string()));
+            storage::remove_file(local).map_err(|e| DwldError::io(archive.as_ref(), e))?;
+        }
+        let _ = pipeline::Stage::Extracted.store(self, config, local);
+        Ok(())
     }
-    /// Adds the path of the file to instance
-    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
-        self.path = path.as_ref().to_string_lossy().to_string();
-        self
-    }
-    /// Adds the URL of the file to instance
-    pub fn with_url(mut self, url: &str) -> Self {
-        self.url = url.to_string();
-        self
-    }
-    /// Adds the size of the file to instance
-    pub fn with_size(mut self, size: u64) -> Self {
-        self.size = size;
-        self
-    }
-    /// Adds the hashes of the file to instance
-    pub fn with_hashes(mut self, hashes: DLHashes) -> Self {
-        self.hashes = hashes;
-        self
-    }
-    /// Adds the decompression configuration of the file to instance
+    /// Continues the extraction of the archive downloaded by a previous session. The
+    /// extracted archives are only skipped if their hashes identify them
     #[cfg(feature = "decompress")]
-    pub fn with_decompression_config(
-        mut self,
-        config: crate::decompress::DLDecompressionConfig,
-    ) -> Self {
-        self.decompression_config = Some(config);
-        self
-    }
-    /// Adds the on_download event handler of the file to instance
-    pub fn with_on_download(mut self, on_download: Arc<dyn Fn(String) + Send + Sync>) -> Self {
-        self.on_download = on_download;
-        self
-    }
-    /// Configure CAS using
-    #[cfg(feature = "cas")]
-    pub fn with_cas(mut self, value: cas::DLStorage) -> Self {
-        self.cas = Some(value);
-        self
+    fn resume_extraction(
+        &self,
+        indicator: &mut impl Indicator,
+    ) -> Result<Option<DLStatus>, DwldError> {
+        let (Some(config), Some(local)) = (
+            &self.decompression_config,
+            self.backend.local_path(&self.path),
+        ) else {
+            return Ok(None);
+        };
+        let reason = match pipeline::Stage::load(self, config, &local) {
+            Some(pipeline::Stage::Downloaded) if local.is_file() => {
+                self.extract(indicator, config, &local)?;
+                "Extraction resumed"
+            }
+            Some(pipeline::Stage::Extracted) if !self.hashes.hashes.is_empty() => {
+                "Archive already extracted"
+            }
+            _ => return Ok(None),
+        };
+        indicator.effect(self.size);
+        indicator.signal(IndicateSignal::Skipped(reason.to_string()));
+        Ok(Some(DLStatus::Skipped(reason.to_string())))
     }
-}
-impl<T: IndicatorFactory> Downloader<T> {
-    /// Creates a new instance of Downloader
-    pub fn new() -> Self {
+    /// Requests the file, unless the URL cache can serve it
+    async fn fetch(
+        &self,
+        indicator: &mut impl Indicator,
+        client: Client,
+        ctx: &DownloadContext<'_>,
+        timeouts: DLTimeouts,
+    ) -> Result<Fetched, DwldError> {
+        // the embedded data and the local files are read without any request, continuing
+        // the partial data of the local files
+        if data_url::is_data_url(&self.url) {
+            let body = surf::Body::from_bytes(data_url::decode(&self.url)?);
+            return Ok(Fetched::Body(
+                Box::new(body),
+                0,
+                None,
+                false,
+                DLHashes::new(),
+            ));
+        }
+        if file_url::is_file_url(&self.url) {
+            let source = file_url::path(&self.url)?;
+            let offset = self.resume_offset().unwrap_or(0);
+            let (body, offset) = file_url::body(&source, offset)?;
+            return Ok(Fetched::Body(
+                Box::new(body),
+                offset,
+                None,
+                false,
+                DLHashes::new(),
+            ));
+        }
+        // the FTP servers are asked the size first and the transfer restarts at the offset
+        #[cfg(feature = "ftp")]
+        if ftp::is_ftp_url(&self.url) {
+            let offset = self.resume_offset().unwrap_or(0);
+            let connector = ctx.ftps.cloned().unwrap_or_default();
+            let body = ftp::body(&self.url, offset, &connector);
+            let (body, offset) =
+                timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, body).await??;
+            return Ok(Fetched::Body(
+                Box::new(body),
+                offset,
+                None,
+                false,
+                DLHashes::new(),
+            ));
+        }
+        #[cfg(feature = "sftp")]
+        if sftp::is_sftp_url(&self.url) {
+            let offset = self.resume_offset().unwrap_or(0);
+            let config = self.sftp.clone().unwrap_or_default();
+            let body = sftp::body(&self.url, offset, config, timeouts);
+            let (body, offset) =
+                timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, body).await??;
+            return Ok(Fetched::Body(
+                Box::new(body),
+                offset,
+                None,
+                false,
+                DLHashes::new(),
+            ));
+        }
+        // fresh responses of the URL cache don't need any request
+        let cached = self.cached_response();
+        if let Some(entry) = cached.as_ref().filter(|entry| entry.is_fresh()) {
+            return self
+                .serve_cached(indicator, entry, ctx)
+                .await
+                .map(Fetched::Served);
+        }
+
+        // continue a previous download if there is partial data
+        let resume_from = self.resume_offset().filter(|_| cached.is_none());
+        // ask if the complete destination changed
+        let validators = match (self.conditional, cached.is_none() && resume_from.is_none()) {
+            (true, true) => self
+                .backend
+                .local_path(&self.path)
+                .and_then(|path| conditional::Validators::load(&path)),
+            _ => None,
+        };
+
+        // big files of servers accepting ranges are split in segments, their size is asked first
+        if self.segments > 1
+            && !self.resume
+            && cached.is_none()
+            && validators.is_none()
+            && self.transform.is_none()
+            && self.is_local()
+        {
+            if let Some((size, digests, policy)) = self.ranges_size(&client, timeouts).await? {
+                return Ok(Fetched::Segments(size, digests, policy));
+            }
+        }
+
+        // make the request with SURF
+        let mut request = add_headers(client.get(&self.url), &self.headers);
+        if let Some(offset) = resume_from {
+            request = request.header("Range", format!("bytes={}-", offset));
+        }
+        // revalidate the stale response of the URL cache
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header("If-None-Match", etag.as_str());
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header("If-Modified-Since", last_modified.as_str());
+            }
+        }
+        if let Some(validators) = &validators {
+            request = validators.apply(request);
+        }
+        let response = timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request)
+            .await
+            .and_then(|response| response.map_err(DwldError::from));
+        let mut response = match response {
+            Ok(response) => response,
+            // stale responses can be used if the server is unreachable
+            Err(_) if cached.as_ref().is_some_and(|entry| !entry.must_revalidate) => {
+                return self
+                    .serve_cached(indicator, cached.as_ref().unwrap(), ctx)
+                    .await
+                    .map(Fetched::Served);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut cache_policy = (self.url_cache.is_some() || self.conditional || self.server_mtime)
+            .then(|| url_cache::CachePolicy::from_response(&response));
+        if let (Some(cache), Some(entry), Some(policy)) = (&self.url_cache, &cached, &cache_policy)
+        {
+            if response.status() == StatusCode::NotModified {
+                let _ = cache.refresh(&self.url, entry, policy);
+                return self
+                    .serve_cached(indicator, entry, ctx)
+                    .await
+                    .map(Fetched::Served);
+            }
+        }
+
+        // the destination didn't change since it was downloaded
+        if validators.is_some() && response.status() == StatusCode::NotModified {
+            let reason = "Destination not modified".to_string();
+            indicator.effect(self.size);
+            indicator.signal(IndicateSignal::Skipped(reason.clone()));
+            return Ok(Fetched::Served(DLStatus::Skipped(reason)));
+        }
+
+        // the server may ignore the range and send the whole file
+        let mut status = response.status();
+        let offset = match resume_from {
+            Some(offset) if status == StatusCode::PartialContent => {
+                // the rest of the file, from the offset
+                let size = (self.size > 0).then_some(self.size);
+                let end = size.map(|size| size - 1);
+                if let Err(e) = segments::check_content_range(&response, offset, end, size) {
+                    return Err(reject(&mut response, timeouts, e).await);
+                }
+                offset
+            }
+            // the partial data is already the whole file, if the size of the file tells it
+            Some(offset)
+                if status == StatusCode::RequestedRangeNotSatisfiable
+                    && match segments::unsatisfied_size(&response) {
+                        Some(size) => size == offset,
+                        None => self.size == offset,
+                    } =>
+            {
+                response.set_body(surf::Body::empty());
+                offset
+            }
+            // the file is smaller or was replaced, the partial data is discarded
+            Some(_) if status == StatusCode::RequestedRangeNotSatisfiable => {
+                trace_event!(DEBUG, size = ?segments::unsatisfied_size(&response), "restarting");
+                let _ = response.body_bytes().await;
+                self.discard_partial()
+                    .await
+                    .map_err(|e| DwldError::io(self.staging_path(), e))?;
+                let request = add_headers(client.get(&self.url), &self.headers);
+                response =
+                    timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request).await??;
+                status = response.status();
+                if !status.is_success() {
+                    return Err(status_error(&mut response, timeouts).await);
+                }
+                cache_policy =
+                    cache_policy.map(|_| url_cache::CachePolicy::from_response(&response));
+                0
+            }
+            _ if !status.is_success() => return Err(status_error(&mut response, timeouts).await),
+            _ => 0,
+        };
+
+        let gzip = response
+            .header("Content-Encoding")
+            .is_some_and(|encoding| encoding.last().as_str().eq_ignore_ascii_case("gzip"));
+        let body = Box::new(response.take_body());
+        trace_event!(DEBUG, status = %status, offset, length = ?body.len(), gzip, "body");
+        // the digests are of the compressed body
+        let digests = match gzip {
+            true => DLHashes::new(),
+            false => self.server_hashes(&response),
+        };
+        Ok(Fetched::Body(body, offset, cache_policy, gzip, digests))
+    }
+    /// Size of the file if the server accepts ranges and it's big enough to be split, with
+    /// the digests announced by the server and the caching rules when they're needed
+    async fn ranges_size(
+        &self,
+        client: &Client,
+        timeouts: DLTimeouts,
+    ) -> Result<Option<(u64, DLHashes, Option<url_cache::CachePolicy>)>, DwldError> {
+        let request = add_headers(client.head(&self.url), &self.headers);
+        let response =
+            timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request).await??;
+        let ranges = response
+            .header("Accept-Ranges")
+            .is_some_and(|value| value.as_str().eq_ignore_ascii_case("bytes"));
+        let size = response
+            .len()
+            .map(|len| len as u64)
+            .filter(|size| ranges && segments::Segmented::count(self.segments, *size) > 1);
+        let size = size.filter(|_| response.status().is_success());
+        let policy = (self.url_cache.is_some() || self.server_mtime)
+            .then(|| url_cache::CachePolicy::from_response(&response));
+        Ok(size.map(|size| (size, self.server_hashes(&response), policy)))
+    }
+    /// Checks if the file is checked with the digests of the server, see
+    /// [DLFile::server_digests]. The objects of Google Cloud Storage always announce theirs,
+    /// the transformed files don't match them
+    fn trusts_server_digests(&self) -> bool {
+        #[cfg(feature = "gcs")]
+        let server_digests = self.server_digests || gcs::is_gcs_url(&self.url);
+        #[cfg(not(feature = "gcs"))]
+        let server_digests = self.server_digests;
+        server_digests && self.hashes.hashes.is_empty() && self.transform.is_none()
+    }
+    /// Digests announced by the server in the response, if the file trusts them
+    fn server_hashes(&self, response: &surf::Response) -> DLHashes {
+        match self.trusts_server_digests() {
+            true => server_digest::digests(response),
+            false => DLHashes::new(),
+        }
+    }
+    /// Checks if the existing destination matches the digests announced by the server,
+    /// asked with a `HEAD` request
+    async fn matches_server_digests(&self, client: &Client, timeouts: DLTimeouts) -> bool {
+        let Some(local) = self.backend.local_path(&self.path).filter(|p| p.is_file()) else {
+            return false;
+        };
+        let request = add_headers(client.head(&self.url), &self.headers);
+        let response = timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request).await;
+        let Ok(Ok(response)) = response else {
+            return false;
+        };
+        let digests = self.server_hashes(&response);
+        response.status().is_success()
+            && !digests.hashes.is_empty()
+            && digests
+                .verify_file_with(&local, self.verify_strategy)
+                .unwrap_or(false)
+    }
+    /// Files where the parts of the object are downloaded
+    fn part_paths(&self) -> Vec<std::path::PathBuf> {
+        let base = match (self.temp_path(""), self.backend.local_path(&self.path)) {
+            (Some(temp), _) => temp,
+            (None, Some(local)) => local,
+            (None, None) => std::env::temp_dir().join(
+                Path::new(&self.path)
+                    .file_name()
+                    .unwrap_or_else(|| "object".as_ref()),
+            ),
+        };
+        (0..self.parts.len())
+            .map(|i| format!("{}.part{}", base.display(), i).into())
+            .collect()
+    }
+    /// File handles open at once by the download, counting every connection with its file
+    fn handles(&self) -> usize {
+        let connections = if self.parts.is_empty() {
+            self.segments.max(1)
+        } else {
+            self.segments.clamp(1, self.parts.len())
+        };
+        // the companions start after the file, all at once
+        let companions = self.companions.iter().map(DLFile::handles).sum();
+        (connections * 2).max(companions)
+    }
+    /// Checks the written file with the verifier, reading it back
+    async fn run_verifier(
+        &self,
+        verifier: &dyn verify::Verifier,
+        local: Option<&Path>,
+        target: &str,
+    ) -> std::io::Result<bool> {
+        match local {
+            Some(local) => verifier.verify(self, &mut std::fs::File::open(local)?),
+            None => {
+                let data = self.backend.read(target).await?;
+                verifier.verify(self, &mut data.as_slice())
+            }
+        }
+    }
+    /// Checks if the URL is read without the network, a `file://` or a `data:` URL
+    fn has_local_source(&self) -> bool {
+        file_url::is_file_url(&self.url) || data_url::is_data_url(&self.url)
+    }
+    /// Checks if the file is written in the local filesystem
+    fn is_local(&self) -> bool {
+        #[cfg(feature = "cas")]
+        if self.cas.is_some() && !self.hashes.hashes.is_empty() {
+            return true;
+        }
+        self.backend.local_path(&self.path).is_some()
+    }
+    /// Checks if the destination exists with the size and the hashes of the file, with
+    /// the reason to keep it
+    async fn destination_is_valid(&self) -> Option<&'static str> {
+        // the trusted files are only checked by their size, hashing them is slow
+        if self.preverified {
+            let valid = match self.backend.size(&self.path) {
+                Some(size) => self.size == 0 || size == self.size,
+                None => self.backend.exists(&self.path).await,
+            };
+            return valid.then_some("Destination preverified");
+        }
+        if self.hashes.hashes.is_empty() {
+            return None;
+        }
+        let valid = match self.backend.local_path(&self.path) {
+            Some(local) => {
+                let metadata = std::fs::metadata(&local).ok()?;
+                if self.size != 0 && metadata.len() != self.size {
+                    return None;
+                }
+                // the sampling needs the size to trust the destination without hashing it
+                let sampling = self.verify_sampling.filter(|_| self.size != 0);
+                if sampling.is_some_and(|sampling| sampling.skips_hash(&self.path, &metadata)) {
+                    return Some("Destination unchanged, not hashed");
+                }
+                self.hashes
+                    .verify_file_with(&local, self.verify_strategy)
+                    .unwrap_or(false)
+            }
+            None => match self.backend.read(&self.path).await {
+                Ok(data) => {
+                    (self.size == 0 || data.len() as u64 == self.size)
+                        && self.hashes.verify_data(&data)
+                }
+                Err(_) => false,
+            },
+        };
+        valid.then_some("Destination already valid")
+    }
+    /// Reason to keep the destination without downloading the file, if it's valid
+    async fn valid_destination(&self) -> Option<&'static str> {
+        if !(self.skip_if_valid || self.preverified) {
+            return None;
+        }
+        self.destination_is_valid().await
+    }
+    /// Response of the URL cache, if it matches the hashes
+    fn cached_response(&self) -> Option<url_cache::CacheEntry> {
+        let cache = self.url_cache.as_ref()?;
+        let entry = cache.lookup(&self.url)?;
+        if !self.hashes.hashes.is_empty()
+            && !self
+                .hashes
+                .verify_file_with(&entry.body, self.verify_strategy)
+                .unwrap_or(false)
+        {
+            let _ = cache.remove(&self.url);
+            return None;
+        }
+        Some(entry)
+    }
+    /// Checks the local destination before writing it, see [storage::prepare_local]. The
+    /// files locked by other processes are waited for outside the executor
+    async fn prepare_destination(
+        &self,
+        path: &str,
+        ctx: &DownloadContext<'_>,
+    ) -> Result<(), DwldError> {
+        let (path, replace_readonly) = (std::path::PathBuf::from(path), self.replace_readonly);
+        let dirs = ctx.dirs.cloned();
+        smol::unblock(move || storage::prepare_local_in(&path, replace_readonly, dirs.as_deref()))
+            .await?;
+        Ok(())
+    }
+    /// Writes the response of the URL cache in the destination
+    async fn serve_cached(
+        &self,
+        indicator: &mut impl Indicator,
+        entry: &url_cache::CacheEntry,
+        ctx: &DownloadContext<'_>,
+    ) -> Result<DLStatus, DwldError> {
+        let path = &self.path;
+        if self.backend.local_path(path).is_some() {
+            self.prepare_destination(path, ctx).await?;
+        }
+        let mut body = std::fs::File::open(&entry.body)
+            .map_err(|e| DwldError::io(entry.body.to_string_lossy(), e))?;
+        let mut file = self
+            .backend
+            .create(path)
+            .await
+            .map_err(|e| storage::DLDestinationError::from_io(path, e))?;
+        let mut buffer = [0; 8192];
+        loop {
+            match std::io::Read::read(&mut body, &mut buffer) {
+                Ok(0) => break,
+                Ok(n) => file
+                    .write(&buffer[..n])
+                    .await
+                    .map_err(|e| DwldError::io(path, e))?,
+                Err(e) => return Err(DwldError::io(entry.body.to_string_lossy(), e)),
+            }
+        }
+        file.finish().await.map_err(|e| DwldError::io(path, e))?;
+        indicator.effect(self.size);
+        indicator.signal(IndicateSignal::Cached());
+        Ok(DLStatus::Cached)
+    }
+    /// State of the file before downloading it, used to initialize the indicators
+    pub fn initial_state(&self) -> TaskState {
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some((_, hash))) = (&self.cas, self.hashes.hashes.first()) {
+            if storage.find(hash).is_some() {
+                return TaskState::Cached;
+            }
+        }
+        if let Some(entry) = self.url_cache.as_ref().and_then(|c| c.lookup(&self.url)) {
+            if entry.is_fresh() {
+                return TaskState::Cached;
+            }
+        }
+        match self.resume_offset() {
+            Some(offset) => TaskState::Resumed(offset),
+            None => TaskState::Fresh,
+        }
+    }
+    /// Path where the file is written before it's complete, `<path>.part` for atomic files.
+    /// The CAS files are written in their blob
+    fn staging_path(&self) -> String {
+        #[cfg(feature = "cas")]
+        if self.cas.is_some() && !self.hashes.hashes.is_empty() {
+            return self.path.clone();
+        }
+        match (self.atomic, self.temp_path(".part")) {
+            (true, Some(temp)) => temp.to_string_lossy().into_owned(),
+            (true, None) => format!("{}.part", self.path),
+            (false, _) => self.path.clone(),
+        }
+    }
+    /// Path in the temporary directory for the file, named after the file and a hash of
+    /// its path so the files with the same name don't collide
+    fn temp_path(&self, suffix: &str) -> Option<std::path::PathBuf> {
+        use sha1::{Digest, Sha1};
+        let temp_dir = self.temp_dir.as_ref()?;
+        self.backend.local_path(&self.path)?;
+        let name = Path::new(&self.path)
+            .file_name()
+            .unwrap_or_else(|| "object".as_ref())
+            .to_string_lossy();
+        let hash = hex::encode(&Sha1::digest(self.path.as_bytes())[..6]);
+        Some(Path::new(temp_dir).join(format!("{}-{}{}", name, hash, suffix)))
+    }
+    /// Removes the partial data of a previous download that can't be continued
+    async fn discard_partial(&self) -> std::io::Result<()> {
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some((_, hash))) = (&self.cas, self.hashes.hashes.first()) {
+            let part = format!("{}.part", storage.path(hash));
+            return match smol::unblock(move || storage::remove_file(part)).await {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                result => result,
+            };
+        }
+        match self.backend.remove(&self.staging_path()).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+    /// Bytes of partial data that can be resumed
+    fn resume_offset(&self) -> Option<u64> {
+        // the transformers can't continue from the middle of the body, the parts of
+        // multipart objects are kept instead
+        if !self.resume || self.transform.is_some() || !self.parts.is_empty() {
+            return None;
+        }
+        #[cfg(feature = "cas")]
+        if let (Some(storage), Some((_, hash))) = (&self.cas, self.hashes.hashes.first()) {
+            return storage.partial_size(hash).filter(|len| *len > 0);
+        }
+        let len = self.backend.size(&self.staging_path())?;
+        // a file with the expected size is not partial
+        (len > 0 && (self.size == 0 || len < self.size)).then_some(len)
+    }
+    /// Name of the file in the indicators, the path followed by its metadata
+    fn label(&self) -> String {
+        if self.metadata.is_empty() {
+            return self.path.clone();
+        }
+        let metadata: Vec<String> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        format!("{} [{}]", self.path, metadata.join(", "))
+    }
+    /// New instance of DLFile with default values
+    pub fn new() -> Self {
+        DLFile {
+            path: String::new(),
+            url: String::new(),
+            size: 0,
+            hashes: DLHashes::new(),
+            verify_strategy: VerifyStrategy::default(),
+            #[cfg(feature = "decompress")]
+            decompression_config: None,
+            on_download: Arc::new(|_| {}),
+            #[cfg(feature = "cas")]
+            cas: None,
+            #[cfg(feature = "encryption")]
+            encryption: None,
+            resume: false,
+            partial_policy: storage::PartialPolicy::default(),
+            replace_readonly: false,
+            backend: _default_backend(),
+            transform: None,
+            hash_stage: HashStage::default(),
+            #[cfg(feature = "sftp")]
+            sftp: None,
+            verifier: None,
+            verifier_mode: VerifierMode::default(),
+            url_cache: None,
+            schedule: None,
+            timeouts: DLTimeouts::default(),
+            skip_if_valid: false,
+            preverified: false,
+            conditional: false,
+            server_digests: false,
+            server_mtime: false,
+            fallback: None,
+            overwrite_policy: None,
+            segments: 1,
+            sparse: false,
+            speed_limit: None,
+            parts: Vec::new(),
+            companions: Vec::new(),
+            priority: 0,
+            group: None,
+            checkpoints: Vec::new(),
+            atomic: false,
+            mirrors: Vec::new(),
+            temp_dir: None,
+            quarantine_dir: None,
+            verify_sampling: None,
+            headers: Vec::new(),
+            metadata: BTreeMap::new(),
+            retry_policy: None,
+            on_checkpoint: Arc::new(|_, _| {}),
+        }
+    }
+    /// Adds the path of the file to instance
+    pub fn with_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = path.as_ref().to_string_lossy().to_string();
+        self
+    }
+    /// Adds the URL of the file to instance
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = url.to_string();
+        self
+    }
+    /// Adds the size of the file to instance
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = size;
+        self
+    }
+    /// Adds the hashes of the file to instance
+    pub fn with_hashes(mut self, hashes: DLHashes) -> Self {
+        self.hashes = hashes;
+        self
+    }
+    /// Sets how the file is read when verifying the hashes
+    pub fn with_verify_strategy(mut self, strategy: VerifyStrategy) -> Self {
+        self.verify_strategy = strategy;
+        self
+    }
+    /// Adds the decompression configuration of the file to instance
+    #[cfg(feature = "decompress")]
+    pub fn with_decompression_config(
+        mut self,
+        config: crate::decompress::DLDecompressionConfig,
+    ) -> Self {
+        self.decompression_config = Some(config);
+        self
+    }
+    /// Adds the on_download event handler of the file to instance
+    pub fn with_on_download(mut self, on_download: Arc<dyn Fn(String) + Send + Sync>) -> Self {
+        self.on_download = on_download;
+        self
+    }
+    /// Configure CAS using
+    #[cfg(feature = "cas")]
+    pub fn with_cas(mut self, value: cas::DLStorage) -> Self {
+        self.cas = Some(value);
+        self
+    }
+    /// Encrypts the file after the verification, writing `<path>.enc`
+    #[cfg(feature = "encryption")]
+    pub fn with_encryption(mut self, config: encryption::DLEncryptionConfig) -> Self {
+        self.encryption = Some(config);
+        self
+    }
+    /// Continues partial downloads with range requests.
+    ///
+    /// Enabling it also keeps the partial data of failed downloads, see [PartialPolicy::Keep]
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        if resume {
+            self.partial_policy = storage::PartialPolicy::Keep;
+        }
+        self
+    }
+    /// Sets what happens to the written data when the download fails
+    pub fn with_partial_policy(mut self, policy: storage::PartialPolicy) -> Self {
+        self.partial_policy = policy;
+        self
+    }
+    /// Renames read-only destinations to `<path>.bak` and replaces them, instead of failing
+    pub fn with_replace_readonly(mut self, replace: bool) -> Self {
+        self.replace_readonly = replace;
+        self
+    }
+    /// Sets the storage backend where the file is written
+    pub fn with_backend(mut self, backend: Arc<dyn storage::StorageBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+    /// Writes the file to the sink instead of its destination, replacing the storage
+    /// backend. The file is hashed while it's written, it's not resumed nor decompressed
+    pub fn with_sink(self, sink: impl sink::DLSink + 'static) -> Self {
+        self.with_backend(Arc::new(sink::SinkBackend(Arc::new(sink))))
+    }
+    /// Transforms the body before writing it, e.g. to decrypt vendor formats.
+    ///
+    /// The factory creates a new transformer for every download, resuming is disabled
+    pub fn with_transform(
+        mut self,
+        factory: impl Fn() -> Box<dyn DLTransformer> + Send + Sync + 'static,
+    ) -> Self {
+        self.transform = Some(Arc::new(factory));
+        self
+    }
+    /// Sets what happens when the destination already exists, see [OverwritePolicy]
+    pub fn with_overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = Some(policy);
+        self
+    }
+    /// Downloads the file with `n` concurrent range requests into a preallocated file, when
+    /// the server accepts ranges. Segmented downloads can't be resumed or transformed
+    pub fn with_segments(mut self, n: usize) -> Self {
+        self.segments = n;
+        self
+    }
+    /// Writes the file as it comes, avoiding reserving the blocks of huge files before
+    /// downloading them. The segments are written in a sparse file on the filesystems
+    /// supporting it
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+    /// Limits the bytes per second of the file, e.g. to let optional assets trickle down
+    /// while the critical files get the bandwidth
+    pub fn with_speed_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.speed_limit = Some(bytes_per_sec);
+        self
+    }
+    /// Builds the file joining the parts of a multipart object, every part is verified
+    /// with its hashes and the whole file with the hashes of the file. The parts are
+    /// downloaded with up to [DLFile::with_segments] connections
+    pub fn with_parts(mut self, parts: Vec<multipart::DLPart>) -> Self {
+        self.parts = parts;
+        self
+    }
+    /// Attaches a file downloaded after this one, e.g. its signature. The file fails if
+    /// the companion fails, the companions aren't downloaded if the file fails
+    pub fn with_companion(mut self, companion: DLFile) -> Self {
+        self.companions.push(companion);
+        self
+    }
+    /// Sets the priority of the file, the downloader starts the higher ones first
+    pub fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+    /// Adds the file to a concurrency group, e.g. `"big"`, see [Downloader::with_group_limit]
+    pub fn with_group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+    /// Writes the file in `<path>.part` and renames it to the path once it's verified, so
+    /// the path never has a half-written file. The partial data is resumed from the
+    /// `.part` file
+    pub fn with_atomic(mut self, atomic: bool) -> Self {
+        self.atomic = atomic;
+        self
+    }
+    /// Sets the URLs tried in order when the URL answers an error status, times out or
+    /// serves a file not matching the hashes
+    pub fn with_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+    /// Writes the partial files and stages the decompression in the directory instead of
+    /// next to the file
+    pub fn with_temp_dir(mut self, path: &str) -> Self {
+        self.temp_dir = Some(path.to_string());
+        self
+    }
+    /// Moves the downloads failing their hashes to the directory with a `.meta` file,
+    /// instead of deleting them
+    pub fn with_quarantine_dir(mut self, path: &str) -> Self {
+        self.quarantine_dir = Some(path.to_string());
+        self
+    }
+    /// Checks the destination kept by [DLFile::with_skip_if_valid] by its size and its
+    /// modification time, hashing it only if it's sampled or recently modified
+    pub fn with_verify_sampling(mut self, sampling: DLSampling) -> Self {
+        self.verify_sampling = Some(sampling);
+        self
+    }
+    /// Adds a header to every request of the file, its mirrors and its parts, e.g. an
+    /// `Accept` header. The headers set by the library, like `Range`, take precedence
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// Authenticates every request of the file with HTTP Basic authentication. The
+    /// credentials aren't sent to the redirections to other origins
+    pub fn with_basic_auth(self, user: &str, password: &str) -> Self {
+        let auth = surf::http::auth::BasicAuth::new(user, password);
+        self.with_header(auth.name().as_str(), auth.value().as_str())
+    }
+    /// Authenticates every request of the file with a bearer token, e.g. of GitHub or of a
+    /// private registry. The token isn't sent to the redirections to other origins
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        let auth = bearer_auth(token);
+        self.with_header(auth.name().as_str(), auth.value().as_str())
+    }
+    /// Sends the user agent in the requests of the file, overriding the one of the downloader
+    pub fn with_user_agent(self, user_agent: &str) -> Self {
+        self.with_header("User-Agent", user_agent)
+    }
+    /// Attaches an identifier of the application to the file, e.g. `("mod_id", "sodium")`
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+    /// Sets the policy deciding if the URLs of the file are tried again when they fail,
+    /// e.g. [retry::ExponentialBackoff]
+    pub fn with_retry_policy(mut self, policy: impl retry::RetryPolicy + 'static) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+    /// Fires the callback once when the progress of the file crosses each checkpoint,
+    /// e.g. at 25, 50 and 75 percent
+    pub fn with_checkpoints(
+        mut self,
+        checkpoints: Vec<checkpoint::DLCheckpoint>,
+        on_checkpoint: Arc<dyn Fn(String, checkpoint::DLCheckpoint) + Send + Sync>,
+    ) -> Self {
+        self.checkpoints = checkpoints;
+        self.on_checkpoint = on_checkpoint;
+        self
+    }
+    /// Sets a fallback building the body of the file when the download fails, e.g. generating
+    /// it or copying it from another install. The body is still verified with the hashes
+    pub fn with_fallback<F, Fut>(mut self, fallback: F) -> Self
+    where
+        F: Fn(&DLFile) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<surf::Body>> + 'static,
+    {
+        self.fallback = Some(Arc::new(move |file| Box::pin(fallback(file))));
+        self
+    }
+    /// Skips the download when the destination already has the size and the hashes of the
+    /// file, it finishes [DLStatus::Skipped] with an [IndicateSignal::Skipped] signal
+    pub fn with_skip_if_valid(mut self, skip: bool) -> Self {
+        self.skip_if_valid = skip;
+        self
+    }
+    /// Trusts the file as verified elsewhere, skipping its hashing, see
+    /// [DLFile::preverified]. The file is still reported in the session
+    pub fn with_preverified(mut self, preverified: bool) -> Self {
+        self.preverified = preverified;
+        self
+    }
+    /// Refreshes the existing destination only if the server changed it, see
+    /// [DLFile::conditional]. Useful for files updated often without hashes
+    pub fn with_conditional(mut self, conditional: bool) -> Self {
+        self.conditional = conditional;
+        self
+    }
+    /// Checks the file with the digests announced by the server when it doesn't have hashes,
+    /// see [DLFile::server_digests]
+    pub fn with_server_digests(mut self, server_digests: bool) -> Self {
+        self.server_digests = server_digests;
+        self
+    }
+    /// Sets the modification time of the downloaded file from the server, see
+    /// [DLFile::server_mtime]
+    pub fn with_server_mtime(mut self, server_mtime: bool) -> Self {
+        self.server_mtime = server_mtime;
+        self
+    }
+    /// Sets the time until the headers of the response arrive, including the connection
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+    /// Sets the time waiting for every chunk of the body
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+    /// Sets the time of the whole download
+    pub fn with_total_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.total = Some(timeout);
+        self
+    }
+    /// Defers the start of the download, see [DLSchedule]
+    pub fn with_schedule(mut self, schedule: DLSchedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+    /// Caches the response by URL following the `Cache-Control` and `Expires` headers
+    pub fn with_url_cache(mut self, cache: url_cache::DLUrlCache) -> Self {
+        self.url_cache = Some(cache);
+        self
+    }
+    /// Sets whether the hashes check the raw body or the transformed file
+    pub fn with_hash_stage(mut self, stage: HashStage) -> Self {
+        self.hash_stage = stage;
+        self
+    }
+    /// Sets the authentication and the known hosts of the `sftp://` URLs
+    #[cfg(feature = "sftp")]
+    pub fn with_sftp_config(mut self, config: sftp::DLSftpConfig) -> Self {
+        self.sftp = Some(config);
+        self
+    }
+    /// Checks the downloaded file with a custom verifier, after its hashes unless the mode
+    /// is [VerifierMode::Replace]
+    pub fn with_verifier(mut self, verifier: impl verify::Verifier + 'static) -> Self {
+        self.verifier = Some(Arc::new(verifier));
+        self
+    }
+    /// Sets how the verifier is combined with the hashes
+    pub fn with_verifier_mode(mut self, mode: VerifierMode) -> Self {
+        self.verifier_mode = mode;
+        self
+    }
+    /// Decompresses the gzip body while downloading it, e.g. of a `.gz` file. The hashes
+    /// check the compressed body with [HashStage::Raw] or the decompressed file with
+    /// [HashStage::Transformed]
+    #[cfg(feature = "gzip")]
+    pub fn with_gunzip(self, stage: HashStage) -> Self {
+        self.with_transform(|| Box::new(transform::Gunzip::new()))
+            .with_hash_stage(stage)
+    }
+}
+impl Default for DLFile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: IndicatorFactory> Default for Downloader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<T: IndicatorFactory> Downloader<T> {
+    /// Creates a new instance of Downloader
+    pub fn new() -> Self {
+        Self::from_plan(DownloadPlan::default())
+    }
+    /// Creates a downloader executing the plan, with a new handle
+    pub fn from_plan(plan: DownloadPlan) -> Self {
         Downloader {
-            files: Vec::new(),
-            max_concurrent_downloads: 5,
-            max_redirections: 5,
+            files: plan.files,
+            max_concurrent_downloads: plan.max_concurrent_downloads,
+            max_redirections: plan.max_redirections,
+            redirect_policy: plan.redirect_policy,
+            duplicate_policy: plan.duplicate_policy,
+            url_rewriter: plan.url_rewriter,
+            rewrite_redirects: plan.rewrite_redirects,
+            schedule: plan.schedule,
+            timeouts: plan.timeouts,
+            network_monitor: plan.network_monitor,
+            network_poll_interval: plan.network_poll_interval,
+            metered_policy: plan.metered_policy,
+            overwrite_policy: plan.overwrite_policy,
+            max_bandwidth: plan.max_bandwidth,
+            max_open_files: plan.max_open_files,
+            temp_dir: plan.temp_dir,
+            quarantine_dir: plan.quarantine_dir,
+            verify_sampling: plan.verify_sampling,
+            retry_policy: plan.retry_policy,
+            warm_up: plan.warm_up,
+            dns_prefetch: plan.dns_prefetch,
+            default_headers: plan.default_headers,
+            group_limits: plan.group_limits,
+            deadline: plan.deadline,
+            #[cfg(feature = "signal")]
+            cancel_on_signal: plan.cancel_on_signal,
+            error_policy: plan.error_policy,
+            #[cfg(feature = "tls")]
+            tls_config: plan.tls_config,
+            #[cfg(feature = "tls")]
+            accept_invalid_certs: plan.accept_invalid_certs,
+            #[cfg(feature = "s3")]
+            s3_config: plan.s3_config,
+            #[cfg(feature = "gcs")]
+            gcs_config: plan.gcs_config,
+            #[cfg(feature = "azure")]
+            azure_config: plan.azure_config,
+            resolver: plan.resolver,
             indicator_factory: Default::default(),
+            handle: DLHandle::new(),
+            mirrors: mirror::DLMirrorBook::new(),
+            redirects: None,
+            #[cfg(not(feature = "no_static_client"))]
+            client: None,
+            #[cfg(feature = "queue")]
+            queue: None,
+        }
+    }
+    /// Files and options of the downloader, to store them or to execute them again. The
+    /// handle, the indicator, the mirror statistics and the queue aren't part of the plan
+    pub fn plan(&self) -> DownloadPlan {
+        DownloadPlan {
+            files: self.files.clone(),
+            max_concurrent_downloads: self.max_concurrent_downloads,
+            max_redirections: self.max_redirections,
+            redirect_policy: self.redirect_policy.clone(),
+            duplicate_policy: self.duplicate_policy,
+            url_rewriter: self.url_rewriter.clone(),
+            rewrite_redirects: self.rewrite_redirects,
+            schedule: self.schedule,
+            timeouts: self.timeouts,
+            network_monitor: self.network_monitor.clone(),
+            network_poll_interval: self.network_poll_interval,
+            metered_policy: self.metered_policy.clone(),
+            overwrite_policy: self.overwrite_policy,
+            max_bandwidth: self.max_bandwidth,
+            max_open_files: self.max_open_files,
+            temp_dir: self.temp_dir.clone(),
+            quarantine_dir: self.quarantine_dir.clone(),
+            verify_sampling: self.verify_sampling,
+            retry_policy: self.retry_policy.clone(),
+            warm_up: self.warm_up,
+            dns_prefetch: self.dns_prefetch,
+            default_headers: self.default_headers.clone(),
+            group_limits: self.group_limits.clone(),
+            deadline: self.deadline,
+            #[cfg(feature = "signal")]
+            cancel_on_signal: self.cancel_on_signal,
+            error_policy: self.error_policy,
+            #[cfg(feature = "tls")]
+            tls_config: self.tls_config.clone(),
+            #[cfg(feature = "tls")]
+            accept_invalid_certs: self.accept_invalid_certs,
+            #[cfg(feature = "s3")]
+            s3_config: self.s3_config.clone(),
+            #[cfg(feature = "gcs")]
+            gcs_config: self.gcs_config.clone(),
+            #[cfg(feature = "azure")]
+            azure_config: self.azure_config.clone(),
+            resolver: self.resolver.clone(),
         }
     }
     /// Adds the files to instance
@@ -340,50 +2241,300 @@ impl<T: IndicatorFactory> Downloader<T> {
         self.files.push(file);
         self
     }
-    /// Starts the download
-    pub fn start(&self) {
-        // create the semaphore of the maximum concurrent downloads
-        let semaphore = Arc::new(Semaphore::new(self.max_concurrent_downloads));
-        // create the executor
-        let executor = Arc::new(Executor::new());
-        let client = Client::new().with(redirection_middleware::RedirectMiddleware::new(
-            self.max_redirections,
-        ));
+    /// Checks that the files can be downloaded together
+    pub fn validate(&self) -> Result<(), DwldError> {
+        validation::resolve_duplicates(&self.files, self.duplicate_policy)
+            .map(|_| ())
+            .map_err(DwldError::Validation)
+    }
+    /// Starts the download, returns the report of the files or fails with
+    /// [DwldError::Batch], carrying the report too, if any of them fails. The downloader is
+    /// consumed by its session, keep its [Downloader::plan] to download the files again
+    pub fn start(self) -> Result<DownloadReport, DwldError> {
+        smol::block_on(self.start_async())
+    }
+    /// Starts the download in the executor of the caller, see [Downloader::start]
+    pub async fn start_async(self) -> Result<DownloadReport, DwldError> {
+        let result = self.run_session().await;
+        // the receivers of the events end with the session
+        self.handle.close_events();
+        result
+    }
+    /// Checks what [Downloader::start] would do without writing anything. The destinations
+    /// and the caches are checked like in the download, and the size of the files is asked
+    /// with `HEAD` requests
+    pub fn dry_run(&self) -> Result<dry_run::DryRunReport, DwldError> {
+        smol::block_on(self.dry_run_async())
+    }
+    /// Runs the dry run in the executor of the caller, see [Downloader::dry_run]
+    pub async fn dry_run_async(&self) -> Result<dry_run::DryRunReport, DwldError> {
+        let mut files = validation::resolve_duplicates(&self.files, self.duplicate_policy)
+            .map_err(DwldError::Validation)?;
+        files.sort_by_key(|file| std::cmp::Reverse(file.priority));
+        let resolutions = match &self.resolver {
+            Some(resolver) => {
+                resolver::resolve_all(&files, resolver, self.max_concurrent_downloads).await
+            }
+            None => files.iter().map(|_| Ok(None)).collect(),
+        };
+
+        let client = self.create_client(&self.redirect_cache());
+        let checker = dry_run::DryRun {
+            client: &client,
+            timeouts: self.timeouts,
+            overwrite_policy: self.overwrite_policy,
+            metered_policy: self.metered_policy.as_ref(),
+            #[cfg(feature = "ftp")]
+            ftps: &self.ftps_connector(),
+        };
+        let semaphore = Semaphore::new(self.max_concurrent_downloads.max(1));
+        let checks = files
+            .into_iter()
+            .zip(resolutions)
+            .map(|(file, resolution)| {
+                let (checker, semaphore) = (&checker, &semaphore);
+                async move {
+                    let file = match resolution {
+                        Ok(Some(resolved)) => Cow::Owned(resolved),
+                        Ok(None) => Cow::Borrowed(file),
+                        Err(e) => return dry_run::DLDryRunFile::failed(file, &e),
+                    };
+                    let _permit = semaphore.acquire().await;
+                    checker.file(&self.file_defaults(file)).await
+                }
+            });
+        Ok(dry_run::DryRunReport {
+            files: futures::future::join_all(checks).await,
+        })
+    }
+    /// Explains what [Downloader::start] would do with the files without any request: the
+    /// order they would start, their settings once the ones of the downloader are applied
+    /// and where they would come from. The explanation is the same for the same files and
+    /// destinations, the resolver isn't called and the size of the files is the declared one
+    pub fn explain(&self) -> Result<explain::DLExplanation, DwldError> {
+        smol::block_on(self.explain_async())
+    }
+    /// Explains the files in the executor of the caller, see [Downloader::explain]
+    pub async fn explain_async(&self) -> Result<explain::DLExplanation, DwldError> {
+        let mut files = validation::resolve_duplicates(&self.files, self.duplicate_policy)
+            .map_err(DwldError::Validation)?;
+        files.sort_by_key(|file| std::cmp::Reverse(file.priority));
+        let explainer = explain::Explain {
+            timeouts: self.timeouts,
+            overwrite_policy: self.overwrite_policy,
+            metered_policy: self.metered_policy.as_ref(),
+            retries: self.retry_policy.is_some(),
+        };
+        let mut explained = Vec::with_capacity(files.len());
+        for file in files {
+            explained.push(
+                explainer
+                    .file(&self.file_defaults(Cow::Borrowed(file)))
+                    .await,
+            );
+        }
+        Ok(explain::DLExplanation { files: explained })
+    }
+    /// Applies the settings of the downloader to the file without its own
+    fn file_defaults<'a>(&self, file: Cow<'a, DLFile>) -> Cow<'a, DLFile> {
+        let mut file = file;
+        if let (Some(temp_dir), None) = (&self.temp_dir, &file.temp_dir) {
+            file.to_mut().temp_dir = Some(temp_dir.clone());
+        }
+        if let (Some(dir), None) = (&self.quarantine_dir, &file.quarantine_dir) {
+            file.to_mut().quarantine_dir = Some(dir.clone());
+        }
+        if let (Some(sampling), None) = (self.verify_sampling, file.verify_sampling) {
+            file.to_mut().verify_sampling = Some(sampling);
+        }
+        // the later headers replace the earlier ones with the same name
+        if !self.default_headers.is_empty() {
+            let file = file.to_mut();
+            file.headers = self
+                .default_headers
+                .iter()
+                .chain(&file.headers)
+                .cloned()
+                .collect();
+        }
+        file
+    }
+    async fn run_session(&self) -> Result<DownloadReport, DwldError> {
+        let started = Instant::now();
+        let mut files = validation::resolve_duplicates(&self.files, self.duplicate_policy)
+            .map_err(DwldError::Validation)?;
+        // the files with higher priority take the permits first
+        files.sort_by_key(|file| std::cmp::Reverse(file.priority));
+        // the signals cancel the session until it finishes
+        #[cfg(feature = "signal")]
+        let _signals = match self.cancel_on_signal {
+            true => Some(signal::cancel_on_signal(&self.handle).map_err(|e| {
+                DwldError::Validation(format!("The signal handlers can't be installed: {}", e))
+            })?),
+            false => None,
+        };
+
+        // state shared by the downloads of the session
+        let session = Session {
+            // semaphore of the maximum concurrent downloads
+            semaphore: Semaphore::new(self.max_concurrent_downloads),
+            // semaphores of the concurrency groups, under the global one
+            groups: self
+                .group_limits
+                .iter()
+                .map(|(group, max)| (group.clone(), Semaphore::new((*max).max(1))))
+                .collect(),
+            // bucket of the bandwidth shared by the downloads
+            bandwidth: self
+                .max_bandwidth
+                .filter(|rate| *rate > 0)
+                .map(throttle::Throttle::new),
+            // budget of the file handles open at once
+            handles: self.max_open_files.map(budget::HandleBudget::new),
+            #[cfg(feature = "no_static_client")]
+            redirects: self.redirect_cache(),
+            #[cfg(not(feature = "no_static_client"))]
+            client: self.session_client(),
+            #[cfg(feature = "ftp")]
+            ftps: self.ftps_connector(),
+            dirs: Arc::default(),
+        };
+
+        // complete the metadata of the files missing it before downloading them, while the
+        // connections to their hosts are opened
+        let resolve = async {
+            match &self.resolver {
+                Some(resolver) => {
+                    resolver::resolve_all(&files, resolver, self.max_concurrent_downloads).await
+                }
+                None => files.iter().map(|_| Ok(None)).collect(),
+            }
+        };
+        let warm_up = async {
+            #[cfg(not(feature = "no_static_client"))]
+            if let Some(connections) = self.warm_up {
+                warm_up::warm_up(&session.client, &files, connections, self.timeouts).await;
+            }
+        };
+        let (resolutions, ()) = futures::future::join(resolve, warm_up).await;
+
+        // the hosts of the files waiting for a permit are resolved while the first ones run
+        let first = self.max_concurrent_downloads.max(1);
+        let prefetch =
+            (self.dns_prefetch).map(|lookups| dns_prefetch::prefetch(&files, first, lookups));
+        let prefetch = async {
+            if let Some(prefetch) = prefetch {
+                prefetch.await;
+            }
+            std::future::pending::<()>().await
+        };
 
         // obtain the futures
-        let futures: Vec<Pin<Box<dyn Future<Output = Result<(), String>>>>> = self
-            .files
-            .iter()
-            .map(|dl_file| {
-                // create the progress bar
-                let mut indicator = self
-                    .indicator_factory
-                    .create_task(&dl_file.path, dl_file.size);
-                // obtain the semaphore permit
-                let semaphore = Arc::clone(&semaphore);
-                let client = client.clone();
-                // create the task
-                let task: Pin<Box<dyn Future<Output = Result<(), String>>>> =
-                    Box::pin(executor.run(async move {
-                        // acquire the semaphore permit
-                        let permit = semaphore.acquire().await;
-                        indicator.signal(IndicateSignal::Start());
-                        // download the file
-                        #[cfg(feature = "no_static_client")]
-                        let client = create_client(self.max_redirections);
-                        dl_file.download(indicator, client.clone()).await?;
-                        // release the semaphore permit
-                        drop(permit);
-                        Ok(())
-                    }));
-                task
-            })
-            .collect();
+        let mut paths = Vec::with_capacity(files.len());
+        let mut tasks = FuturesUnordered::new();
+        for (dl_file, resolution) in files.into_iter().zip(resolutions) {
+            let (dl_file, failure) = match resolution {
+                Ok(Some(resolved)) => (Cow::Owned(resolved), None),
+                Ok(None) => (Cow::Borrowed(dl_file), None),
+                Err(e) => (Cow::Borrowed(dl_file), Some(e)),
+            };
+            paths.push((dl_file.path.clone(), dl_file.metadata.clone()));
+            let stats = Rc::new(report::TransferStats::default());
+            let task = self.task(&session, dl_file, failure, Rc::clone(&stats));
+            tasks.push(indexed(paths.len() - 1, stats, task));
+        }
 
-        // join all futures
-        smol::block_on(async {
-            futures::future::join_all(futures).await;
-        });
+        // run the tasks until all of them finish, including the files added meanwhile
+        let downloads = async {
+            let mut results = Vec::with_capacity(paths.len());
+            loop {
+                let event = smol::future::or(
+                    async { futures::future::Either::Left(tasks.next().await) },
+                    async { futures::future::Either::Right(self.handle.added().await) },
+                )
+                .await;
+                let added = match event {
+                    futures::future::Either::Left(Some((index, result, stats))) => {
+                        let (path, metadata) = &paths[index];
+                        self.handle
+                            .emit(report::DownloadEvent::new(path, metadata, &result));
+                        // the files aborted by the cancellation don't cancel it again
+                        match (&result, self.error_policy) {
+                            (Err(DwldError::Cancelled(_)), _) => {}
+                            (Err(_), ErrorPolicy::Abort) => {
+                                self.handle.cancel_with(CancelReason::Failure(path.clone()))
+                            }
+                            _ => {}
+                        }
+                        results.push((index, result, stats));
+                        continue;
+                    }
+                    futures::future::Either::Left(None) => match self.handle.try_added() {
+                        Some(file) => file,
+                        None => break,
+                    },
+                    futures::future::Either::Right(file) => file,
+                };
+                paths.push((added.path.clone(), added.metadata.clone()));
+                let stats = Rc::new(report::TransferStats::default());
+                let task = self.added_task(&session, added, Rc::clone(&stats));
+                tasks.push(indexed(paths.len() - 1, stats, task));
+            }
+            results
+        };
+
+        // watch the network and the deadline while the downloads run, and prefetch the DNS
+        let paused_offline = Cell::new(false);
+        let network = async {
+            match &self.network_monitor {
+                Some(monitor) => self.watch_network(monitor, &paused_offline).await,
+                None => std::future::pending().await,
+            }
+        };
+        let deadline = async {
+            if let Some(deadline) = self.deadline {
+                smol::Timer::at(started + deadline).await;
+                self.handle.cancel_with(CancelReason::Deadline);
+            }
+            std::future::pending::<()>().await
+        };
+        let watch = futures::future::join3(network, deadline, prefetch);
+        let mut results = {
+            futures::pin_mut!(downloads, watch);
+            match futures::future::select(downloads, watch).await {
+                futures::future::Either::Left((results, _)) => results,
+                futures::future::Either::Right((_, downloads)) => downloads.await,
+            }
+        };
+        if paused_offline.get() {
+            self.handle.resume();
+        }
+
+        // report every file, the failed ones too
+        results.sort_by_key(|(index, _, _)| *index);
+        let mut files = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for (index, result, stats) in results {
+            let (path, metadata) = &paths[index];
+            match result {
+                Ok(status) => files.push(stats.report(path, metadata, status)),
+                Err(e) => {
+                    let mut file = stats.report(path, metadata, DLStatus::Failed(e.to_string()));
+                    file.code = Some(e.code().to_string());
+                    files.push(file);
+                    errors.push((path.clone(), e));
+                }
+            }
+        }
+        let report = DownloadReport {
+            files,
+            elapsed: started.elapsed(),
+        };
+        if errors.is_empty() {
+            Ok(report)
+        } else {
+            Err(DwldError::Batch { report, errors })
+        }
     }
     /// Sets the maximum number of concurrent downloads
     pub fn with_max_concurrent_downloads(mut self, max_concurrent_downloads: usize) -> Self {
@@ -395,15 +2546,496 @@ impl<T: IndicatorFactory> Downloader<T> {
         self.max_redirections = max_redirections;
         self
     }
+    /// Only follows the redirections allowed by the policy, e.g. to the same origin or to a
+    /// list of hosts. The refused ones fail with [DwldError::RedirectRefused]
+    pub fn with_redirect_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.redirect_policy = policy;
+        self
+    }
+    /// Sets what to do when several files have the same destination
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = policy;
+        self
+    }
+    /// Sets a hook rewriting the URL of every request, e.g. to switch CDN or use a mirror
+    pub fn with_url_rewriter(
+        mut self,
+        rewriter: impl Fn(&Url) -> Url + Send + Sync + 'static,
+    ) -> Self {
+        self.url_rewriter = Some(Arc::new(rewriter));
+        self
+    }
+    /// Sets whether the URL rewriter is also applied to the redirect targets
+    pub fn with_rewrite_redirects(mut self, rewrite_redirects: bool) -> Self {
+        self.rewrite_redirects = rewrite_redirects;
+        self
+    }
+    /// Downloads the pending files of the queue, marking them as finished when they complete
+    #[cfg(feature = "queue")]
+    pub fn with_queue(mut self, queue: queue::DLQueue) -> Self {
+        self.files
+            .extend(queue.pending().into_iter().map(|(_, file)| file));
+        self.queue = Some(queue);
+        self
+    }
+    /// Sets the connect timeout of the files without their own, see [DLTimeouts]
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.connect = Some(timeout);
+        self
+    }
+    /// Sets the read timeout of the files without their own, see [DLTimeouts]
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.read = Some(timeout);
+        self
+    }
+    /// Sets the total timeout of the files without their own, see [DLTimeouts]
+    pub fn with_total_timeout(mut self, timeout: Duration) -> Self {
+        self.timeouts.total = Some(timeout);
+        self
+    }
+    /// Sets what happens when the destination of a file already exists, the files can
+    /// override it
+    pub fn with_overwrite_policy(mut self, policy: OverwritePolicy) -> Self {
+        self.overwrite_policy = policy;
+        self
+    }
+    /// Limits the bytes per second downloaded by all the files together
+    pub fn with_max_bandwidth(mut self, bytes_per_sec: u64) -> Self {
+        self.max_bandwidth = Some(bytes_per_sec);
+        self
+    }
+    /// Limits the concurrent downloads of the files of the group, see [DLFile::with_group].
+    /// The groups share the global limit, e.g. `"big"` with 2 and `"small"` with 16 under
+    /// 16 concurrent downloads. The groups without limit only have the global one
+    pub fn with_group_limit(mut self, group: &str, max: usize) -> Self {
+        self.group_limits.insert(group.to_string(), max);
+        self
+    }
+    /// Limits the file handles open at once, every connection counts with the file it
+    /// writes. The files wait for their handles after their concurrency permit, so big
+    /// batches don't run out of descriptors with segmented or multipart downloads. The
+    /// destination and CAS files are opened with the first chunk of the body
+    pub fn with_max_open_files(mut self, max: usize) -> Self {
+        self.max_open_files = Some(max);
+        self
+    }
+    /// Writes the partial files and stages the decompressions in the directory, e.g. in
+    /// the volume of the destinations for cheap renames or in a scratch disk. The files
+    /// with their own directory keep it
+    pub fn with_temp_dir(mut self, path: &str) -> Self {
+        self.temp_dir = Some(path.to_string());
+        self
+    }
+    /// Opens up to `connections` connections to every host of the batch before starting the
+    /// downloads, so hundreds of files starting at once don't wait for the DNS and the TLS
+    /// handshakes. It needs the shared client, nothing is opened with `no_static_client`
+    pub fn with_warm_up(mut self, connections: usize) -> Self {
+        self.warm_up = Some(connections);
+        self
+    }
+    /// Resolves the hosts of the upcoming files, up to `lookups` at once, while the first
+    /// files download, so the long batches spanning many mirrors don't wait for the DNS of
+    /// every file. The answers are cached by the system resolver
+    pub fn with_dns_prefetch(mut self, lookups: usize) -> Self {
+        self.dns_prefetch = Some(lookups);
+        self
+    }
+    /// Moves the downloads failing their hashes to the directory, for the files without
+    /// their own, see [DLFile::with_quarantine_dir]
+    pub fn with_quarantine_dir(mut self, path: &str) -> Self {
+        self.quarantine_dir = Some(path.to_string());
+        self
+    }
+    /// Samples the destinations checked by the files without their own sampling, see
+    /// [DLFile::with_verify_sampling]. Without it every destination is hashed
+    pub fn with_verify_sampling(mut self, sampling: DLSampling) -> Self {
+        self.verify_sampling = Some(sampling);
+        self
+    }
+    /// Adds a header to every request of the session, e.g. a corporate auth header. The
+    /// headers of the files with the same name override it
+    pub fn with_default_header(mut self, name: &str, value: &str) -> Self {
+        self.default_headers
+            .push((name.to_string(), value.to_string()));
+        self
+    }
+    /// Sets the TLS settings of the connections, e.g. to trust a private CA or to send a
+    /// client certificate
+    #[cfg(feature = "tls")]
+    pub fn with_tls_config(mut self, config: tls::DLTlsConfig) -> Self {
+        self.tls_config = Some(config);
+        self
+    }
+    /// Accepts every server certificate, even expired, self-signed or for another host, e.g.
+    /// for internal test servers.
+    ///
+    /// INSECURE: anyone in the network can intercept the connections and change the
+    /// downloads, only the hashes of the files protect them. Prefer trusting the certificate
+    /// of the server with [Downloader::with_tls_config]
+    #[cfg(feature = "tls")]
+    pub fn danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+    /// Sets the credentials, the region and the endpoint of the `s3://bucket/key` URLs
+    #[cfg(feature = "s3")]
+    pub fn with_s3_config(mut self, config: s3::DLS3Config) -> Self {
+        self.s3_config = Some(config);
+        self
+    }
+    /// Sets the credentials and the endpoint of the `gs://bucket/object` URLs
+    #[cfg(feature = "gcs")]
+    pub fn with_gcs_config(mut self, config: gcs::DLGcsConfig) -> Self {
+        self.gcs_config = Some(config);
+        self
+    }
+    /// Sets the credentials and the endpoint of the `az://account/container/blob` URLs and
+    /// of the `https://<account>.blob.core.windows.net` ones
+    #[cfg(feature = "azure")]
+    pub fn with_azure_config(mut self, config: azure::DLAzureConfig) -> Self {
+        self.azure_config = Some(config);
+        self
+    }
+    /// Authenticates every request of the session with HTTP Basic authentication, the
+    /// credentials of the files override it, see [DLFile::with_basic_auth]
+    pub fn with_basic_auth(self, user: &str, password: &str) -> Self {
+        let auth = surf::http::auth::BasicAuth::new(user, password);
+        self.with_default_header(auth.name().as_str(), auth.value().as_str())
+    }
+    /// Authenticates every request of the session with a bearer token, the credentials of
+    /// the files override it, see [DLFile::with_bearer_token]
+    pub fn with_bearer_token(self, token: &str) -> Self {
+        let auth = bearer_auth(token);
+        self.with_default_header(auth.name().as_str(), auth.value().as_str())
+    }
+    /// Sends the user agent in every request of the session, e.g. for the CDNs rejecting the
+    /// default one. The files can override it, see [DLFile::with_user_agent]
+    pub fn with_user_agent(self, user_agent: &str) -> Self {
+        self.with_default_header("User-Agent", user_agent)
+    }
+    /// Sets the policy deciding if the URLs of the files without their own are tried
+    /// again when they fail, e.g. [retry::ExponentialBackoff]
+    pub fn with_retry_policy(mut self, policy: impl retry::RetryPolicy + 'static) -> Self {
+        self.retry_policy = Some(Arc::new(policy));
+        self
+    }
+    /// Sets the resolver asked for the size and the hashes of the files missing them
+    /// before downloading, the files it fails for aren't downloaded
+    pub fn with_resolver<F, Fut>(mut self, resolver: F) -> Self
+    where
+        F: Fn(&DLFile) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = std::io::Result<resolver::DLMetadata>> + 'static,
+    {
+        self.resolver = Some(Arc::new(move |file| Box::pin(resolver(file))));
+        self
+    }
+    /// Sets the policy consulted before starting every file with the bytes it will download,
+    /// e.g. to defer big optional files on metered connections. The refused files are skipped
+    pub fn with_metered_policy(
+        mut self,
+        policy: impl Fn(&DLFile, u64) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.metered_policy = Some(Arc::new(policy));
+        self
+    }
+    /// Pauses the downloads while the monitor reports the network offline or after
+    /// the system is resumed from sleep, until the network is usable again
+    pub fn with_network_monitor(
+        mut self,
+        monitor: impl network::NetworkMonitor + 'static,
+        interval: Duration,
+    ) -> Self {
+        self.network_monitor = Some(Arc::new(monitor));
+        self.network_poll_interval = interval;
+        self
+    }
+    /// Cancels the session with [CancelReason::Deadline] once the time passes since its
+    /// start, the files not finished yet fail with [DwldError::Cancelled]
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+    /// Cancels the session with [CancelReason::Signal] when the process receives Ctrl-C or
+    /// `SIGTERM`, instead of ending it. Without a session the signals still end the process
+    #[cfg(feature = "signal")]
+    pub fn with_cancel_on_signal(mut self, cancel: bool) -> Self {
+        self.cancel_on_signal = cancel;
+        self
+    }
+    /// Sets what happens to the other files when one fails, e.g. cancelling the session at
+    /// the first failure with [ErrorPolicy::Abort]
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+    /// Defers the start of all the files, see [DLSchedule]
+    pub fn with_schedule(mut self, schedule: DLSchedule) -> Self {
+        self.schedule = Some(schedule);
+        self
+    }
+    /// Handle to control the download session from other threads, e.g. to cancel it
+    pub fn handle(&self) -> DLHandle {
+        self.handle.clone()
+    }
+    /// Uses the given handle, sharing it with other downloaders
+    pub fn with_handle(mut self, handle: DLHandle) -> Self {
+        self.handle = handle;
+        self
+    }
+    /// Statistics of the mirrors accumulated by the sessions of the downloader
+    pub fn mirror_stats(&self) -> mirror::DLMirrorBook {
+        self.mirrors.clone()
+    }
+    /// Accumulates the statistics of the mirrors in the given book, e.g. loaded from
+    /// previous runs or shared with other downloaders
+    pub fn with_mirror_stats(mut self, mirrors: mirror::DLMirrorBook) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+    /// Keeps the final URLs of the redirections in the given cache, e.g. loaded from previous
+    /// runs or shared with other downloaders. Every session has its own cache if unset
+    pub fn with_redirect_cache(mut self, redirects: redirect_cache::DLRedirectCache) -> Self {
+        self.redirects = Some(redirects);
+        self
+    }
     /// Sets the indicator tracer
     pub fn with_indicator(mut self, indicator: T) -> Self {
         self.indicator_factory = indicator;
         self
     }
-}
-#[cfg(feature = "no_static_client")]
-fn create_client(max_redirections: usize) -> Client {
-    Client::new().with(redirection_middleware::RedirectMiddleware::new(
-        max_redirections,
-    ))
+    /// Task downloading a file of the session
+    fn task<'a>(
+        &'a self,
+        session: &'a Session,
+        dl_file: Cow<'a, DLFile>,
+        failure: Option<DwldError>,
+        stats: Rc<report::TransferStats>,
+    ) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let dl_file = self.file_defaults(dl_file);
+            let dl_file = dl_file.as_ref();
+            // create the progress bar
+            let label = dl_file.label();
+            let state = dl_file.initial_state();
+            let indicator =
+                self.indicator_factory
+                    .create_task_with_state(&label, dl_file.size, state);
+            // the retries signal the progress they discard
+            let mut indicator = indicator::Rewinding::new(indicator, state, dl_file.size);
+            // the files without their metadata aren't downloaded
+            if let Some(e) = failure {
+                indicator.signal(IndicateSignal::Fail(e.to_string()));
+                return Err(e);
+            }
+            // wait for the schedule without taking a permit
+            self.wait_schedule(dl_file, &mut indicator).await;
+            // acquire the permit of the group first, the files waiting for their group
+            // don't hold a global permit
+            let group = dl_file.group.as_ref();
+            let group_permit = match group.and_then(|group| session.groups.get(group)) {
+                Some(semaphore) => Some(semaphore.acquire().await),
+                None => None,
+            };
+            // acquire the semaphore permit
+            let permit = session.semaphore.acquire().await;
+            // reserve the file handles of the download
+            let handles = match &session.handles {
+                Some(budget) => budget.acquire(dl_file.handles()).await,
+                None => Vec::new(),
+            };
+            // don't start new files while paused or after the cancellation
+            futures::future::select(
+                Box::pin(async { while self.handle.resumed().await {} }),
+                Box::pin(self.handle.cancelled()),
+            )
+            .await;
+            if self.handle.is_cancelled() {
+                let e = self.handle.cancelled_error();
+                if let DwldError::Cancelled(reason) = &e {
+                    indicator.signal(IndicateSignal::Cancelled(reason.clone()));
+                }
+                return Err(e);
+            }
+            // ask if the bytes can be downloaded now
+            if let Some(policy) = &self.metered_policy {
+                let bytes = match dl_file.initial_state() {
+                    TaskState::Fresh => Some(dl_file.size),
+                    TaskState::Resumed(offset) => Some(dl_file.size.saturating_sub(offset)),
+                    TaskState::Cached => None,
+                };
+                if let Some(bytes) = bytes.filter(|bytes| !policy(dl_file, *bytes)) {
+                    let reason = format!("Deferred, {} bytes not allowed now", bytes);
+                    indicator.signal(IndicateSignal::Skipped(reason.clone()));
+                    return Ok(DLStatus::Skipped(reason));
+                }
+            }
+            indicator.signal(IndicateSignal::Start());
+            stats.started.set(Some(Instant::now()));
+            // download the file
+            #[cfg(feature = "no_static_client")]
+            let client = self.create_client(&session.redirects);
+            #[cfg(not(feature = "no_static_client"))]
+            let client = session.client.clone();
+            let result = dl_file
+                .download_with(
+                    indicator,
+                    client,
+                    &DownloadContext {
+                        handle: Some(&self.handle),
+                        timeouts: self.timeouts,
+                        overwrite_policy: self.overwrite_policy,
+                        bandwidth: session.bandwidth.as_ref(),
+                        stats: Some(&stats),
+                        mirrors: Some(&self.mirrors),
+                        retry_policy: self.retry_policy.as_deref(),
+                        #[cfg(feature = "ftp")]
+                        ftps: Some(&session.ftps),
+                        dirs: Some(&session.dirs),
+                    },
+                )
+                .await;
+            // a job not marked as finished is only downloaded again
+            #[cfg(feature = "queue")]
+            if let (Some(queue), Ok(_)) = (&self.queue, &result) {
+                let _ = queue.complete_path(&dl_file.path);
+            }
+            // release the file handles and the semaphore permits
+            drop(handles);
+            drop(permit);
+            drop(group_permit);
+            result
+        })
+    }
+    /// Task of a file added to the running session, resolving its metadata first
+    fn added_task<'a>(
+        &'a self,
+        session: &'a Session,
+        dl_file: DLFile,
+        stats: Rc<report::TransferStats>,
+    ) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let resolution = match &self.resolver {
+                Some(resolver) => resolver::resolve_all(&[&dl_file], resolver, 1).await.pop(),
+                None => None,
+            };
+            let (dl_file, failure) = match resolution {
+                Some(Ok(Some(resolved))) => (resolved, None),
+                Some(Err(e)) => (dl_file, Some(e)),
+                _ => (dl_file, None),
+            };
+            self.task(session, Cow::Owned(dl_file), failure, stats)
+                .await
+        })
+    }
+    /// Pauses the session while the network is offline, never completes
+    async fn watch_network(&self, monitor: &Arc<dyn network::NetworkMonitor>, paused: &Cell<bool>) {
+        loop {
+            let (instant, time) = (Instant::now(), SystemTime::now());
+            smol::Timer::after(self.network_poll_interval).await;
+            // the wall clock jumps when the system is resumed from sleep, the
+            // connections may be dead so the network is checked again before resuming
+            let slept =
+                time.elapsed().unwrap_or_default() > instant.elapsed() + self.network_poll_interval;
+            let online = !slept && {
+                let monitor = Arc::clone(monitor);
+                smol::unblock(move || monitor.is_online()).await
+            };
+            if !online && !paused.get() {
+                self.handle.pause();
+                paused.set(true);
+            } else if online && paused.get() {
+                self.handle.resume();
+                paused.set(false);
+            }
+        }
+    }
+    /// Sleeps until the schedules of the batch and the file allow the start
+    async fn wait_schedule(&self, file: &DLFile, indicator: &mut impl Indicator) {
+        loop {
+            let now = SystemTime::now();
+            let wait = [self.schedule, file.schedule]
+                .iter()
+                .flatten()
+                .map(|schedule| schedule.wait_time(now))
+                .max()
+                .unwrap_or_default();
+            if wait.is_zero() || self.handle.is_cancelled() {
+                return;
+            }
+            indicator.signal(IndicateSignal::State("Scheduled".to_string()));
+            futures::future::select(
+                Box::pin(smol::Timer::after(wait)),
+                Box::pin(self.handle.cancelled()),
+            )
+            .await;
+        }
+    }
+    /// Uses the client in the sessions, keeping its connections, e.g. of a
+    /// [service::DownloadService]
+    #[cfg(not(feature = "no_static_client"))]
+    pub(crate) fn with_client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+    /// Client of a session, the given one or a new client
+    #[cfg(not(feature = "no_static_client"))]
+    fn session_client(&self) -> Client {
+        match &self.client {
+            Some(client) => client.clone(),
+            None => self.create_client(&self.redirect_cache()),
+        }
+    }
+    /// Cache of the redirections of a session, shared with the previous sessions only if
+    /// given to the downloader
+    fn redirect_cache(&self) -> redirect_cache::DLRedirectCache {
+        self.redirects.clone().unwrap_or_default()
+    }
+    /// TLS settings of the connections, if they aren't the default ones
+    #[cfg(feature = "tls")]
+    fn tls(&self) -> Option<tls::DLTlsConfig> {
+        match self.accept_invalid_certs {
+            true => {
+                trace_event!(WARN, "the server certificates aren't verified");
+                let tls = self.tls_config.clone().unwrap_or_default();
+                Some(tls.accepting_invalid_certs())
+            }
+            false => self.tls_config.clone(),
+        }
+    }
+    /// Connector of the FTPS connections, with the TLS settings of the HTTPS ones
+    #[cfg(feature = "ftp")]
+    fn ftps_connector(&self) -> async_tls::TlsConnector {
+        #[cfg(feature = "tls")]
+        if let Some(tls) = self.tls() {
+            return tls.client_config().into();
+        }
+        async_tls::TlsConnector::default()
+    }
+    fn create_client(&self, redirects: &redirect_cache::DLRedirectCache) -> Client {
+        #[cfg(feature = "tls")]
+        let client = match self.tls() {
+            Some(tls) => {
+                let config = surf::Config::new().set_tls_config(Some(tls.client_config()));
+                let Ok(client) = Client::try_from(config);
+                client
+            }
+            None => Client::new(),
+        };
+        #[cfg(not(feature = "tls"))]
+        let client = Client::new();
+        // the requests of the cloud storages are addressed and authenticated before the
+        // redirections
+        #[cfg(feature = "s3")]
+        let client = client.with(self.s3_config.clone().unwrap_or_default().middleware());
+        #[cfg(feature = "gcs")]
+        let client = client.with(self.gcs_config.clone().unwrap_or_default().middleware());
+        #[cfg(feature = "azure")]
+        let client = client.with(self.azure_config.clone().unwrap_or_default().middleware());
+        client.with(
+            redirection_middleware::RedirectMiddleware::new(self.max_redirections)
+                .with_rewriter(self.url_rewriter.clone(), self.rewrite_redirects)
+                .with_cache(redirects.clone())
+                .with_policy(self.redirect_policy.clone()),
+        )
+    }
 }
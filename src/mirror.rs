@@ -0,0 +1,139 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use surf::Url;
+
+use crate::{DLStatus, DwldError};
+
+/// Quality of a mirror measured by the downloads from it
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLMirrorStats {
+    /// Downloads finished without errors
+    pub successes: u64,
+    /// Downloads failed because of the mirror, e.g. error status or timeout
+    pub failures: u64,
+    /// Bytes downloaded from the mirror
+    pub bytes: u64,
+    /// Time spent downloading from the mirror
+    pub elapsed: Duration,
+}
+
+impl DLMirrorStats {
+    /// Ratio of the downloads finished without errors, 1 if none was made
+    pub fn success_rate(&self) -> f64 {
+        match self.successes + self.failures {
+            0 => 1.0,
+            attempts => self.successes as f64 / attempts as f64,
+        }
+    }
+    /// Bytes per second downloaded from the mirror
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => self.bytes as f64 / secs,
+        }
+    }
+}
+
+/// Statistics of the mirrors by origin, e.g. `https://example.com`. Its clones share the
+/// statistics, so they can be kept across sessions and loaded from previous runs
+#[derive(Debug, Clone, Default)]
+pub struct DLMirrorBook {
+    stats: Arc<Mutex<HashMap<String, DLMirrorStats>>>,
+}
+
+impl DLMirrorBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Book with the statistics of previous runs
+    pub fn from_stats(stats: HashMap<String, DLMirrorStats>) -> Self {
+        Self {
+            stats: Arc::new(Mutex::new(stats)),
+        }
+    }
+    /// Copy of the statistics of every mirror, e.g. to persist them
+    pub fn stats(&self) -> HashMap<String, DLMirrorStats> {
+        self.lock().clone()
+    }
+    /// Statistics of the mirror of the URL
+    pub fn get(&self, url: &str) -> Option<DLMirrorStats> {
+        self.lock().get(&origin(url)?).cloned()
+    }
+    /// Accounts a download from the URL, the files not downloaded and the errors not
+    /// caused by the mirror are ignored
+    pub(crate) fn record(
+        &self,
+        url: &str,
+        result: &Result<DLStatus, DwldError>,
+        bytes: u64,
+        elapsed: Duration,
+    ) {
+        let success = match result {
+            Ok(status) if status.is_network() => true,
+            Err(e) if is_mirror_error(e) => false,
+            _ => return,
+        };
+        let Some(origin) = origin(url) else {
+            return;
+        };
+        let mut stats = self.lock();
+        let mirror = stats.entry(origin).or_default();
+        if success {
+            mirror.successes += 1;
+        } else {
+            mirror.failures += 1;
+        }
+        mirror.bytes += bytes;
+        mirror.elapsed += elapsed;
+    }
+    /// Sorts the URLs from the best mirror to the worst, by success rate and then by
+    /// throughput. The mirrors without statistics keep their place after the known ones
+    pub fn rank(&self, urls: &mut [String]) {
+        let stats = self.lock();
+        let score = |url: &String| {
+            let mirror = origin(url).and_then(|origin| stats.get(&origin));
+            mirror.map(|mirror| (mirror.success_rate(), mirror.throughput()))
+        };
+        urls.sort_by(|a, b| match (score(a), score(b)) {
+            (Some(a), Some(b)) => b.partial_cmp(&a).unwrap_or(std::cmp::Ordering::Equal),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, DLMirrorStats>> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Checks if the error is caused by the server, so another mirror may work
+pub(crate) fn is_mirror_error(e: &DwldError) -> bool {
+    matches!(
+        e,
+        DwldError::Network(_)
+            | DwldError::Timeout(_)
+            | DwldError::Status { .. }
+            | DwldError::Ftp { .. }
+            | DwldError::Sftp(_)
+            | DwldError::Credentials(_)
+            | DwldError::Source { .. }
+            | DwldError::HashMismatch { .. }
+            | DwldError::ContentRange { .. }
+            | DwldError::Truncated { .. }
+            | DwldError::RedirectLimit { .. }
+            | DwldError::RedirectRefused { .. }
+            | DwldError::InvalidRedirect { .. }
+    )
+}
+
+/// Mirror of the URL, the URLs that can't be parsed don't have one
+pub(crate) fn origin(url: &str) -> Option<String> {
+    let origin = Url::parse(url).ok()?.origin();
+    origin.is_tuple().then(|| origin.ascii_serialization())
+}
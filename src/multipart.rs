@@ -0,0 +1,203 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use smol::{io::AsyncReadExt, lock::Semaphore};
+use surf::Client;
+
+use crate::{
+    DLHandle, DLHashes, DLTimeouts, DwldError, VerifyStrategy,
+    indicator::Indicator,
+    report::TransferStats,
+    storage,
+    throttle::{self, Throttle},
+    timeout::{self, DLTimeoutKind},
+};
+
+/// Part of an object published in several files, joined in order after downloading them
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLPart {
+    /// URL of the part
+    pub url: String,
+    /// Hashes of the part, checked before joining it
+    pub hashes: DLHashes,
+}
+
+impl DLPart {
+    pub fn new(url: &str, hashes: DLHashes) -> Self {
+        Self {
+            url: url.to_string(),
+            hashes,
+        }
+    }
+}
+
+/// Download of the parts of an object
+pub(crate) struct Multipart<'a> {
+    pub client: &'a Client,
+    /// Custom headers of the file, sent with every part
+    pub headers: &'a [(String, String)],
+    pub timeouts: DLTimeouts,
+    pub handle: Option<&'a DLHandle>,
+    pub bandwidth: Option<&'a Throttle>,
+    pub speed_limit: Option<&'a Throttle>,
+    pub verify_strategy: VerifyStrategy,
+    /// Parts downloaded at the same time
+    pub connections: usize,
+    pub stats: Option<&'a TransferStats>,
+}
+
+impl Multipart<'_> {
+    /// Downloads the parts in `paths`, returns the body of the whole object
+    pub async fn download(
+        &self,
+        parts: &[DLPart],
+        paths: &[PathBuf],
+        indicator: &mut impl Indicator,
+    ) -> Result<surf::Body, DwldError> {
+        let semaphore = Semaphore::new(self.connections.max(1));
+        let progress = RefCell::new((vec![0; parts.len()], indicator));
+        let downloads = parts
+            .iter()
+            .zip(paths)
+            .enumerate()
+            .map(|(i, (part, path))| {
+                let semaphore = &semaphore;
+                let progress = &progress;
+                async move {
+                    let _permit = semaphore.acquire().await;
+                    self.part(part, path, |position| {
+                        let mut progress = progress.borrow_mut();
+                        let (positions, indicator) = &mut *progress;
+                        positions[i] = position;
+                        indicator.effect(positions.iter().sum());
+                    })
+                    .await
+                }
+            });
+        futures::future::try_join_all(downloads).await?;
+
+        let mut size = 0;
+        for path in paths {
+            size += fs::metadata(path).map_err(|e| part_error(path, e))?.len();
+        }
+        let parts = PartsReader {
+            paths: paths.iter().cloned().collect(),
+            current: None,
+        };
+        let body = smol::io::BufReader::new(smol::io::AssertAsync::new(parts));
+        Ok(surf::Body::from_reader(body, Some(size as usize)))
+    }
+    /// Downloads and verifies a part, the valid parts of previous attempts are kept
+    async fn part(
+        &self,
+        part: &DLPart,
+        path: &Path,
+        mut progress: impl FnMut(u64),
+    ) -> Result<(), DwldError> {
+        let hashed = !part.hashes.hashes.is_empty();
+        if hashed
+            && part
+                .hashes
+                .verify_file_with(path, self.verify_strategy)
+                .unwrap_or(false)
+        {
+            progress(fs::metadata(path).map_or(0, |metadata| metadata.len()));
+            return Ok(());
+        }
+
+        let request = crate::add_headers(self.client.get(&part.url), self.headers);
+        let mut response =
+            timeout::timeout(self.timeouts.connect, DLTimeoutKind::Connect, request).await??;
+        if !response.status().is_success() {
+            return Err(crate::status_error(&mut response, self.timeouts).await);
+        }
+        let mut body = response.take_body();
+        let expected = body.len().map(|len| len as u64);
+
+        // the parts may be in a temporary directory not created yet
+        if let Some(parent) = path.parent() {
+            storage::create_dir_all(parent).map_err(|e| part_error(path, e))?;
+        }
+        let mut file = File::create(path).map_err(|e| part_error(path, e))?;
+        let mut hasher = part.hashes.hasher();
+        let mut downloaded = 0;
+        let mut buffer = [0; 8192];
+        loop {
+            // stop reading while the session is paused
+            if let Some(handle) = self.handle {
+                while handle.resumed().await {}
+            }
+            let read = AsyncReadExt::read(&mut body, &mut buffer);
+            let n = timeout::timeout(self.timeouts.read, DLTimeoutKind::Read, read)
+                .await?
+                .map_err(|e| DwldError::Network(e.into()))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            file.write_all(&buffer[..n])
+                .map_err(|e| part_error(path, e))?;
+            if let Some(stats) = self.stats {
+                stats.add(n);
+            }
+            throttle::consume(&[self.bandwidth, self.speed_limit], n).await;
+            downloaded += n as u64;
+            progress(downloaded);
+        }
+        file.flush().map_err(|e| part_error(path, e))?;
+        if let Some(expected) = expected.filter(|expected| *expected != downloaded) {
+            return Err(DwldError::Truncated {
+                path: path.to_string_lossy().into_owned(),
+                expected,
+                received: downloaded,
+            });
+        }
+
+        if hashed && !hasher.verify() {
+            let body = crate::peek_file(path);
+            let _ = fs::remove_file(path);
+            return Err(DwldError::HashMismatch {
+                path: path.to_string_lossy().into_owned(),
+                body,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Reads the parts one after another, opening each of them only when it's reached
+struct PartsReader {
+    paths: VecDeque<PathBuf>,
+    current: Option<File>,
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let part = match &mut self.current {
+                Some(part) => part,
+                None => match self.paths.pop_front() {
+                    Some(path) => self.current.insert(File::open(path)?),
+                    None => return Ok(0),
+                },
+            };
+            let n = part.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+            // closes the finished part before opening the next one
+            self.current = None;
+        }
+    }
+}
+
+fn part_error(path: &Path, e: io::Error) -> DwldError {
+    DwldError::io(path.to_string_lossy(), e)
+}
@@ -0,0 +1,44 @@
+use std::{
+    net::{SocketAddr, TcpStream},
+    time::Duration,
+};
+
+/// Source of the connectivity of the system, the downloads are paused while it's offline
+pub trait NetworkMonitor: Send + Sync {
+    /// Checks if the network is usable, it can block
+    fn is_online(&self) -> bool;
+}
+
+impl<F: Fn() -> bool + Send + Sync> NetworkMonitor for F {
+    fn is_online(&self) -> bool {
+        self()
+    }
+}
+
+/// Monitor connecting to a TCP address, e.g. the server of the files
+#[derive(Debug, Clone)]
+pub struct TcpProbe {
+    pub addr: SocketAddr,
+    pub timeout: Duration,
+}
+
+impl TcpProbe {
+    /// Creates a probe of the address with a timeout of 3 seconds
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            timeout: Duration::from_secs(3),
+        }
+    }
+    /// Sets the time waiting for the connection
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl NetworkMonitor for TcpProbe {
+    fn is_online(&self) -> bool {
+        TcpStream::connect_timeout(&self.addr, self.timeout).is_ok()
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{DLFile, decompress::DLDecompressionConfig};
+
+/// Stage of the extraction of a downloaded archive, stored in `<path>.pipeline` so a
+/// restarted session continues it instead of downloading or skipping the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    /// The archive is complete and verified, its extraction didn't finish
+    Downloaded,
+    /// The archive was extracted
+    Extracted,
+}
+
+impl Stage {
+    /// Stage of the archive in the destination, if it's extracted to the same output and
+    /// has the same URL and hashes
+    pub fn load(file: &DLFile, config: &DLDecompressionConfig, local: &Path) -> Option<Self> {
+        let stored = fs::read_to_string(path(local)).ok()?;
+        let (stage, stored_key) = stored.split_once('\n')?;
+        if stored_key != key(file, config) {
+            return None;
+        }
+        match stage {
+            "downloaded" => Some(Stage::Downloaded),
+            "extracted" => Some(Stage::Extracted),
+            _ => None,
+        }
+    }
+    /// Records the stage of the archive in the destination
+    pub fn store(
+        self,
+        file: &DLFile,
+        config: &DLDecompressionConfig,
+        local: &Path,
+    ) -> io::Result<()> {
+        let stage = match self {
+            Stage::Downloaded => "downloaded",
+            Stage::Extracted => "extracted",
+        };
+        fs::write(path(local), format!("{}\n{}", stage, key(file, config)))
+    }
+}
+
+/// Identity of the archive and its extraction
+fn key(file: &DLFile, config: &DLDecompressionConfig) -> String {
+    let mut key = format!("url {}\noutput {}\n", file.url, config.output);
+    for (hash_type, hash) in &file.hashes.hashes {
+        let hash_type = format!("{:?}", hash_type).to_lowercase();
+        let _ = writeln!(key, "{} {}", hash_type, hash);
+    }
+    key
+}
+
+fn path(local: &Path) -> PathBuf {
+    let mut path = local.as_os_str().to_owned();
+    path.push(".pipeline");
+    PathBuf::from(path)
+}
@@ -0,0 +1,203 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::{
+    DLFile, DLSampling, DLSchedule, DLTimeouts, Downloader, DuplicatePolicy, ErrorPolicy,
+    MeteredPolicy, OverwritePolicy, RedirectPolicy, UrlRewriter, indicator::IndicatorFactory,
+    network, resolver, retry, validation,
+};
+
+/// Files and options of a download session, without its running state. A plan can be
+/// cloned, stored and executed several times, every execution with its own handle.
+///
+/// The hooks, like the resolver or the retry policy, aren't serialized
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DownloadPlan {
+    pub files: Vec<DLFile>,
+    pub max_concurrent_downloads: usize,
+    pub max_redirections: usize,
+    /// What to do when several files have the same destination
+    pub duplicate_policy: DuplicatePolicy,
+    /// Hook rewriting the URL of every request
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub url_rewriter: Option<UrlRewriter>,
+    /// Apply the URL rewriter also to the redirect targets
+    pub rewrite_redirects: bool,
+    /// Which redirections are followed
+    pub redirect_policy: RedirectPolicy,
+    /// When the files of the batch can start
+    pub schedule: Option<DLSchedule>,
+    /// Timeouts of the files without their own
+    pub timeouts: DLTimeouts,
+    /// Connectivity checked while downloading, pausing the session when offline
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub network_monitor: Option<Arc<dyn network::NetworkMonitor>>,
+    /// Interval between the connectivity checks
+    pub network_poll_interval: Duration,
+    /// Consulted before starting every file, the refused ones are skipped
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub metered_policy: Option<MeteredPolicy>,
+    /// What happens when the destination of a file already exists
+    pub overwrite_policy: OverwritePolicy,
+    /// Bytes per second shared by all the downloads, unlimited if unset
+    pub max_bandwidth: Option<u64>,
+    /// File handles the downloads keep open at once, only bounded by the concurrency if
+    /// unset
+    pub max_open_files: Option<usize>,
+    /// Directory of the partial files and the decompression staging of the files without
+    /// their own
+    pub temp_dir: Option<String>,
+    /// Directory where the downloads failing their hashes are moved, for the files without
+    /// their own
+    pub quarantine_dir: Option<String>,
+    /// Quick check of the destinations of the files without their own
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub verify_sampling: Option<DLSampling>,
+    /// Decides if the URLs of the files without their own policy are tried again
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub retry_policy: Option<Arc<dyn retry::RetryPolicy>>,
+    /// Connections opened to every host of the batch before downloading
+    pub warm_up: Option<usize>,
+    /// Lookups at once of the hosts of the upcoming files, while the first files download
+    pub dns_prefetch: Option<usize>,
+    /// Headers sent in every request of the session, the headers of the files override them
+    pub default_headers: Vec<(String, String)>,
+    /// Concurrent downloads of every concurrency group, under the global limit
+    pub group_limits: BTreeMap<String, usize>,
+    /// Time after which the session is cancelled, since its start
+    pub deadline: Option<Duration>,
+    /// Cancels the session on Ctrl-C or `SIGTERM`
+    #[cfg(feature = "signal")]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cancel_on_signal: bool,
+    /// What happens to the other files when one fails
+    pub error_policy: ErrorPolicy,
+    /// TLS settings of the connections, e.g. private root certificates
+    #[cfg(feature = "tls")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub tls_config: Option<crate::tls::DLTlsConfig>,
+    /// Accepts invalid server certificates. INSECURE, see
+    /// [Downloader::danger_accept_invalid_certs]
+    #[cfg(feature = "tls")]
+    pub accept_invalid_certs: bool,
+    /// Credentials, region and endpoint of the `s3://` URLs
+    #[cfg(feature = "s3")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub s3_config: Option<crate::s3::DLS3Config>,
+    /// Credentials and endpoint of the `gs://` URLs
+    #[cfg(feature = "gcs")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub gcs_config: Option<crate::gcs::DLGcsConfig>,
+    /// Credentials and endpoint of the Azure Blob URLs
+    #[cfg(feature = "azure")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub azure_config: Option<crate::azure::DLAzureConfig>,
+    /// Completes the size and the hashes of the files missing them before downloading
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub resolver: Option<resolver::DLResolver>,
+}
+
+impl Default for DownloadPlan {
+    fn default() -> Self {
+        Self {
+            files: Vec::new(),
+            max_concurrent_downloads: 5,
+            max_redirections: 5,
+            redirect_policy: RedirectPolicy::default(),
+            duplicate_policy: DuplicatePolicy::default(),
+            url_rewriter: None,
+            rewrite_redirects: false,
+            schedule: None,
+            timeouts: DLTimeouts::default(),
+            network_monitor: None,
+            network_poll_interval: Duration::from_secs(5),
+            metered_policy: None,
+            overwrite_policy: OverwritePolicy::default(),
+            max_bandwidth: None,
+            max_open_files: None,
+            temp_dir: None,
+            quarantine_dir: None,
+            verify_sampling: None,
+            retry_policy: None,
+            warm_up: None,
+            dns_prefetch: None,
+            default_headers: Vec::new(),
+            group_limits: BTreeMap::new(),
+            deadline: None,
+            #[cfg(feature = "signal")]
+            cancel_on_signal: false,
+            error_policy: ErrorPolicy::default(),
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            #[cfg(feature = "tls")]
+            accept_invalid_certs: false,
+            #[cfg(feature = "s3")]
+            s3_config: None,
+            #[cfg(feature = "gcs")]
+            gcs_config: None,
+            #[cfg(feature = "azure")]
+            azure_config: None,
+            resolver: None,
+        }
+    }
+}
+
+impl DownloadPlan {
+    /// New downloader executing the plan, with a new handle
+    pub fn downloader<T: IndicatorFactory>(&self) -> Downloader<T> {
+        Downloader::from_plan(self.clone())
+    }
+    /// Files of the plan that differ from the files of the previous one, matched by
+    /// destination. A file changes when its URL, hashes or size change
+    pub fn diff(&self, previous: &DownloadPlan) -> DLPlanDiff {
+        fn targets(plan: &DownloadPlan) -> HashMap<PathBuf, &DLFile> {
+            let files = plan.files.iter();
+            files
+                .map(|file| (validation::target(&file.path), file))
+                .collect()
+        }
+        let (current, old) = (targets(self), targets(previous));
+        let mut diff = DLPlanDiff::default();
+        for file in &self.files {
+            match old.get(&validation::target(&file.path)) {
+                None => diff.added.push(file.path.clone()),
+                Some(old) if !validation::same_content(old, file) || old.size != file.size => {
+                    diff.changed.push(file.path.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for file in &previous.files {
+            if !current.contains_key(&validation::target(&file.path)) {
+                diff.removed.push(file.path.clone());
+            }
+        }
+        diff
+    }
+}
+
+/// Destinations of the files that differ between two plans
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLPlanDiff {
+    /// Files only in the new plan
+    pub added: Vec<String>,
+    /// Files only in the previous plan
+    pub removed: Vec<String>,
+    /// Files in both plans with different content
+    pub changed: Vec<String>,
+}
+
+impl DLPlanDiff {
+    /// Checks if the plans download the same files
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
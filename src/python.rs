@@ -0,0 +1,294 @@
+//! Python bindings, built with `maturin` and the `python` feature:
+//!
+//! ```python
+//! import dwldutil
+//!
+//! file = dwldutil.File("https://example.com/model.bin", "model.bin",
+//!                      hashes=dwldutil.Hashes().sha256("..."))
+//! downloader = dwldutil.Downloader(max_concurrent=4, retries=3)
+//! downloader.add_file(file)
+//! for report in downloader.start(progress=lambda path, done, total: print(path, done, total)):
+//!     print(report.path, report.status)
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+
+use crate::{
+    CancelReason, DLFile, DLHandle, DLHashes, DLStatus, DownloadPlan, Downloader, DwldError,
+    indicator::{IndicateSignal, Indicator, IndicatorFactory},
+    report::DLFileReport,
+    retry::ExponentialBackoff,
+};
+
+create_exception!(dwldutil, DownloadError, PyException, "A download failed");
+
+/// Minimum time between the calls to the progress callback of a file
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+/// Time between the checks of the signals of Python, e.g. Ctrl-C, while downloading
+const SIGNALS_INTERVAL: Duration = Duration::from_millis(50);
+
+fn download_error(error: DwldError) -> PyErr {
+    DownloadError::new_err(error.to_string())
+}
+
+/// Expected hashes of a file
+#[pyclass(name = "Hashes", module = "dwldutil", skip_from_py_object)]
+#[derive(Clone, Default)]
+struct PyHashes(DLHashes);
+
+#[pymethods]
+impl PyHashes {
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    fn sha1(&self, hash: &str) -> Self {
+        Self(self.0.clone().sha1(hash))
+    }
+    fn sha256(&self, hash: &str) -> Self {
+        Self(self.0.clone().sha256(hash))
+    }
+    fn sha512(&self, hash: &str) -> Self {
+        Self(self.0.clone().sha512(hash))
+    }
+    fn md5(&self, hash: &str) -> Self {
+        Self(self.0.clone().md5(hash))
+    }
+    /// Checks if the file matches the hashes
+    fn verify_file(&self, py: Python<'_>, path: &str) -> bool {
+        py.detach(|| self.0.verify_file(path))
+    }
+}
+
+/// File to download
+#[pyclass(name = "File", module = "dwldutil", skip_from_py_object)]
+#[derive(Clone)]
+struct PyFile(DLFile);
+
+#[pymethods]
+impl PyFile {
+    #[new]
+    #[pyo3(signature = (url, path, size = 0, hashes = None, mirrors = Vec::new(), headers = HashMap::new(), cas = None))]
+    fn new(
+        url: &str,
+        path: &str,
+        size: u64,
+        hashes: Option<PyRef<'_, PyHashes>>,
+        mirrors: Vec<String>,
+        headers: HashMap<String, String>,
+        cas: Option<&str>,
+    ) -> PyResult<Self> {
+        let mut file = DLFile::new()
+            .with_url(url)
+            .with_path(path)
+            .with_size(size)
+            .with_mirrors(mirrors);
+        if let Some(hashes) = hashes {
+            file = file.with_hashes(hashes.0.clone());
+        }
+        for (name, value) in &headers {
+            file = file.with_header(name, value);
+        }
+        match cas {
+            #[cfg(feature = "cas")]
+            Some(cas) => file = file.with_cas(crate::cas::DLStorage::new(cas)),
+            #[cfg(not(feature = "cas"))]
+            Some(_) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(
+                    "dwldutil was built without the cas feature",
+                ));
+            }
+            None => {}
+        }
+        Ok(Self(file))
+    }
+    #[getter]
+    fn url(&self) -> &str {
+        &self.0.url
+    }
+    #[getter]
+    fn path(&self) -> &str {
+        &self.0.path
+    }
+    #[getter]
+    fn size(&self) -> u64 {
+        self.0.size
+    }
+}
+
+/// Outcome of a downloaded file
+#[pyclass(name = "FileReport", module = "dwldutil", get_all, skip_from_py_object)]
+struct PyFileReport {
+    path: String,
+    /// `downloaded`, `cached` or `skipped: <reason>`
+    status: String,
+    bytes: u64,
+    elapsed: f64,
+    retries: u32,
+}
+
+impl From<DLFileReport> for PyFileReport {
+    fn from(report: DLFileReport) -> Self {
+        let status = match report.status {
+            DLStatus::Downloaded => "downloaded".to_string(),
+            DLStatus::Cached => "cached".to_string(),
+            DLStatus::Skipped(reason) => format!("skipped: {}", reason),
+            DLStatus::Failed(error) => format!("failed: {}", error),
+        };
+        Self {
+            path: report.path,
+            status,
+            bytes: report.bytes,
+            elapsed: report.elapsed.as_secs_f64(),
+            retries: report.retries,
+        }
+    }
+}
+
+/// Downloader of a batch of files
+#[pyclass(name = "Downloader", module = "dwldutil")]
+struct PyDownloader(DownloadPlan);
+
+#[pymethods]
+impl PyDownloader {
+    #[new]
+    #[pyo3(signature = (max_concurrent = 10, retries = 0))]
+    fn new(max_concurrent: usize, retries: u32) -> Self {
+        let mut plan = DownloadPlan {
+            max_concurrent_downloads: max_concurrent.max(1),
+            ..Default::default()
+        };
+        if retries > 0 {
+            plan.retry_policy = Some(Arc::new(ExponentialBackoff::new(retries)));
+        }
+        Self(plan)
+    }
+    fn add_file(&mut self, file: PyRef<'_, PyFile>) {
+        self.0.files.push(file.0.clone());
+    }
+    /// Downloads the files, calling `progress(path, downloaded, total)` while they
+    /// download. Raises `DownloadError` if any file fails, Ctrl-C cancels the downloads
+    #[pyo3(signature = (progress = None))]
+    fn start(&self, py: Python<'_>, progress: Option<Py<PyAny>>) -> PyResult<Vec<PyFileReport>> {
+        let plan = self.0.clone();
+        let handle = DLHandle::new();
+        let session = handle.clone();
+        let factory = PyProgress(progress.map(Arc::new));
+        let worker = thread::Builder::new()
+            .name("dwldutil-python".to_string())
+            .spawn(move || {
+                Downloader::from_plan(plan)
+                    .with_indicator(factory)
+                    .with_handle(session)
+                    .start()
+            })?;
+        // the signals are only handled by the thread holding the GIL
+        while !worker.is_finished() {
+            if let Err(e) = py.check_signals() {
+                handle.cancel_with(CancelReason::Signal);
+                let _ = py.detach(|| worker.join());
+                return Err(e);
+            }
+            py.detach(|| thread::sleep(SIGNALS_INTERVAL));
+        }
+        match worker.join() {
+            Ok(Ok(report)) => Ok(report.files.into_iter().map(Into::into).collect()),
+            Ok(Err(e)) => Err(download_error(e)),
+            Err(_) => Err(DownloadError::new_err("the download thread panicked")),
+        }
+    }
+}
+
+/// Indicator calling the progress callback of Python
+#[derive(Default)]
+struct PyProgress(Option<Arc<Py<PyAny>>>);
+
+impl IndicatorFactory for PyProgress {
+    fn create_task(&self, name: &str, size: u64) -> impl Indicator {
+        PyProgressChild {
+            callback: self.0.clone(),
+            name: name.to_string(),
+            size,
+            position: 0,
+            reported: None,
+        }
+    }
+}
+
+struct PyProgressChild {
+    callback: Option<Arc<Py<PyAny>>>,
+    name: String,
+    size: u64,
+    position: u64,
+    /// Time and position of the last call
+    reported: Option<(Instant, u64)>,
+}
+
+impl PyProgressChild {
+    fn report(&mut self) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        self.reported = Some((Instant::now(), self.position));
+        let failed = Python::attach(|py| {
+            let args = (self.name.as_str(), self.position, self.size);
+            let result = callback.call1(py, args);
+            result.map_err(|e| e.print(py)).is_err()
+        });
+        // a failing callback doesn't stop the download, it's not called again
+        if failed {
+            self.callback = None;
+        }
+    }
+}
+
+impl Indicator for PyProgressChild {
+    fn effect(&mut self, position: u64) {
+        self.position = position;
+        // the chunks are small, calling Python for every one would slow the download
+        let due = self
+            .reported
+            .is_none_or(|(reported, _)| reported.elapsed() >= PROGRESS_INTERVAL);
+        if due || position == self.size {
+            self.report();
+        }
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        match signal {
+            IndicateSignal::Size(size) => self.size = size,
+            // the corrected position is reported at once
+            IndicateSignal::Reset() | IndicateSignal::Rewind(_) => self.reported = None,
+            // files of unknown size end with the bytes written
+            IndicateSignal::Success() => {
+                if self.size == 0 {
+                    self.size = self.position;
+                }
+                if self
+                    .reported
+                    .is_none_or(|(_, position)| position != self.position)
+                {
+                    self.report();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Parallel downloads verified by their hashes
+#[pymodule]
+fn dwldutil(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyHashes>()?;
+    m.add_class::<PyFile>()?;
+    m.add_class::<PyFileReport>()?;
+    m.add_class::<PyDownloader>()?;
+    m.add("DownloadError", m.py().get_type::<DownloadError>())?;
+    Ok(())
+}
@@ -0,0 +1,55 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{hash::DLDigest, storage};
+
+/// Download failing its hashes, kept to report the mirror serving it
+pub(crate) struct Evidence<'a> {
+    /// URL the file was downloaded from
+    pub url: &'a str,
+    /// Destination of the file
+    pub path: &'a str,
+    pub digests: &'a [DLDigest],
+    /// When the download started
+    pub started: SystemTime,
+}
+
+impl Evidence<'_> {
+    /// Moves the downloaded file into the quarantine directory, next to a `.meta` file with
+    /// the URL, the expected and the actual hashes and the timestamps
+    pub fn quarantine(&self, dir: &Path, file: &Path) -> io::Result<()> {
+        storage::create_dir_all(dir)?;
+        let now = SystemTime::now();
+        let name = Path::new(self.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "download".to_string());
+        // every failure is kept, the same file may fail in several mirrors
+        let name = format!("{}-{}", name, millis(now));
+        let target = dir.join(&name);
+        storage::move_file(file, &target)?;
+
+        let mut meta = Vec::new();
+        writeln!(meta, "url {}", self.url)?;
+        writeln!(meta, "path {}", self.path)?;
+        for digest in self.digests {
+            let hash_type = format!("{:?}", digest.hash_type).to_lowercase();
+            writeln!(meta, "expected {} {}", hash_type, digest.expected)?;
+            writeln!(meta, "actual {} {}", hash_type, digest.actual)?;
+        }
+        writeln!(meta, "started {}", millis(self.started) / 1000)?;
+        writeln!(meta, "quarantined {}", millis(now) / 1000)?;
+        fs::write(dir.join(format!("{}.meta", name)), meta)
+    }
+}
+
+/// Milliseconds since the epoch
+fn millis(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+}
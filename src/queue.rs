@@ -0,0 +1,140 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::DLFile;
+
+/// Durable queue of downloads, every file is written to an append-only log before
+/// being scheduled, so accepted jobs survive a crash of the process.
+///
+/// The `on_download` events and the fields skipped by serde aren't persisted
+#[derive(Clone)]
+pub struct DLQueue {
+    inner: Arc<Mutex<QueueLog>>,
+}
+
+struct QueueLog {
+    path: PathBuf,
+    log: File,
+    next_id: u64,
+    pending: Vec<(u64, DLFile)>,
+}
+
+/// Record of the log, one JSON object per line
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record<F> {
+    Add { id: u64, file: F },
+    Done { id: u64 },
+}
+
+impl DLQueue {
+    /// Opens the queue, replaying the log to recover the pending files
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut pending: Vec<(u64, DLFile)> = Vec::new();
+        let mut next_id = 0;
+        if path.exists() {
+            let lines = BufReader::new(File::open(&path)?)
+                .lines()
+                .collect::<io::Result<Vec<_>>>()?;
+            let last = lines.len().saturating_sub(1);
+            for (i, line) in lines.iter().enumerate() {
+                match serde_json::from_str::<Record<DLFile>>(line) {
+                    Ok(Record::Add { id, file }) => {
+                        next_id = next_id.max(id + 1);
+                        pending.push((id, file));
+                    }
+                    Ok(Record::Done { id }) => pending.retain(|(pending, _)| *pending != id),
+                    // a crash can leave the last record half written
+                    Err(_) if i == last => {}
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                }
+            }
+        }
+        let log = compact(&path, &pending)?;
+        Ok(Self {
+            inner: Arc::new(Mutex::new(QueueLog {
+                path,
+                log,
+                next_id,
+                pending,
+            })),
+        })
+    }
+    /// Writes the file to the log, returns the id of the job
+    pub fn enqueue(&self, file: DLFile) -> io::Result<u64> {
+        let mut queue = self.lock();
+        let id = queue.next_id;
+        queue.append(&Record::Add { id, file: &file })?;
+        queue.next_id += 1;
+        queue.pending.push((id, file));
+        Ok(id)
+    }
+    /// Marks the job as finished, it won't be recovered anymore
+    pub fn complete(&self, id: u64) -> io::Result<()> {
+        let mut queue = self.lock();
+        if !queue.pending.iter().any(|(pending, _)| *pending == id) {
+            return Ok(());
+        }
+        queue.append(&Record::<&DLFile>::Done { id })?;
+        queue.pending.retain(|(pending, _)| *pending != id);
+        Ok(())
+    }
+    /// Marks every job downloading to the path as finished
+    pub(crate) fn complete_path(&self, path: &str) -> io::Result<()> {
+        let ids: Vec<u64> = self
+            .lock()
+            .pending
+            .iter()
+            .filter(|(_, file)| file.path == path)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter().try_for_each(|id| self.complete(id))
+    }
+    /// Files not finished yet, with their ids
+    pub fn pending(&self) -> Vec<(u64, DLFile)> {
+        self.lock().pending.clone()
+    }
+    /// Rewrites the log keeping only the pending files
+    pub fn compact(&self) -> io::Result<()> {
+        let mut queue = self.lock();
+        queue.log = compact(&queue.path, &queue.pending)?;
+        Ok(())
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueLog> {
+        // the log is only written with complete records, a poisoned lock is still usable
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl QueueLog {
+    fn append(&mut self, record: &Record<&DLFile>) -> io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        self.log.write_all(&line)?;
+        // the job is accepted only when it's on disk
+        self.log.sync_data()
+    }
+}
+
+/// Writes a new log with the pending files, replacing the old one atomically
+fn compact(path: &Path, pending: &[(u64, DLFile)]) -> io::Result<File> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    for (id, file) in pending {
+        let mut line = serde_json::to_vec(&Record::Add { id: *id, file })?;
+        line.push(b'\n');
+        tmp.write_all(&line)?;
+    }
+    tmp.sync_all()?;
+    crate::storage::rename(&tmp_path, path)?;
+    OpenOptions::new().append(true).open(path)
+}
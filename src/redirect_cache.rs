@@ -0,0 +1,49 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use surf::Url;
+
+/// Final URLs of the redirected URLs, so the files behind the same redirecting endpoint
+/// don't follow its redirections again. Its clones share the entries, so they can be kept
+/// across sessions and loaded from previous runs
+#[derive(Debug, Clone, Default)]
+pub struct DLRedirectCache {
+    targets: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl DLRedirectCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Cache with the redirections resolved by previous runs
+    pub fn from_targets(targets: HashMap<String, String>) -> Self {
+        Self {
+            targets: Arc::new(Mutex::new(targets)),
+        }
+    }
+    /// Copy of the final URL of every redirected URL, e.g. to persist them
+    pub fn targets(&self) -> HashMap<String, String> {
+        self.lock().clone()
+    }
+    /// Final URL of the redirections of the URL
+    pub fn get(&self, url: &Url) -> Option<Url> {
+        let target = self.lock().get(url.as_str()).cloned()?;
+        Url::parse(&target).ok()
+    }
+    /// Forgets every redirection
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+    pub(crate) fn insert(&self, url: &Url, target: &Url) {
+        self.lock().insert(url.to_string(), target.to_string());
+    }
+    /// Forgets the redirection of the URL, e.g. its target expired
+    pub(crate) fn remove(&self, url: &Url) {
+        self.lock().remove(url.as_str());
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        self.targets.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -1,33 +1,247 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use surf::http::Method;
 use surf::middleware::{Middleware, Next};
 use surf::{Client, Request, Response, Result, StatusCode, Url};
 
+use crate::redirect_cache::DLRedirectCache;
+
+/// Hook rewriting the URL of a request before sending it
+pub type UrlRewriter = Arc<dyn Fn(&Url) -> Url + Send + Sync>;
+
+/// Headers with credentials, not sent to the redirections to other origins
+const CREDENTIAL_HEADERS: [&str; 2] = ["Authorization", "Cookie"];
+
+/// The redirections of a request exceeded the limit
+#[derive(Debug)]
+pub(crate) struct RedirectLimit {
+    /// URLs requested, from the first one to the last redirection
+    pub chain: Vec<Url>,
+    /// Status of the last response
+    pub status: StatusCode,
+}
+
+impl fmt::Display for RedirectLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Too many redirects, {} URLs visited", self.chain.len())
+    }
+}
+
+impl Error for RedirectLimit {}
+
+/// The redirect policy refused a redirection
+#[derive(Debug)]
+pub(crate) struct RedirectRefused {
+    /// Target of the refused redirection
+    pub url: Url,
+}
+
+impl fmt::Display for RedirectRefused {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Redirect to {} refused", self.url)
+    }
+}
+
+impl Error for RedirectRefused {}
+
+/// The `Location` of a redirection isn't a valid URL
+#[derive(Debug)]
+pub(crate) struct InvalidLocation {
+    pub location: String,
+}
+
+impl fmt::Display for InvalidLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid redirect location {}", self.location)
+    }
+}
+
+impl Error for InvalidLocation {}
+
+/// Which redirections are followed. The credentials are never sent to the redirections to
+/// other origins
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum RedirectPolicy {
+    /// Follow every redirection
+    #[default]
+    Any,
+    /// Only follow the redirections to the origin of the requested URL
+    SameOrigin,
+    /// Only follow the redirections to the origin of the requested URL and to the hosts,
+    /// e.g. the CDN of the server
+    Hosts(Vec<String>),
+}
+
+impl RedirectPolicy {
+    /// Checks if the redirection of the request to the URL is followed
+    pub fn allows(&self, request: &Url, url: &Url) -> bool {
+        match self {
+            RedirectPolicy::Any => true,
+            _ if url.origin() == request.origin() => true,
+            RedirectPolicy::SameOrigin => false,
+            RedirectPolicy::Hosts(hosts) => url
+                .host_str()
+                .is_some_and(|host| hosts.iter().any(|h| h.eq_ignore_ascii_case(host))),
+        }
+    }
+}
+
 pub struct RedirectMiddleware {
     max_redirects: usize,
+    rewriter: Option<UrlRewriter>,
+    rewrite_redirects: bool,
+    cache: Option<DLRedirectCache>,
+    policy: RedirectPolicy,
 }
 
 impl RedirectMiddleware {
     pub fn new(max_redirects: usize) -> Self {
-        Self { max_redirects }
+        Self {
+            max_redirects,
+            rewriter: None,
+            rewrite_redirects: false,
+            cache: None,
+            policy: RedirectPolicy::Any,
+        }
     }
-}
-
-#[surf::utils::async_trait]
-impl Middleware for RedirectMiddleware {
-    async fn handle(&self, req: Request, client: Client, _: Next<'_>) -> Result<Response> {
+    pub fn with_rewriter(mut self, rewriter: Option<UrlRewriter>, rewrite_redirects: bool) -> Self {
+        self.rewriter = rewriter;
+        self.rewrite_redirects = rewrite_redirects;
+        self
+    }
+    /// Only follows the redirections allowed by the policy
+    pub fn with_policy(mut self, policy: RedirectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    /// Remembers the final URL of the redirected requests in the cache
+    pub fn with_cache(mut self, cache: DLRedirectCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    fn rewrite(&self, req: &mut Request) {
+        if let Some(rewriter) = &self.rewriter {
+            let url = rewriter(req.url());
+            *AsMut::<surf::http::Request>::as_mut(req).url_mut() = url;
+        }
+    }
+    /// Sends the request, following its redirections
+    async fn follow(&self, req: Request, client: Client) -> Result<Response> {
         let mut req = req;
-        for _ in 0..self.max_redirects {
+        let original = req.url().clone();
+        if let Some(res) = self.cached(&req, &original, &client).await? {
+            return Ok(res);
+        }
+        self.rewrite(&mut req);
+        let mut chain = Vec::new();
+        loop {
+            chain.push(req.url().clone());
             let res = client.send(req.clone()).await?;
+            trace_event!(
+                DEBUG,
+                url = %req.url(),
+                redirects = chain.len() - 1,
+                status = %res.status(),
+                "response"
+            );
             if res.status().is_redirection() {
                 if let Some(location) = res.header("Location") {
-                    req = Request::new(req.method(), Url::parse(location.last().as_str()).unwrap());
+                    if chain.len() > self.max_redirects {
+                        let status = res.status();
+                        trace_event!(WARN, redirects = chain.len() - 1, "too many redirects");
+                        return Err(surf::Error::new(status, RedirectLimit { chain, status }));
+                    }
+                    // keep the headers of the request, e.g. Range
+                    // the relative locations are resolved against the requested URL
+                    let location = location.last().as_str();
+                    let Ok(url) = req.url().join(location) else {
+                        let location = location.to_string();
+                        trace_event!(WARN, location = %location, "invalid redirect location");
+                        let error = InvalidLocation { location };
+                        return Err(surf::Error::new(res.status(), error));
+                    };
+                    if !self.policy.allows(&original, &url) {
+                        trace_event!(WARN, from = %req.url(), to = %url, "redirect refused");
+                        return Err(surf::Error::new(res.status(), RedirectRefused { url }));
+                    }
+                    trace_event!(DEBUG, from = %req.url(), to = %url, "redirect");
+                    // the credentials are only sent to the origin they were given for
+                    if url.origin() != req.url().origin() {
+                        for header in CREDENTIAL_HEADERS {
+                            req.remove_header(header);
+                        }
+                    }
+                    *AsMut::<surf::http::Request>::as_mut(&mut req).url_mut() = url;
+                    if self.rewrite_redirects {
+                        self.rewrite(&mut req);
+                    }
                     continue;
                 }
             }
+            if let Some(cache) = &self.cache {
+                if chain.len() > 1 && usable(res.status()) {
+                    cache.insert(&original, req.url());
+                }
+            }
             return Ok(res);
         }
-        Err(surf::Error::from_str(
-            StatusCode::TooManyRequests,
-            "Too many redirects, Increase max_redirections in DLStartConfig",
-        ))
+    }
+    /// Sends the request to the final URL of its cached redirections. The redirection is
+    /// forgotten if its target fails, e.g. an expired signed URL, to follow it again
+    async fn cached(
+        &self,
+        req: &Request,
+        original: &Url,
+        client: &Client,
+    ) -> Result<Option<Response>> {
+        let Some(cache) = &self.cache else {
+            return Ok(None);
+        };
+        // the cache may come from a session with another policy
+        let Some(url) = cache.get(original) else {
+            return Ok(None);
+        };
+        if !self.policy.allows(original, &url) {
+            return Ok(None);
+        }
+        let mut cached = req.clone();
+        if url.origin() != original.origin() {
+            for header in CREDENTIAL_HEADERS {
+                cached.remove_header(header);
+            }
+        }
+        *AsMut::<surf::http::Request>::as_mut(&mut cached).url_mut() = url;
+        let mut res = client.send(cached.clone()).await?;
+        trace_event!(
+            DEBUG,
+            from = %original,
+            to = %cached.url(),
+            status = %res.status(),
+            "cached redirect"
+        );
+        if usable(res.status()) {
+            return Ok(Some(res));
+        }
+        cache.remove(original);
+        // the body of the failure is discarded to reuse the connection, the responses to
+        // HEAD don't have one
+        if req.method() != Method::Head {
+            let _ = res.body_bytes().await;
+        }
+        Ok(None)
+    }
+}
+
+/// Checks if the status is the response of the final URL of the redirections
+fn usable(status: StatusCode) -> bool {
+    status.is_success() || status == StatusCode::NotModified
+}
+
+#[surf::utils::async_trait]
+impl Middleware for RedirectMiddleware {
+    async fn handle(&self, req: Request, client: Client, _: Next<'_>) -> Result<Response> {
+        instrumented!(self.follow(req, client), "request", url = %req.url()).await
     }
 }
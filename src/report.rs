@@ -0,0 +1,230 @@
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use crate::{DwldError, handle::CancelReason};
+
+/// Outcome of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DLStatus {
+    /// The file was downloaded from the network
+    Downloaded,
+    /// The file was already in the CAS storage
+    Cached,
+    /// The file was not downloaded, with the reason
+    Skipped(String),
+    /// The file failed or was cancelled, with the message of the error
+    Failed(String),
+}
+
+impl DLStatus {
+    /// Checks if the file was transferred over the network
+    pub fn is_network(&self) -> bool {
+        matches!(self, DLStatus::Downloaded)
+    }
+}
+
+/// Event of a file finishing, sent while the session runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum DownloadEvent {
+    /// The file finished, downloaded or taken from the CAS storage
+    Completed {
+        path: String,
+        metadata: BTreeMap<String, String>,
+        status: DLStatus,
+    },
+    /// The file failed, with the code and the message of the error
+    Failed {
+        path: String,
+        metadata: BTreeMap<String, String>,
+        code: String,
+        error: String,
+    },
+    /// The file was not downloaded, with the reason
+    Skipped {
+        path: String,
+        metadata: BTreeMap<String, String>,
+        reason: String,
+    },
+    /// The session was cancelled before the file finished, with the reason
+    Cancelled {
+        path: String,
+        metadata: BTreeMap<String, String>,
+        reason: CancelReason,
+    },
+}
+
+impl DownloadEvent {
+    pub(crate) fn new(
+        path: &str,
+        metadata: &BTreeMap<String, String>,
+        result: &Result<DLStatus, DwldError>,
+    ) -> Self {
+        let (path, metadata) = (path.to_string(), metadata.clone());
+        match result {
+            Ok(DLStatus::Skipped(reason)) => DownloadEvent::Skipped {
+                path,
+                metadata,
+                reason: reason.clone(),
+            },
+            Ok(status) => DownloadEvent::Completed {
+                path,
+                metadata,
+                status: status.clone(),
+            },
+            Err(DwldError::Cancelled(reason)) => DownloadEvent::Cancelled {
+                path,
+                metadata,
+                reason: reason.clone(),
+            },
+            Err(e) => DownloadEvent::Failed {
+                path,
+                metadata,
+                code: e.code().to_string(),
+                error: e.to_string(),
+            },
+        }
+    }
+    /// Destination of the file
+    pub fn path(&self) -> &str {
+        match self {
+            DownloadEvent::Completed { path, .. }
+            | DownloadEvent::Failed { path, .. }
+            | DownloadEvent::Skipped { path, .. }
+            | DownloadEvent::Cancelled { path, .. } => path,
+        }
+    }
+    /// Metadata of the file, see [crate::DLFile::with_metadata]
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        match self {
+            DownloadEvent::Completed { metadata, .. }
+            | DownloadEvent::Failed { metadata, .. }
+            | DownloadEvent::Skipped { metadata, .. }
+            | DownloadEvent::Cancelled { metadata, .. } => metadata,
+        }
+    }
+}
+
+/// Summary of a session, with an entry for every file including the failed ones
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DownloadReport {
+    /// Report of every file of the session
+    pub files: Vec<DLFileReport>,
+    /// Duration of the whole session
+    pub elapsed: Duration,
+}
+
+impl DownloadReport {
+    /// Bytes transferred over the network by all the files
+    pub fn bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.bytes).sum()
+    }
+    /// Bytes per second transferred during the session
+    pub fn throughput(&self) -> f64 {
+        match self.elapsed.as_secs_f64() {
+            0.0 => 0.0,
+            secs => self.bytes() as f64 / secs,
+        }
+    }
+    /// Files that failed or were cancelled
+    pub fn failed(&self) -> impl Iterator<Item = &DLFileReport> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.status, DLStatus::Failed(_)))
+    }
+}
+
+/// Outcome of a file in a [DownloadReport]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLFileReport {
+    pub path: String,
+    /// Metadata of the file, see [crate::DLFile::with_metadata]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub metadata: BTreeMap<String, String>,
+    pub status: DLStatus,
+    /// Code of the error of the failed files, see [DwldError::code]
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub code: Option<String>,
+    /// Bytes transferred over the network, including the companions
+    pub bytes: u64,
+    /// Time since the file started, without the time waiting for its turn
+    pub elapsed: Duration,
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Phases of the request that downloaded the file, to tell a slow connection setup from
+    /// a slow transfer
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timings: DLTimings,
+}
+
+/// Duration of the phases of a request, the ones the HTTP client doesn't expose are unset.
+/// The HTTP/1 client of surf hides its connections, so only the time to the first byte and
+/// the transfer are measured: `dns`, `connect` and `tls` are always `None` and their time
+/// is part of `ttfb` when the connection wasn't reused from the pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLTimings {
+    /// Resolution of the host name, `None` under surf
+    pub dns: Option<Duration>,
+    /// TCP connection to the server, `None` under surf
+    pub connect: Option<Duration>,
+    /// TLS handshake, `None` under surf
+    pub tls: Option<Duration>,
+    /// Time until the headers of the response. Includes the DNS, connect and TLS phases
+    /// when the connection wasn't reused, there's no way to tell them apart under surf
+    pub ttfb: Option<Duration>,
+    /// Reading and writing the body
+    pub transfer: Option<Duration>,
+}
+
+/// Counters of a file while it downloads
+#[derive(Debug, Default)]
+pub(crate) struct TransferStats {
+    pub bytes: Cell<u64>,
+    pub retries: Cell<u32>,
+    pub started: Cell<Option<Instant>>,
+    pub elapsed: Cell<Duration>,
+    pub timings: Cell<DLTimings>,
+}
+
+impl TransferStats {
+    /// Counts the bytes read from the network
+    pub fn add(&self, bytes: usize) {
+        self.bytes.set(self.bytes.get() + bytes as u64);
+    }
+    /// Stops the clock of the file
+    pub fn finish(&self) {
+        if let Some(started) = self.started.get() {
+            self.elapsed.set(started.elapsed());
+        }
+    }
+    /// Report of the finished file
+    pub fn report(
+        &self,
+        path: &str,
+        metadata: &BTreeMap<String, String>,
+        status: DLStatus,
+    ) -> DLFileReport {
+        DLFileReport {
+            path: path.to_string(),
+            metadata: metadata.clone(),
+            status,
+            code: None,
+            bytes: self.bytes.get(),
+            elapsed: self.elapsed.get(),
+            retries: self.retries.get(),
+            timings: self.timings.get(),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::{future::Future, io, pin::Pin, sync::Arc};
+
+use smol::lock::Semaphore;
+
+use crate::{DLFile, DLHashes, DwldError};
+
+/// Resolver of the size and the hashes of the files missing them, e.g. asking an index API
+pub type DLResolver =
+    Arc<dyn Fn(&DLFile) -> Pin<Box<dyn Future<Output = io::Result<DLMetadata>>>> + Send + Sync>;
+
+/// Metadata of a file found by a [DLResolver], only the missing values are used
+#[derive(Debug, Clone, Default)]
+pub struct DLMetadata {
+    pub size: Option<u64>,
+    pub hashes: DLHashes,
+}
+
+impl DLMetadata {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.size = Some(size);
+        self
+    }
+    pub fn with_hashes(mut self, hashes: DLHashes) -> Self {
+        self.hashes = hashes;
+        self
+    }
+}
+
+/// Resolves the files missing their size or their hashes, with up to `concurrency` at once.
+/// The complete files are kept as they are
+pub(crate) async fn resolve_all(
+    files: &[&DLFile],
+    resolver: &DLResolver,
+    concurrency: usize,
+) -> Vec<Result<Option<DLFile>, DwldError>> {
+    let semaphore = Semaphore::new(concurrency.max(1));
+    let resolutions = files.iter().map(|file| {
+        let semaphore = &semaphore;
+        async move {
+            if file.size > 0 && !file.hashes.hashes.is_empty() {
+                return Ok(None);
+            }
+            let _permit = semaphore.acquire().await;
+            let metadata = resolver(file).await.map_err(DwldError::Resolver)?;
+            let mut file = (*file).clone();
+            if file.size == 0 {
+                file.size = metadata.size.unwrap_or(0);
+            }
+            if file.hashes.hashes.is_empty() {
+                file.hashes = metadata.hashes;
+            }
+            Ok(Some(file))
+        }
+    });
+    futures::future::join_all(resolutions).await
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use surf::StatusCode;
+
+use crate::DwldError;
+
+/// What to do after a failed attempt of a download
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryDecision {
+    /// Try the URL again after the delay
+    Retry(Duration),
+    /// Fail the URL, the mirrors and the fallback are tried next
+    GiveUp,
+}
+
+/// Decides if a failed download is tried again. `attempt` counts the failed attempts of
+/// the URL, starting at 1; the cancelled downloads are never retried
+pub trait RetryPolicy: Send + Sync {
+    fn retry(&self, error: &DwldError, attempt: u32) -> RetryDecision;
+}
+
+impl<F: Fn(&DwldError, u32) -> RetryDecision + Send + Sync> RetryPolicy for F {
+    fn retry(&self, error: &DwldError, attempt: u32) -> RetryDecision {
+        self(error, attempt)
+    }
+}
+
+/// Policy never retrying, the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Never;
+
+impl RetryPolicy for Never {
+    fn retry(&self, _: &DwldError, _: u32) -> RetryDecision {
+        RetryDecision::GiveUp
+    }
+}
+
+/// Policy retrying the transient errors with a delay growing after every attempt: network
+/// failures, timeouts, cut transfers and the statuses 408, 429 and 5xx. The responses with
+/// `Retry-After` wait the time asked by the server, giving up if it's over the maximum
+/// delay
+#[derive(Debug, Clone, Copy)]
+pub struct ExponentialBackoff {
+    /// Retries of the URL before giving up
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_delay: Duration,
+    /// Maximum delay between the retries
+    pub max_delay: Duration,
+    /// Factor applied to the delay after every retry
+    pub factor: f64,
+}
+
+impl ExponentialBackoff {
+    /// Creates a policy of `max_retries` retries, waiting 500ms, then twice the previous
+    /// delay up to 30 seconds
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            factor: 2.0,
+        }
+    }
+    /// Sets the delay before the first retry
+    pub fn with_initial_delay(mut self, delay: Duration) -> Self {
+        self.initial_delay = delay;
+        self
+    }
+    /// Sets the maximum delay between the retries
+    pub fn with_max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+    /// Sets the factor applied to the delay after every retry
+    pub fn with_factor(mut self, factor: f64) -> Self {
+        self.factor = factor;
+        self
+    }
+    /// Delay before the retry following the attempt
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = self.factor.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        Duration::try_from_secs_f64(self.initial_delay.as_secs_f64() * factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn retry(&self, error: &DwldError, attempt: u32) -> RetryDecision {
+        if attempt > self.max_retries || !is_transient(error) {
+            return RetryDecision::GiveUp;
+        }
+        match error.retry_after() {
+            Some(wait) if wait > self.max_delay => RetryDecision::GiveUp,
+            Some(wait) => RetryDecision::Retry(wait),
+            None => RetryDecision::Retry(self.delay(attempt)),
+        }
+    }
+}
+
+/// Checks if the error may not happen again, e.g. a dropped connection or an overloaded
+/// server
+pub fn is_transient(error: &DwldError) -> bool {
+    match error {
+        DwldError::Network(_)
+        | DwldError::Timeout(_)
+        | DwldError::Truncated { .. }
+        | DwldError::ContentRange { .. } => true,
+        DwldError::Status { status, .. } => {
+            status.is_server_error()
+                || matches!(
+                    status,
+                    StatusCode::RequestTimeout | StatusCode::TooManyRequests
+                )
+        }
+        // the replies 4xx of FTP are transient, e.g. too many connections
+        DwldError::Ftp { code, .. } => (400..500).contains(code),
+        _ => false,
+    }
+}
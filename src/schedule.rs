@@ -0,0 +1,67 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Seconds in a day
+const DAY: u64 = 24 * 60 * 60;
+
+/// Constraints on when a download can start, e.g. to defer big updates to off-peak hours.
+///
+/// Only the start is constrained, running downloads aren't stopped when the window ends
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLSchedule {
+    /// Don't start before this time
+    pub not_before: Option<SystemTime>,
+    /// Only start between these hours of the day, the window can wrap midnight
+    pub window: Option<(u8, u8)>,
+    /// Offset of the local time from UTC in seconds, used by the window
+    pub utc_offset: i32,
+}
+
+impl DLSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Defers the start until the given time
+    pub fn not_before(mut self, time: SystemTime) -> Self {
+        self.not_before = Some(time);
+        self
+    }
+    /// Only starts between the hours `start` and `end`, e.g. `(22, 6)` for the night
+    pub fn only_between(mut self, start: u8, end: u8) -> Self {
+        self.window = Some((start % 24, end % 24));
+        self
+    }
+    /// Sets the offset of the local time from UTC in seconds
+    pub fn with_utc_offset(mut self, offset: i32) -> Self {
+        self.utc_offset = offset;
+        self
+    }
+    /// Time to wait from `now` until the download can start
+    pub fn wait_time(&self, now: SystemTime) -> Duration {
+        let delay = self
+            .not_before
+            .and_then(|time| time.duration_since(now).ok())
+            .unwrap_or_default();
+        let Some((start, end)) = self.window else {
+            return delay;
+        };
+        // second of the local day when the delay ends
+        let secs = (now + delay)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        let second = (secs + self.utc_offset as i64).rem_euclid(DAY as i64) as u64;
+        let (start, end) = (start as u64 * 3600, end as u64 * 3600);
+        let inside = match start.cmp(&end) {
+            std::cmp::Ordering::Less => (start..end).contains(&second),
+            std::cmp::Ordering::Greater => second >= start || second < end,
+            std::cmp::Ordering::Equal => true,
+        };
+        if inside {
+            delay
+        } else {
+            delay + Duration::from_secs((start + DAY - second) % DAY)
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+};
+
+use futures::AsyncReadExt;
+use surf::{Client, StatusCode};
+
+use crate::{
+    DLHandle, DLTimeouts, DwldError,
+    indicator::Indicator,
+    report::TransferStats,
+    storage,
+    throttle::{self, Throttle},
+    timeout::{self, DLTimeoutKind},
+};
+
+/// Smallest range downloaded by its own connection
+pub(crate) const MIN_SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// Download of a file split in ranges fetched concurrently
+pub(crate) struct Segmented<'a> {
+    pub client: &'a Client,
+    pub url: &'a str,
+    /// Custom headers of the file
+    pub headers: &'a [(String, String)],
+    /// Local file written by the segments
+    pub path: &'a Path,
+    /// Size of the whole file
+    pub size: u64,
+    pub segments: u64,
+    pub timeouts: DLTimeouts,
+    pub handle: Option<&'a DLHandle>,
+    pub bandwidth: Option<&'a Throttle>,
+    /// Limit of the file, shared by its segments
+    pub speed_limit: Option<&'a Throttle>,
+    /// Only set the size of the file instead of reserving its blocks
+    pub sparse: bool,
+    pub stats: Option<&'a TransferStats>,
+}
+
+impl Segmented<'_> {
+    /// Number of segments used for the file, files too small aren't split
+    pub fn count(requested: usize, size: u64) -> u64 {
+        (requested as u64).min(size / MIN_SEGMENT_SIZE)
+    }
+    /// Downloads the segments concurrently, the progress is the sum of all of them
+    pub async fn download(&self, indicator: &mut impl Indicator) -> Result<(), DwldError> {
+        // every segment writes its own range of the file
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(self.path)
+            .map_err(|e| self.io_error(e))?;
+        if self.sparse {
+            // the ranges not written yet are holes of the file
+            file.set_len(self.size)
+        } else {
+            storage::preallocate(&file, self.size)
+        }
+        .map_err(|e| self.io_error(e))?;
+
+        let length = self.size.div_ceil(self.segments);
+        let downloaded = Cell::new(0);
+        let indicator = RefCell::new(indicator);
+        let segments = (0..self.segments)
+            .map(|i| (i * length, ((i + 1) * length).min(self.size)))
+            .filter(|(start, end)| start < end)
+            .map(|range| self.segment(&file, range, &downloaded, &indicator));
+        futures::future::try_join_all(segments).await?;
+        file.sync_data().map_err(|e| self.io_error(e))
+    }
+    /// Downloads the bytes of `start..end`
+    async fn segment(
+        &self,
+        file: &File,
+        (start, end): (u64, u64),
+        downloaded: &Cell<u64>,
+        indicator: &RefCell<&mut impl Indicator>,
+    ) -> Result<(), DwldError> {
+        let request = crate::add_headers(self.client.get(self.url), self.headers)
+            .header("Range", format!("bytes={}-{}", start, end - 1));
+        let mut response =
+            timeout::timeout(self.timeouts.connect, DLTimeoutKind::Connect, request).await??;
+        if response.status() != StatusCode::PartialContent {
+            return Err(crate::status_error(&mut response, self.timeouts).await);
+        }
+        if let Err(e) = check_content_range(&response, start, Some(end - 1), Some(self.size)) {
+            return Err(crate::reject(&mut response, self.timeouts, e).await);
+        }
+        let mut body = response.take_body();
+
+        let mut position = start;
+        let mut buffer = [0; 8192];
+        while position < end {
+            // stop reading while the session is paused
+            if let Some(handle) = self.handle {
+                while handle.resumed().await {}
+            }
+            let len = (end - position).min(buffer.len() as u64) as usize;
+            let read = AsyncReadExt::read(&mut body, &mut buffer[..len]);
+            let n = timeout::timeout(self.timeouts.read, DLTimeoutKind::Read, read)
+                .await?
+                .map_err(|e| DwldError::Network(e.into()))?;
+            if n == 0 {
+                let e = io::Error::from(io::ErrorKind::UnexpectedEof);
+                return Err(DwldError::Network(e.into()));
+            }
+            write_all_at(file, &buffer[..n], position).map_err(|e| self.io_error(e))?;
+            position += n as u64;
+            if let Some(stats) = self.stats {
+                stats.add(n);
+            }
+            throttle::consume(&[self.bandwidth, self.speed_limit], n).await;
+            downloaded.set(downloaded.get() + n as u64);
+            indicator.borrow_mut().effect(downloaded.get());
+        }
+        Ok(())
+    }
+    fn io_error(&self, e: io::Error) -> DwldError {
+        DwldError::io(self.path.to_string_lossy(), e)
+    }
+}
+
+/// Writes the data at the offset of the file, without the cursor shared by the segments
+#[cfg(unix)]
+fn write_all_at(file: &File, data: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, data, offset)
+}
+/// Writes the data at the offset of the file, without the cursor shared by the segments
+#[cfg(windows)]
+fn write_all_at(file: &File, mut data: &[u8], mut offset: u64) -> io::Result<()> {
+    while !data.is_empty() {
+        match std::os::windows::fs::FileExt::seek_write(file, data, offset) {
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+            Ok(n) => {
+                data = &data[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a partial response has the requested range, from `start` to the inclusive
+/// `end`, of a file of `size` bytes. Unset values aren't checked
+pub(crate) fn check_content_range(
+    response: &surf::Response,
+    start: u64,
+    end: Option<u64>,
+    size: Option<u64>,
+) -> Result<(), DwldError> {
+    let received = response
+        .header("Content-Range")
+        .map(|values| values.last().as_str().to_string());
+    let valid = received
+        .as_deref()
+        .and_then(parse_content_range)
+        .is_some_and(|(first, last, total)| {
+            first == start
+                && end.is_none_or(|end| last == end)
+                && size.is_none_or(|size| total.is_none_or(|total| total == size))
+        });
+    if valid {
+        return Ok(());
+    }
+    let expected = format!(
+        "bytes {}-{}/{}",
+        start,
+        end.map_or(String::new(), |end| end.to_string()),
+        size.map_or("*".to_string(), |size| size.to_string())
+    );
+    Err(DwldError::ContentRange { expected, received })
+}
+
+/// Size of the file told by a `416 Range Not Satisfiable`, in a `Content-Range` header
+/// like `bytes */1000`
+pub(crate) fn unsatisfied_size(response: &surf::Response) -> Option<u64> {
+    let header = response.header("Content-Range")?;
+    let total = header.last().as_str().strip_prefix("bytes */")?;
+    total.trim().parse().ok()
+}
+
+/// Range and total size of a `Content-Range` header like `bytes 0-99/1000`, the total
+/// can be unknown
+fn parse_content_range(header: &str) -> Option<(u64, u64, Option<u64>)> {
+    let (range, total) = header.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    let total = match total.trim() {
+        "*" => None,
+        total => Some(total.parse().ok()?),
+    };
+    Some((first.trim().parse().ok()?, last.trim().parse().ok()?, total))
+}
@@ -0,0 +1,49 @@
+use surf::{Response, StatusCode};
+
+use crate::{DLHashType, DLHashes};
+
+/// Digests of the whole file announced by the server in the headers of the response:
+/// `Digest`, `Repr-Digest`, `Content-Digest` of complete responses, `x-goog-hash` and the
+/// `ETag` of the servers using the MD5 of the file, like S3
+pub(crate) fn digests(response: &Response) -> DLHashes {
+    let mut hashes = DLHashes::new();
+    let mut headers = vec!["Digest", "Repr-Digest", "x-goog-hash"];
+    // the content digest of a partial response is only of its range
+    if response.status() == StatusCode::Ok {
+        headers.push("Content-Digest");
+    }
+    for name in headers {
+        for value in response.header(name).into_iter().flatten() {
+            for digest in value.as_str().split(',') {
+                if let Some(hash) = parse(digest) {
+                    hashes.hashes.push(hash);
+                }
+            }
+        }
+    }
+    if let Some(etag) = response.header("ETag") {
+        // the multipart uploads of S3 have a `-<parts>` suffix and aren't the MD5
+        let etag = etag.last().as_str().trim_matches('"');
+        if etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit()) {
+            hashes
+                .hashes
+                .push((DLHashType::MD5, etag.to_ascii_lowercase()));
+        }
+    }
+    hashes
+}
+
+/// Hash of a `<algorithm>=<base64>` digest, the structured fields wrap it in colons
+fn parse(digest: &str) -> Option<(DLHashType, String)> {
+    let (algorithm, value) = digest.trim().split_once('=')?;
+    let hash_type = match algorithm.trim().to_ascii_lowercase().as_str() {
+        "sha" => DLHashType::SHA1,
+        "sha-256" => DLHashType::SHA256,
+        "sha-512" => DLHashType::SHA512,
+        "md5" => DLHashType::MD5,
+        "crc32c" => DLHashType::CRC32C,
+        _ => return None,
+    };
+    let hash = base64::decode(value.trim().trim_matches(':')).ok()?;
+    Some((hash_type, hex::encode(hash)))
+}
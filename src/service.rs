@@ -0,0 +1,189 @@
+use std::{future::Future, io, marker::PhantomData, pin::Pin, thread};
+
+use smol::channel::{self, Receiver, Sender};
+#[cfg(not(feature = "no_static_client"))]
+use surf::Client;
+
+use crate::{
+    DLHandle, DownloadPlan, DownloadReport, Downloader, DwldError, indicator::IndicatorFactory,
+    mirror::DLMirrorBook, redirect_cache::DLRedirectCache, url_cache::DLUrlCache,
+};
+
+/// Batch submitted to the service, creating its session in the thread of the service
+type Job = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// Long-running downloader executing the plans submitted by the application. The client,
+/// its connections, the executor and the caches are kept across the batches instead of
+/// building them for every download round.
+///
+/// The batches run concurrently in a thread of the service, each one with the limits and
+/// the options of its plan, except the ones of the client: the TLS settings, the
+/// redirections and the URL rewriter are the ones of the service. With
+/// `no_static_client`, every download still creates its own client.
+pub struct DownloadService<T: IndicatorFactory + 'static> {
+    jobs: Option<Sender<Job>>,
+    worker: Option<thread::JoinHandle<()>>,
+    shared: Shared,
+    indicator: PhantomData<fn() -> T>,
+}
+
+/// State of the service given to every batch
+#[derive(Clone)]
+struct Shared {
+    #[cfg(not(feature = "no_static_client"))]
+    client: Client,
+    mirrors: DLMirrorBook,
+    redirects: DLRedirectCache,
+    #[cfg(feature = "cas")]
+    cas: Option<crate::cas::DLStorage>,
+    url_cache: Option<DLUrlCache>,
+}
+
+impl<T: IndicatorFactory + 'static> DownloadService<T> {
+    /// Creates a service with the default client, fails if its thread can't be spawned
+    pub fn new() -> io::Result<Self> {
+        Self::from_plan(DownloadPlan::default())
+    }
+    /// Creates a service with the client of the plan: its TLS settings, redirections and
+    /// URL rewriter. The files of the plan aren't downloaded. Fails if the thread of the
+    /// service can't be spawned
+    pub fn from_plan(plan: DownloadPlan) -> io::Result<Self> {
+        let redirects = DLRedirectCache::new();
+        #[cfg(not(feature = "no_static_client"))]
+        let client = Downloader::<T>::from_plan(plan).create_client(&redirects);
+        #[cfg(feature = "no_static_client")]
+        let _ = plan;
+        let (jobs, received) = channel::unbounded();
+        let worker = thread::Builder::new()
+            .name("dwldutil-service".to_string())
+            .spawn(move || work(received))?;
+        Ok(Self {
+            jobs: Some(jobs),
+            worker: Some(worker),
+            shared: Shared {
+                #[cfg(not(feature = "no_static_client"))]
+                client,
+                mirrors: DLMirrorBook::new(),
+                redirects,
+                #[cfg(feature = "cas")]
+                cas: None,
+                url_cache: None,
+            },
+            indicator: PhantomData,
+        })
+    }
+    /// Stores the files of the batches without their own storage in the CAS storage
+    #[cfg(feature = "cas")]
+    pub fn with_cas(mut self, cas: crate::cas::DLStorage) -> Self {
+        self.shared.cas = Some(cas);
+        self
+    }
+    /// Serves the files of the batches without their own cache from the URL cache
+    pub fn with_url_cache(mut self, cache: DLUrlCache) -> Self {
+        self.shared.url_cache = Some(cache);
+        self
+    }
+    /// Accumulates the statistics of the mirrors of every batch in the given book
+    pub fn with_mirror_stats(mut self, mirrors: DLMirrorBook) -> Self {
+        self.shared.mirrors = mirrors;
+        self
+    }
+    /// Statistics of the mirrors accumulated by the batches
+    pub fn mirror_stats(&self) -> DLMirrorBook {
+        self.shared.mirrors.clone()
+    }
+    /// Final URLs of the redirections followed by the batches
+    pub fn redirect_cache(&self) -> DLRedirectCache {
+        self.shared.redirects.clone()
+    }
+    /// Starts downloading the files of the plan, returns the job to control it and to wait
+    /// for its report
+    pub fn submit(&self, plan: DownloadPlan) -> DLJob {
+        let handle = DLHandle::new();
+        let (reports, report) = channel::bounded(1);
+        let (shared, session_handle) = (self.shared.clone(), handle.clone());
+        let job: Job = Box::new(move || {
+            Box::pin(async move {
+                let downloader = shared.downloader::<T>(plan).with_handle(session_handle);
+                let _ = reports.send(downloader.start_async().await).await;
+            })
+        });
+        if let Some(jobs) = &self.jobs {
+            // the worker only stops when the service is dropped
+            let _ = jobs.try_send(job);
+        }
+        DLJob { handle, report }
+    }
+    /// Waits for the submitted batches and stops the service, the same as dropping it
+    pub fn shutdown(self) {}
+}
+
+impl<T: IndicatorFactory + 'static> Drop for DownloadService<T> {
+    fn drop(&mut self) {
+        // closing the channel stops the worker once its batches finish
+        self.jobs.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Shared {
+    /// Downloader of a batch, using the state of the service
+    fn downloader<T: IndicatorFactory>(self, mut plan: DownloadPlan) -> Downloader<T> {
+        for file in &mut plan.files {
+            #[cfg(feature = "cas")]
+            if file.cas.is_none() {
+                file.cas = self.cas.clone();
+            }
+            if file.url_cache.is_none() {
+                file.url_cache = self.url_cache.clone();
+            }
+        }
+        let downloader = Downloader::from_plan(plan)
+            .with_mirror_stats(self.mirrors)
+            .with_redirect_cache(self.redirects);
+        #[cfg(not(feature = "no_static_client"))]
+        let downloader = downloader.with_client(self.client);
+        downloader
+    }
+}
+
+/// Runs the batches submitted to the service until it's dropped
+fn work(jobs: Receiver<Job>) {
+    let executor = smol::LocalExecutor::new();
+    smol::block_on(executor.run(async {
+        let mut running = Vec::new();
+        while let Ok(job) = jobs.recv().await {
+            running.retain(|task: &smol::Task<()>| !task.is_finished());
+            running.push(executor.spawn(job()));
+        }
+        futures::future::join_all(running).await;
+    }));
+}
+
+/// Batch submitted to a [DownloadService]
+pub struct DLJob {
+    handle: DLHandle,
+    report: Receiver<Result<DownloadReport, DwldError>>,
+}
+
+impl DLJob {
+    /// Handle of the session of the batch, to cancel or pause it, add files or listen to
+    /// its events
+    pub fn handle(&self) -> &DLHandle {
+        &self.handle
+    }
+    /// Waits for the report of the batch, see [Downloader::start]
+    pub fn wait(self) -> Result<DownloadReport, DwldError> {
+        smol::block_on(self.report())
+    }
+    /// Waits for the report of the batch in the executor of the caller
+    pub async fn report(self) -> Result<DownloadReport, DwldError> {
+        // the worker only drops the batch if it died, e.g. after a panic
+        self.report
+            .recv()
+            .await
+            .unwrap_or(Err(DwldError::ServiceStopped))
+    }
+}
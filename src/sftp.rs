@@ -0,0 +1,207 @@
+use std::{
+    io::{self, Seek, SeekFrom},
+    net::TcpStream,
+    path::{Path, PathBuf},
+};
+
+use ssh2::{CheckResult, KnownHostFileKind, Session};
+use surf::Url;
+
+use crate::{DwldError, data_url, timeout::DLTimeouts};
+
+/// Checks if the URL is an `sftp://` URL, downloaded with SFTP
+pub(crate) fn is_sftp_url(url: &str) -> bool {
+    url.get(..7)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("sftp://"))
+}
+
+/// Authentication and host checking of the SFTP connections. The password of the URL is
+/// used if it has one, then the private key and then the SSH agent
+#[derive(Debug, Clone, Default)]
+pub struct DLSftpConfig {
+    /// Private key of the user, e.g. `~/.ssh/id_ed25519`
+    pub private_key: Option<PathBuf>,
+    /// Passphrase of the private key
+    pub passphrase: Option<String>,
+    /// File with the keys of the known servers, `~/.ssh/known_hosts` if unset
+    pub known_hosts: Option<PathBuf>,
+    /// Trusts the servers missing in the known hosts. The changed keys always fail
+    pub accept_unknown_hosts: bool,
+}
+
+impl DLSftpConfig {
+    /// Settings authenticating with the SSH agent and trusting the known hosts
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Authenticates with the private key, encrypted with the passphrase if any
+    pub fn with_private_key(mut self, path: impl Into<PathBuf>, passphrase: Option<&str>) -> Self {
+        self.private_key = Some(path.into());
+        self.passphrase = passphrase.map(str::to_string);
+        self
+    }
+    /// Checks the keys of the servers with the known hosts file
+    pub fn with_known_hosts(mut self, path: impl Into<PathBuf>) -> Self {
+        self.known_hosts = Some(path.into());
+        self
+    }
+    /// Trusts the servers missing in the known hosts, INSECURE on the first connection
+    pub fn accepting_unknown_hosts(mut self) -> Self {
+        self.accept_unknown_hosts = true;
+        self
+    }
+    fn known_hosts_path(&self) -> Option<PathBuf> {
+        self.known_hosts.clone().or_else(|| {
+            let home = std::env::var_os("HOME")?;
+            Some(Path::new(&home).join(".ssh").join("known_hosts"))
+        })
+    }
+}
+
+/// Server, credentials and path of an SFTP URL
+struct Location {
+    host: String,
+    port: u16,
+    user: String,
+    password: Option<String>,
+    /// Absolute path, `sftp://host/~/file` is relative to the home of the user
+    path: PathBuf,
+}
+
+impl Location {
+    fn parse(url: &str) -> Result<Self, DwldError> {
+        let invalid = |message: &str| DwldError::Source {
+            path: url.to_string(),
+            source: io::Error::new(io::ErrorKind::InvalidInput, message.to_string()),
+        };
+        let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+        let decode = |text: &str| {
+            data_url::percent_decode(text)
+                .and_then(|text| String::from_utf8(text).ok())
+                .ok_or_else(|| invalid("invalid percent escape"))
+        };
+        let path = decode(parsed.path())?;
+        let path = path.strip_prefix("/~/").unwrap_or(&path);
+        if path.is_empty() || path.ends_with('/') {
+            return Err(invalid("not the path of a file"));
+        }
+        Ok(Self {
+            host: parsed
+                .host_str()
+                .ok_or_else(|| invalid("missing the host"))?
+                .to_string(),
+            port: parsed.port().unwrap_or(22),
+            user: match parsed.username() {
+                "" => return Err(invalid("missing the user")),
+                user => decode(user)?,
+            },
+            password: parsed.password().map(decode).transpose()?,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+fn sftp_error(e: impl Into<io::Error>) -> DwldError {
+    DwldError::Sftp(e.into())
+}
+
+/// Opens the file of the URL, with its size if the server tells it
+fn open(
+    url: &str,
+    config: &DLSftpConfig,
+    timeouts: DLTimeouts,
+) -> Result<(ssh2::File, Option<u64>), DwldError> {
+    let location = Location::parse(url)?;
+    let tcp = TcpStream::connect((location.host.as_str(), location.port))
+        .map_err(|e| DwldError::Network(e.into()))?;
+    let mut session = Session::new().map_err(sftp_error)?;
+    // the blocking calls of the session end with the read timeout
+    if let Some(read) = timeouts.read {
+        session.set_timeout(read.as_millis().try_into().unwrap_or(u32::MAX));
+    }
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(sftp_error)?;
+    check_host(&session, &location, config)?;
+
+    let user = location.user.as_str();
+    let authenticated = match (&location.password, &config.private_key) {
+        (Some(password), _) => session.userauth_password(user, password),
+        (None, Some(key)) => {
+            let passphrase = config.passphrase.as_deref();
+            session.userauth_pubkey_file(user, None, key, passphrase)
+        }
+        (None, None) => session.userauth_agent(user),
+    };
+    authenticated.map_err(sftp_error)?;
+
+    let sftp = session.sftp().map_err(sftp_error)?;
+    let size = sftp.stat(&location.path).map_err(sftp_error)?.size;
+    let file = sftp.open(&location.path).map_err(sftp_error)?;
+    Ok((file, size))
+}
+
+/// Checks the key of the server with the known hosts
+fn check_host(
+    session: &Session,
+    location: &Location,
+    config: &DLSftpConfig,
+) -> Result<(), DwldError> {
+    let mut known_hosts = session.known_hosts().map_err(sftp_error)?;
+    // a missing file doesn't know any host
+    if let Some(path) = config.known_hosts_path().filter(|path| path.exists()) {
+        known_hosts
+            .read_file(&path, KnownHostFileKind::OpenSSH)
+            .map_err(sftp_error)?;
+    }
+    let (key, _) = session
+        .host_key()
+        .ok_or_else(|| sftp_error(io::Error::other("the server sent no host key")))?;
+    let rejected = match known_hosts.check_port(&location.host, location.port, key) {
+        CheckResult::Match => return Ok(()),
+        CheckResult::NotFound if config.accept_unknown_hosts => return Ok(()),
+        CheckResult::NotFound => "unknown host key",
+        CheckResult::Mismatch => "the host key changed",
+        CheckResult::Failure => "the host key can't be checked",
+    };
+    let message = format!("{} of {}", rejected, location.host);
+    Err(sftp_error(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        message,
+    )))
+}
+
+/// Size of the file of an SFTP URL, if the server tells it
+pub(crate) async fn length(
+    url: &str,
+    config: DLSftpConfig,
+    timeouts: DLTimeouts,
+) -> Result<Option<u64>, DwldError> {
+    let url = url.to_string();
+    smol::unblock(move || open(&url, &config, timeouts).map(|(_, size)| size)).await
+}
+
+/// Body of the file of an SFTP URL from the offset, with the offset it starts at. The file
+/// is read from the beginning if it's shorter than the offset
+pub(crate) async fn body(
+    url: &str,
+    offset: u64,
+    config: DLSftpConfig,
+    timeouts: DLTimeouts,
+) -> Result<(surf::Body, u64), DwldError> {
+    let url = url.to_string();
+    let (file, size, offset) = smol::unblock(move || {
+        let (mut file, size) = open(&url, &config, timeouts)?;
+        let offset = match size {
+            Some(size) if offset > size => 0,
+            _ => offset,
+        };
+        file.seek(SeekFrom::Start(offset)).map_err(sftp_error)?;
+        Ok::<_, DwldError>((file, size, offset))
+    })
+    .await?;
+    // the blocking reads of the session run outside the executor
+    let reader = smol::io::BufReader::new(smol::Unblock::new(file));
+    let len = size.map(|size| (size - offset) as usize);
+    let body = surf::Body::from_reader(reader, len);
+    Ok((body, offset))
+}
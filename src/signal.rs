@@ -0,0 +1,77 @@
+use std::{
+    io,
+    sync::{
+        Mutex, OnceLock,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+};
+
+use async_signal::{Signal, Signals};
+use futures::StreamExt;
+
+use crate::{CancelReason, DLHandle};
+
+/// Sessions cancelled by the signals, with the identifier of their registration
+static SESSIONS: Mutex<Vec<(u64, DLHandle)>> = Mutex::new(Vec::new());
+/// Identifier of the next registration
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+/// Result of installing the listener, once per process
+static LISTENER: OnceLock<Result<(), String>> = OnceLock::new();
+
+/// Registration of a session, removed when dropped
+pub(crate) struct SignalGuard(u64);
+
+impl Drop for SignalGuard {
+    fn drop(&mut self) {
+        sessions().retain(|(id, _)| *id != self.0);
+    }
+}
+
+fn sessions() -> std::sync::MutexGuard<'static, Vec<(u64, DLHandle)>> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Cancels the session of the handle with [CancelReason::Signal] on Ctrl-C or `SIGTERM`
+/// until the guard is dropped. The handlers of the signals are installed once and never
+/// removed, without sessions the signals end the process like their default action
+pub(crate) fn cancel_on_signal(handle: &DLHandle) -> io::Result<SignalGuard> {
+    LISTENER
+        .get_or_init(|| listen().map_err(|e| e.to_string()))
+        .clone()
+        .map_err(io::Error::other)?;
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    sessions().push((id, handle.clone()));
+    Ok(SignalGuard(id))
+}
+
+/// Installs the handlers of the signals, received in a thread of their own
+fn listen() -> io::Result<()> {
+    #[cfg(unix)]
+    let mut signals = Signals::new([Signal::Int, Signal::Term])?;
+    // only Ctrl-C can be handled on Windows
+    #[cfg(not(unix))]
+    let mut signals = Signals::new([Signal::Int])?;
+    thread::Builder::new()
+        .name("dwldutil-signals".to_string())
+        .spawn(move || {
+            smol::block_on(async {
+                while let Some(Ok(signal)) = signals.next().await {
+                    trace_event!(INFO, signal = ?signal, "signal received");
+                    let sessions = sessions();
+                    if sessions.is_empty() {
+                        // the status of the shells for the processes killed by the signal
+                        let status = match signal {
+                            Signal::Term => 128 + 15,
+                            _ => 128 + 2,
+                        };
+                        std::process::exit(status);
+                    }
+                    for (_, handle) in sessions.iter() {
+                        handle.cancel_with(CancelReason::Signal);
+                    }
+                }
+            })
+        })?;
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::{
+    future::Future,
+    io::{self, Write},
+    sync::{Arc, Mutex},
+};
+
+use smol::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::storage::{StorageBackend, StorageWriter};
+
+/// Target receiving the data of a file instead of its destination, e.g. a database, an
+/// encryption wrapper, a pipe or a socket. See [crate::DLFile::with_sink]
+#[surf::utils::async_trait]
+pub trait DLSink: Send + Sync {
+    /// Opens the writer of a download attempt, every attempt writes the file from the
+    /// beginning
+    async fn open(&self) -> io::Result<Box<dyn StorageWriter>>;
+    /// Drops the data written by a failed attempt, e.g. rolling back a transaction. Only
+    /// called with [crate::PartialPolicy::Delete]
+    async fn discard(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink keeping the file in memory, its clones share the data
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Copy of the data written
+    pub fn data(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+    /// Takes the data written, leaving the sink empty
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.lock())
+    }
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[surf::utils::async_trait]
+impl DLSink for MemorySink {
+    async fn open(&self) -> io::Result<Box<dyn StorageWriter>> {
+        self.lock().clear();
+        Ok(Box::new(MemoryWriter(self.clone())))
+    }
+    async fn discard(&self) -> io::Result<()> {
+        self.lock().clear();
+        Ok(())
+    }
+}
+
+struct MemoryWriter(MemorySink);
+
+#[surf::utils::async_trait]
+impl StorageWriter for MemoryWriter {
+    async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.lock().extend_from_slice(buf);
+        Ok(())
+    }
+    async fn finish(self: Box<Self>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sink writing the file to the [Write] opened for every attempt, e.g. a pipe or a
+/// `TcpStream`
+pub struct WriteSink<F> {
+    open: F,
+}
+
+impl<W, F> WriteSink<F>
+where
+    W: Write + Send + 'static,
+    F: Fn() -> io::Result<W> + Send + Sync,
+{
+    pub fn new(open: F) -> Self {
+        Self { open }
+    }
+}
+
+#[surf::utils::async_trait]
+impl<W, F> DLSink for WriteSink<F>
+where
+    W: Write + Send + 'static,
+    F: Fn() -> io::Result<W> + Send + Sync,
+{
+    async fn open(&self) -> io::Result<Box<dyn StorageWriter>> {
+        Ok(Box::new(SyncWriter((self.open)()?)))
+    }
+}
+
+struct SyncWriter<W>(W);
+
+#[surf::utils::async_trait]
+impl<W: Write + Send> StorageWriter for SyncWriter<W> {
+    async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.write_all(buf)
+    }
+    async fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Sink writing the file to the [AsyncWrite] opened for every attempt, e.g. an async
+/// socket or a compression encoder
+pub struct AsyncWriteSink<F> {
+    open: F,
+}
+
+impl<W, F, Fut> AsyncWriteSink<F>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = io::Result<W>> + Send,
+{
+    pub fn new(open: F) -> Self {
+        Self { open }
+    }
+}
+
+#[surf::utils::async_trait]
+impl<W, F, Fut> DLSink for AsyncWriteSink<F>
+where
+    W: AsyncWrite + Send + Unpin + 'static,
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = io::Result<W>> + Send,
+{
+    async fn open(&self) -> io::Result<Box<dyn StorageWriter>> {
+        Ok(Box::new(AsyncWriter((self.open)().await?)))
+    }
+}
+
+struct AsyncWriter<W>(W);
+
+#[surf::utils::async_trait]
+impl<W: AsyncWrite + Send + Unpin> StorageWriter for AsyncWriter<W> {
+    async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.write_all(buf).await
+    }
+    async fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.close().await
+    }
+}
+
+/// Storage backend of a file written to a sink. The sink only receives the data, it can't
+/// be read back nor renamed, so the file is never staged nor resumed
+pub(crate) struct SinkBackend(pub Arc<dyn DLSink>);
+
+#[surf::utils::async_trait]
+impl StorageBackend for SinkBackend {
+    async fn create(&self, _path: &str) -> io::Result<Box<dyn StorageWriter>> {
+        self.0.open().await
+    }
+    async fn read(&self, _path: &str) -> io::Result<Vec<u8>> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+    async fn exists(&self, _path: &str) -> bool {
+        false
+    }
+    async fn remove(&self, _path: &str) -> io::Result<()> {
+        self.0.discard().await
+    }
+    async fn rename(&self, _from: &str, _to: &str) -> io::Result<()> {
+        // the data was already given to the sink
+        Ok(())
+    }
+}
@@ -0,0 +1,1011 @@
+use std::{
+    collections::BTreeSet,
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Attempts of the file operations failing because another process holds the file
+const LOCKED_ATTEMPTS: u32 = 6;
+/// Delay before the first new attempt, doubled after each one
+const LOCKED_DELAY: Duration = Duration::from_millis(50);
+
+/// Backend where the downloaded files are written
+#[surf::utils::async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Opens a writer for the file, replacing any previous content
+    async fn create(&self, path: &str) -> io::Result<Box<dyn StorageWriter>>;
+    /// Opens a writer appending to the file, not every backend supports it
+    async fn append(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+        let _ = path;
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+    /// Size of the file, if it exists and it can be known without a request
+    fn size(&self, path: &str) -> Option<u64> {
+        let _ = path;
+        None
+    }
+    /// Reads the whole content of the file
+    async fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+    /// Checks if the file exists
+    async fn exists(&self, path: &str) -> bool;
+    /// Removes the file
+    async fn remove(&self, path: &str) -> io::Result<()>;
+    /// Renames the file, not every backend supports it
+    async fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let _ = (from, to);
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+    /// Path of the file in the local filesystem, if the backend is local
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        let _ = path;
+        None
+    }
+}
+
+/// Writer of one single file of a backend
+#[surf::utils::async_trait]
+pub trait StorageWriter: Send {
+    /// Writes a chunk of the file
+    async fn write(&mut self, buf: &[u8]) -> io::Result<()>;
+    /// Reserves the space of a file of `size` bytes without changing its length, the
+    /// backends without support ignore it
+    async fn reserve(&mut self, size: u64) -> io::Result<()> {
+        let _ = size;
+        Ok(())
+    }
+    /// Finishes the file, flushing all the pending data
+    async fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+/// Default backend, writes the files in the local filesystem
+#[derive(Debug, Default, Clone)]
+pub struct LocalBackend;
+
+#[surf::utils::async_trait]
+impl StorageBackend for LocalBackend {
+    async fn create(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+        // create the parent directory if it doesn't exist
+        if let Some(parent) = Path::new(path).parent() {
+            create_dir_all(parent)?;
+        }
+        if Path::new(path).is_dir() {
+            return Err(io::Error::from(io::ErrorKind::IsADirectory));
+        }
+        Ok(Box::new(LocalWriter(File::create(path)?)))
+    }
+    async fn append(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+        let file = fs::OpenOptions::new().append(true).open(path)?;
+        Ok(Box::new(LocalWriter(file)))
+    }
+    fn size(&self, path: &str) -> Option<u64> {
+        fs::metadata(path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+    }
+    async fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+    async fn exists(&self, path: &str) -> bool {
+        Path::new(path).exists()
+    }
+    // the files locked by other processes are waited for outside the executor
+    async fn remove(&self, path: &str) -> io::Result<()> {
+        let path = path.to_string();
+        smol::unblock(move || remove_file(path)).await
+    }
+    async fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let (from, to) = (PathBuf::from(from), PathBuf::from(to));
+        smol::unblock(move || move_file(&from, &to)).await
+    }
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        Some(PathBuf::from(path))
+    }
+}
+
+/// Writer of a local file
+pub struct LocalWriter(pub File);
+
+#[surf::utils::async_trait]
+impl StorageWriter for LocalWriter {
+    async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.write_all(buf)
+    }
+    async fn reserve(&mut self, size: u64) -> io::Result<()> {
+        reserve(&self.0, size)
+    }
+    async fn finish(mut self: Box<Self>) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// What happens to the data already written when a download fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum PartialPolicy {
+    /// Remove the partial data
+    #[default]
+    Delete,
+    /// Keep the partial data, to resume the download later.
+    ///
+    /// Partial CAS blobs are moved to `<blob>.part`, so they are never taken as complete
+    Keep,
+    /// Keep the partial data in `<path>.failed`
+    RenameFailed,
+}
+
+/// What happens when the destination of a file already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OverwritePolicy {
+    /// Replace the existing file
+    #[default]
+    Overwrite,
+    /// Keep the existing file and don't download
+    Skip,
+    /// Fail the download
+    Error,
+    /// Move the existing file to the first free `<path>.N` and download
+    RenameWithSuffix,
+}
+
+/// Moves the file to the first free `<path>.N`, returns the new path
+pub(crate) async fn rename_with_suffix(
+    backend: &dyn StorageBackend,
+    path: &str,
+) -> io::Result<String> {
+    let mut n = 1;
+    loop {
+        let target = format!("{}.{}", path, n);
+        if !backend.exists(&target).await {
+            backend.rename(path, &target).await?;
+            return Ok(target);
+        }
+        n += 1;
+    }
+}
+
+/// Reserves the blocks of the whole file, failing early when the disk is full
+pub(crate) fn preallocate(file: &File, size: u64) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::os::fd::AsRawFd;
+        if size > 0 {
+            // SAFETY: the descriptor is owned by the open file
+            match unsafe { libc::posix_fallocate(file.as_raw_fd(), 0, size as libc::off_t) } {
+                0 => return Ok(()),
+                // filesystems without support only get the size
+                libc::EOPNOTSUPP | libc::EINVAL => {}
+                code => return Err(io::Error::from_raw_os_error(code)),
+            }
+        }
+    }
+    file.set_len(size)
+}
+
+/// Reserves the blocks of the file keeping its length, so the data written is still the
+/// whole file if the download stops. Only supported on Linux
+pub(crate) fn reserve(file: &File, size: u64) -> io::Result<()> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use std::os::fd::AsRawFd;
+        if size > 0 {
+            let (fd, mode) = (file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE);
+            // SAFETY: the descriptor is owned by the open file
+            if unsafe { libc::fallocate(fd, mode, 0, size as libc::off_t) } != 0 {
+                let e = io::Error::last_os_error();
+                // filesystems without support write the file as it comes
+                return match e.raw_os_error() {
+                    Some(libc::EOPNOTSUPP | libc::EINVAL | libc::ENOSYS) => Ok(()),
+                    _ => Err(e),
+                };
+            }
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = (file, size);
+    Ok(())
+}
+
+/// Creates the directory and its parents. A directory created meanwhile by a concurrent
+/// download counts as created
+pub(crate) fn create_dir_all(path: &Path) -> io::Result<()> {
+    match fs::create_dir_all(path) {
+        Err(e) if !(e.kind() == io::ErrorKind::AlreadyExists && path.is_dir()) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Directories created by the downloads of a batch, so the parents shared by thousands of
+/// files are only created once. Every batch has its own, the directories removed between
+/// the batches are created again
+#[derive(Debug, Default)]
+pub(crate) struct CreatedDirs(Mutex<BTreeSet<PathBuf>>);
+
+impl CreatedDirs {
+    /// Creates the directory and its parents, once per batch
+    pub(crate) fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let created = |dirs: &Mutex<BTreeSet<PathBuf>>| {
+            let dirs = dirs.lock().unwrap_or_else(|e| e.into_inner());
+            dirs.contains(path)
+        };
+        if created(&self.0) {
+            return Ok(());
+        }
+        create_dir_all(path)?;
+        let mut dirs = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        dirs.insert(path.to_path_buf());
+        Ok(())
+    }
+}
+
+/// Renames the file, copying it when the destination is in another filesystem. The copy
+/// is written next to the destination and renamed, so the destination is never partial
+pub(crate) fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut copy = to.as_os_str().to_owned();
+            copy.push(".moving");
+            let copy = PathBuf::from(copy);
+            if let Err(e) = fs::copy(from, &copy).and_then(|_| rename(&copy, to)) {
+                let _ = fs::remove_file(&copy);
+                return Err(e);
+            }
+            remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// Renames the file, waiting for the processes holding it, see [retry_locked]
+pub(crate) fn rename(from: impl AsRef<Path>, to: impl AsRef<Path>) -> io::Result<()> {
+    let (from, to) = (from.as_ref(), to.as_ref());
+    retry_locked(from, || fs::rename(from, to))
+}
+
+/// Removes the file, waiting for the processes holding it, see [retry_locked]
+pub(crate) fn remove_file(path: impl AsRef<Path>) -> io::Result<()> {
+    let path = path.as_ref();
+    retry_locked(path, || fs::remove_file(path))
+}
+
+/// Runs the operation on the file again while another process holds it, like the
+/// antivirus scanning the fresh files on Windows. It fails with the last error once the
+/// attempts are exhausted, telling the file is locked. It sleeps between the attempts, the
+/// downloads run it with `smol::unblock`
+pub(crate) fn retry_locked<T>(
+    path: &Path,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = LOCKED_DELAY;
+    for _ in 1..LOCKED_ATTEMPTS {
+        match operation() {
+            Err(e) if is_locked(&e) => {
+                trace_event!(DEBUG, path = %path.display(), delay = ?delay, error = %e, "file locked");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+    operation().map_err(|e| {
+        if !is_locked(&e) {
+            return e;
+        }
+        io::Error::new(
+            e.kind(),
+            format!(
+                "{} is still locked by another process after {} attempts: {}",
+                path.display(),
+                LOCKED_ATTEMPTS,
+                e
+            ),
+        )
+    })
+}
+
+/// Checks if the operation failed because another process holds the file
+fn is_locked(e: &io::Error) -> bool {
+    // ERROR_ACCESS_DENIED, ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION, the files
+    // being deleted or scanned deny the access too
+    #[cfg(windows)]
+    if matches!(e.raw_os_error(), Some(5 | 32 | 33)) {
+        return true;
+    }
+    e.kind() == io::ErrorKind::ResourceBusy
+}
+
+/// Sets the modification time of the file, the symbolic links set the one of their target
+pub(crate) fn set_modified(path: &Path, time: SystemTime) -> io::Result<()> {
+    File::options().write(true).open(path)?.set_modified(time)
+}
+
+/// Moves the entries of the directory into the destination, merging the directories that
+/// already exist there
+#[cfg(feature = "decompress")]
+pub(crate) fn move_tree(from: &Path, to: &Path) -> io::Result<()> {
+    create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            move_tree(&entry.path(), &target)?;
+        } else {
+            move_file(&entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Data written by a download, handled by the [PartialPolicy] if it fails
+pub(crate) enum PartialTarget {
+    /// File written through the storage backend
+    Backend(String),
+    /// CAS blob and the link to it
+    Cas { blob: String, link: String },
+}
+
+impl PartialTarget {
+    /// Applies the policy to the written data
+    pub(crate) async fn apply(
+        &self,
+        policy: PartialPolicy,
+        backend: &dyn StorageBackend,
+    ) -> io::Result<()> {
+        match (self, policy) {
+            (PartialTarget::Backend(path), PartialPolicy::Delete) => backend.remove(path).await,
+            (PartialTarget::Backend(_), PartialPolicy::Keep) => Ok(()),
+            (PartialTarget::Backend(path), PartialPolicy::RenameFailed) => {
+                backend.rename(path, &format!("{}.failed", path)).await
+            }
+            (PartialTarget::Cas { blob, link }, policy) => {
+                // the link would point to an incomplete blob
+                if fs::symlink_metadata(link).is_ok() {
+                    remove_file(link)?;
+                }
+                match policy {
+                    PartialPolicy::Delete => remove_file(blob),
+                    PartialPolicy::Keep => rename(blob, format!("{}.part", blob)),
+                    PartialPolicy::RenameFailed => {
+                        let failed = format!("{}.failed", link);
+                        // the storage may be in other device
+                        rename(blob, &failed).or_else(|_| {
+                            fs::copy(blob, &failed)?;
+                            remove_file(blob)
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Error preparing the destination of a file
+#[derive(Debug)]
+pub enum DLDestinationError {
+    /// The destination is a directory
+    IsDirectory(PathBuf),
+    /// The destination is a read-only file
+    ReadOnly(PathBuf),
+    /// The destination can't be written
+    PermissionDenied(PathBuf),
+    /// The parent directory of the destination can't be created
+    CreateParent(PathBuf, io::Error),
+    /// The destination can't be created
+    Create(PathBuf, io::Error),
+    /// The destination already exists, see [OverwritePolicy::Error]
+    Exists(PathBuf),
+}
+
+impl DLDestinationError {
+    /// Classifies an IO error produced writing the destination
+    pub fn from_io(path: impl AsRef<Path>, error: io::Error) -> Self {
+        let path = path.as_ref().to_path_buf();
+        match error.kind() {
+            io::ErrorKind::IsADirectory => Self::IsDirectory(path),
+            io::ErrorKind::ReadOnlyFilesystem => Self::ReadOnly(path),
+            io::ErrorKind::PermissionDenied => Self::PermissionDenied(path),
+            _ => Self::Create(path, error),
+        }
+    }
+    /// Path of the offending file or directory
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::IsDirectory(path)
+            | Self::ReadOnly(path)
+            | Self::PermissionDenied(path)
+            | Self::CreateParent(path, _)
+            | Self::Create(path, _)
+            | Self::Exists(path) => path,
+        }
+    }
+}
+
+impl fmt::Display for DLDestinationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IsDirectory(path) => write!(f, "Destination {} is a directory", path.display()),
+            Self::ReadOnly(path) => write!(f, "Destination {} is read-only", path.display()),
+            Self::PermissionDenied(path) => {
+                write!(f, "Permission denied writing {}", path.display())
+            }
+            Self::CreateParent(path, e) => {
+                write!(f, "Failed to create directory {}: {}", path.display(), e)
+            }
+            Self::Create(path, e) => write!(f, "Failed to create {}: {}", path.display(), e),
+            Self::Exists(path) => write!(f, "Destination {} already exists", path.display()),
+        }
+    }
+}
+
+impl Error for DLDestinationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::CreateParent(_, e) | Self::Create(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Checks that a local destination can be written, creating its parent directory.
+///
+/// Read-only files are renamed to `<path>.bak` when `replace_readonly` is set.
+pub fn prepare_local(path: &Path, replace_readonly: bool) -> Result<(), DLDestinationError> {
+    prepare_local_in(path, replace_readonly, None)
+}
+
+/// Checks the local destination like [prepare_local], creating its parent once per batch
+/// with the directories of the batch
+pub(crate) fn prepare_local_in(
+    path: &Path,
+    replace_readonly: bool,
+    dirs: Option<&CreatedDirs>,
+) -> Result<(), DLDestinationError> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        match dirs {
+            Some(dirs) => dirs.create_dir_all(parent),
+            None => create_dir_all(parent),
+        }
+        .map_err(|e| DLDestinationError::CreateParent(parent.to_path_buf(), e))?;
+        if !parent.is_dir() {
+            return Err(DLDestinationError::CreateParent(
+                parent.to_path_buf(),
+                io::Error::from(io::ErrorKind::NotADirectory),
+            ));
+        }
+    }
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(DLDestinationError::IsDirectory(path.to_path_buf()))
+        }
+        Ok(metadata) if metadata.permissions().readonly() => {
+            if !replace_readonly {
+                return Err(DLDestinationError::ReadOnly(path.to_path_buf()));
+            }
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(".bak");
+            rename(path, &backup).map_err(|e| DLDestinationError::from_io(path, e))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(any(feature = "webdav", feature = "s3", feature = "gcs"))]
+fn to_io(e: surf::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+/// Fails with the status of the responses of the remote backends that aren't successful
+#[cfg(any(feature = "webdav", feature = "s3", feature = "gcs"))]
+fn check_status(status: surf::StatusCode) -> io::Result<()> {
+    if status.is_success() {
+        Ok(())
+    } else if status == surf::StatusCode::NotFound {
+        Err(io::Error::new(io::ErrorKind::NotFound, status.to_string()))
+    } else {
+        Err(io::Error::other(status.to_string()))
+    }
+}
+
+/// WebDAV backend, uploads the files with HTTP PUT while they are downloaded.
+///
+/// The servers need to accept the uploads without a known length, S3 and Google Cloud
+/// Storage have their own backends with the `s3` and `gcs` features.
+#[cfg(feature = "webdav")]
+pub mod webdav {
+    use std::io;
+
+    use smol::io::{AsyncWriteExt, BufReader};
+    use surf::{Body, Client, Url, http::Method};
+
+    use super::{StorageBackend, StorageWriter, check_status, to_io};
+
+    /// Size of the pipe between the download and the upload, DEFAULT = 64KB
+    const PIPE_CAPACITY: usize = 64 * 1024;
+
+    /// WebDAV storage backend
+    #[derive(Clone)]
+    pub struct WebDavBackend {
+        base: Url,
+        headers: Vec<(String, String)>,
+        client: Client,
+    }
+
+    impl WebDavBackend {
+        /// Creates a new backend, the paths of the files are joined to the base URL
+        pub fn new(base: &str) -> Result<Self, String> {
+            let mut base = Url::parse(base).map_err(|e| e.to_string())?;
+            if !base.path().ends_with('/') {
+                base.set_path(&format!("{}/", base.path()));
+            }
+            Ok(Self {
+                base,
+                headers: Vec::new(),
+                client: Client::new(),
+            })
+        }
+        /// Adds a header sent on every request, e.g. `Authorization`
+        pub fn with_header(mut self, name: &str, value: &str) -> Self {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+        fn url(&self, path: &str) -> io::Result<Url> {
+            self.base
+                .join(path.trim_start_matches('/'))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        }
+        fn request(&self, mut builder: surf::RequestBuilder) -> surf::RequestBuilder {
+            for (name, value) in &self.headers {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            builder
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageBackend for WebDavBackend {
+        async fn create(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+            let (reader, writer) = piper::pipe(PIPE_CAPACITY);
+            let request = self
+                .request(self.client.put(self.url(path)?))
+                .body(Body::from_reader(BufReader::new(reader), None));
+            // the upload runs while the writer is being fed by the download
+            let upload = smol::spawn(async move {
+                let response = request.await.map_err(to_io)?;
+                check_status(response.status())
+            });
+            Ok(Box::new(WebDavWriter { writer, upload }))
+        }
+        async fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            let mut response = self
+                .request(self.client.get(self.url(path)?))
+                .await
+                .map_err(to_io)?;
+            check_status(response.status())?;
+            response.body_bytes().await.map_err(to_io)
+        }
+        async fn exists(&self, path: &str) -> bool {
+            let Ok(url) = self.url(path) else {
+                return false;
+            };
+            match self.request(self.client.head(url)).await {
+                Ok(response) => response.status().is_success(),
+                Err(_) => false,
+            }
+        }
+        async fn remove(&self, path: &str) -> io::Result<()> {
+            let response = self
+                .request(self.client.delete(self.url(path)?))
+                .await
+                .map_err(to_io)?;
+            check_status(response.status())
+        }
+        async fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+            let request = surf::RequestBuilder::new(Method::Move, self.url(from)?)
+                .header("Destination", self.url(to)?.as_str());
+            let response = self
+                .client
+                .send(self.request(request))
+                .await
+                .map_err(to_io)?;
+            check_status(response.status())
+        }
+    }
+
+    /// Writer streaming the file to the PUT request
+    pub struct WebDavWriter {
+        writer: piper::Writer,
+        upload: smol::Task<io::Result<()>>,
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageWriter for WebDavWriter {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.writer.write_all(buf).await
+        }
+        async fn finish(self: Box<Self>) -> io::Result<()> {
+            let WebDavWriter { writer, upload } = *self;
+            // closing the pipe ends the request body
+            drop(writer);
+            upload.await
+        }
+    }
+}
+
+/// Amazon S3 backend, uploads the files to the `s3://bucket/prefix/` of the base URL with
+/// the settings of [crate::s3::DLS3Config].
+///
+/// S3 needs the length of the uploads, the file is sent in parts of 8MB with a multipart
+/// upload while it's downloaded, the files smaller than a part with a single `PUT`.
+#[cfg(feature = "s3")]
+pub mod s3 {
+    use std::{io, mem};
+
+    use surf::{Client, Url};
+
+    use super::{StorageBackend, StorageWriter, check_status, to_io};
+    use crate::s3::DLS3Config;
+
+    /// Size of the parts of the multipart uploads, DEFAULT = 8MB
+    const PART_SIZE: usize = 8 * 1024 * 1024;
+
+    /// S3 storage backend
+    #[derive(Clone)]
+    pub struct S3Backend {
+        base: Url,
+        client: Client,
+    }
+
+    impl S3Backend {
+        /// Creates a new backend, the paths of the files are joined to the `s3://` base URL
+        pub fn new(base: &str, config: &DLS3Config) -> Result<Self, String> {
+            let mut base = Url::parse(base).map_err(|e| e.to_string())?;
+            if base.scheme() != "s3" || base.host_str().is_none() {
+                return Err(format!("{} isn't an s3://bucket/ URL", base));
+            }
+            if !base.path().ends_with('/') {
+                base.set_path(&format!("{}/", base.path()));
+            }
+            Ok(Self {
+                base,
+                client: Client::new().with(config.middleware()),
+            })
+        }
+        fn url(&self, path: &str) -> io::Result<Url> {
+            self.base
+                .join(path.trim_start_matches('/'))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageBackend for S3Backend {
+        async fn create(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+            Ok(Box::new(S3Writer {
+                client: self.client.clone(),
+                url: self.url(path)?,
+                buffer: Vec::new(),
+                upload: None,
+                parts: Vec::new(),
+            }))
+        }
+        async fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            let mut response = self.client.get(self.url(path)?).await.map_err(to_io)?;
+            check_status(response.status())?;
+            response.body_bytes().await.map_err(to_io)
+        }
+        async fn exists(&self, path: &str) -> bool {
+            let Ok(url) = self.url(path) else {
+                return false;
+            };
+            match self.client.head(url).await {
+                Ok(response) => response.status().is_success(),
+                Err(_) => false,
+            }
+        }
+        async fn remove(&self, path: &str) -> io::Result<()> {
+            let response = self.client.delete(self.url(path)?).await.map_err(to_io)?;
+            check_status(response.status())
+        }
+    }
+
+    /// Writer sending the file in the parts of a multipart upload
+    pub struct S3Writer {
+        client: Client,
+        url: Url,
+        buffer: Vec<u8>,
+        /// Id of the multipart upload, once the file outgrows a part
+        upload: Option<String>,
+        /// ETags of the parts sent
+        parts: Vec<String>,
+    }
+
+    impl S3Writer {
+        /// URL of the object with the parameters of the multipart upload
+        fn upload_url(&self, parameters: &[(&str, &str)]) -> Url {
+            let mut url = self.url.clone();
+            url.query_pairs_mut().extend_pairs(parameters);
+            url
+        }
+        /// Starts the multipart upload, returns its id
+        async fn start(&self) -> io::Result<String> {
+            let mut url = self.url.clone();
+            url.set_query(Some("uploads"));
+            let mut response = self.client.post(url).await.map_err(to_io)?;
+            check_status(response.status())?;
+            let body = response.body_string().await.map_err(to_io)?;
+            xml_value(&body, "UploadId")
+                .map(str::to_string)
+                .ok_or_else(|| io::Error::other("S3 answered without the id of the upload"))
+        }
+        /// Sends the part, starting the multipart upload with the first one
+        async fn send_part(&mut self, part: Vec<u8>) -> io::Result<()> {
+            let id = match &self.upload {
+                Some(id) => id.clone(),
+                None => self.upload.insert(self.start().await?).clone(),
+            };
+            let number = (self.parts.len() + 1).to_string();
+            let url = self.upload_url(&[("partNumber", &number), ("uploadId", &id)]);
+            let response = self.client.put(url).body(part).await.map_err(to_io)?;
+            check_status(response.status())?;
+            let etag = response
+                .header("ETag")
+                .map(|values| values.last().as_str().to_string())
+                .ok_or_else(|| io::Error::other("S3 answered the part without its ETag"))?;
+            self.parts.push(etag);
+            Ok(())
+        }
+        /// Completes the multipart upload with the ETags of its parts
+        async fn complete(&self, id: &str) -> io::Result<()> {
+            let mut body = String::from("<CompleteMultipartUpload>");
+            for (index, etag) in self.parts.iter().enumerate() {
+                body.push_str(&format!(
+                    "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                    index + 1,
+                    etag
+                ));
+            }
+            body.push_str("</CompleteMultipartUpload>");
+            let url = self.upload_url(&[("uploadId", id)]);
+            let mut response = self.client.post(url).body(body).await.map_err(to_io)?;
+            check_status(response.status())?;
+            // the errors after the upload starts are answered with 200
+            let body = response.body_string().await.map_err(to_io)?;
+            match xml_value(&body, "Code") {
+                Some(code) if body.contains("<Error>") => Err(io::Error::other(format!(
+                    "S3 failed to complete the upload: {}",
+                    code
+                ))),
+                _ => Ok(()),
+            }
+        }
+        /// Aborts the multipart upload, S3 keeps the parts of the unfinished ones
+        fn abort(&mut self) {
+            if let Some(id) = self.upload.take() {
+                let request = self.client.delete(self.upload_url(&[("uploadId", &id)]));
+                smol::spawn(async move {
+                    let _ = request.await;
+                })
+                .detach();
+            }
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageWriter for S3Writer {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.buffer.extend_from_slice(buf);
+            if self.buffer.len() >= PART_SIZE {
+                let part = mem::take(&mut self.buffer);
+                self.send_part(part).await?;
+            }
+            Ok(())
+        }
+        async fn finish(mut self: Box<Self>) -> io::Result<()> {
+            let last = mem::take(&mut self.buffer);
+            let Some(id) = self.upload.clone() else {
+                let response = self.client.put(self.url.clone()).body(last);
+                return check_status(response.await.map_err(to_io)?.status());
+            };
+            if !last.is_empty() {
+                self.send_part(last).await?;
+            }
+            self.complete(&id).await?;
+            self.upload = None;
+            Ok(())
+        }
+    }
+
+    impl Drop for S3Writer {
+        fn drop(&mut self) {
+            self.abort();
+        }
+    }
+
+    /// Text of the first element of the XML
+    fn xml_value<'a>(xml: &'a str, element: &str) -> Option<&'a str> {
+        let start = xml.find(&format!("<{}>", element))? + element.len() + 2;
+        let end = xml[start..].find(&format!("</{}>", element))? + start;
+        Some(&xml[start..end])
+    }
+}
+
+/// Google Cloud Storage backend, uploads the files to the `gs://bucket/prefix/` of the base
+/// URL with the credentials of [crate::gcs::DLGcsConfig].
+///
+/// The file is sent in chunks of 8MB with a resumable upload of the XML API while it's
+/// downloaded, the files smaller than a chunk with a single `PUT`.
+#[cfg(feature = "gcs")]
+pub mod gcs {
+    use std::{io, mem};
+
+    use surf::{Client, StatusCode, Url};
+
+    use super::{StorageBackend, StorageWriter, check_status, to_io};
+    use crate::gcs::DLGcsConfig;
+
+    /// Size of the chunks of the resumable uploads, a multiple of 256KB, DEFAULT = 8MB
+    const CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+    /// GCS storage backend
+    #[derive(Clone)]
+    pub struct GcsBackend {
+        base: Url,
+        client: Client,
+    }
+
+    impl GcsBackend {
+        /// Creates a new backend, the paths of the files are joined to the `gs://` base URL
+        pub fn new(base: &str, config: &DLGcsConfig) -> Result<Self, String> {
+            let mut base = Url::parse(base).map_err(|e| e.to_string())?;
+            if base.scheme() != "gs" || base.host_str().is_none() {
+                return Err(format!("{} isn't a gs://bucket/ URL", base));
+            }
+            if !base.path().ends_with('/') {
+                base.set_path(&format!("{}/", base.path()));
+            }
+            Ok(Self {
+                base,
+                client: Client::new().with(config.write_middleware()),
+            })
+        }
+        fn url(&self, path: &str) -> io::Result<Url> {
+            self.base
+                .join(path.trim_start_matches('/'))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageBackend for GcsBackend {
+        async fn create(&self, path: &str) -> io::Result<Box<dyn StorageWriter>> {
+            Ok(Box::new(GcsWriter {
+                client: self.client.clone(),
+                url: self.url(path)?,
+                buffer: Vec::new(),
+                session: None,
+                sent: 0,
+            }))
+        }
+        async fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+            let mut response = self.client.get(self.url(path)?).await.map_err(to_io)?;
+            check_status(response.status())?;
+            response.body_bytes().await.map_err(to_io)
+        }
+        async fn exists(&self, path: &str) -> bool {
+            let Ok(url) = self.url(path) else {
+                return false;
+            };
+            match self.client.head(url).await {
+                Ok(response) => response.status().is_success(),
+                Err(_) => false,
+            }
+        }
+        async fn remove(&self, path: &str) -> io::Result<()> {
+            let response = self.client.delete(self.url(path)?).await.map_err(to_io)?;
+            check_status(response.status())
+        }
+    }
+
+    /// Writer sending the file in the chunks of a resumable upload
+    pub struct GcsWriter {
+        client: Client,
+        url: Url,
+        buffer: Vec<u8>,
+        /// URL of the resumable upload, once the file outgrows a chunk
+        session: Option<Url>,
+        /// Bytes sent to the resumable upload
+        sent: u64,
+    }
+
+    impl GcsWriter {
+        /// Starts the resumable upload, returns its URL
+        async fn start(&self) -> io::Result<Url> {
+            let request = self.client.post(self.url.clone());
+            let response = request
+                .header("x-goog-resumable", "start")
+                .await
+                .map_err(to_io)?;
+            check_status(response.status())?;
+            response
+                .header("Location")
+                .and_then(|values| Url::parse(values.last().as_str()).ok())
+                .ok_or_else(|| io::Error::other("GCS answered without the upload URL"))
+        }
+        /// Sends the chunk, starting the resumable upload with the first one. The size of
+        /// the file is only told with the last chunk
+        async fn send_chunk(&mut self, chunk: Vec<u8>, total: Option<u64>) -> io::Result<()> {
+            let session = match &self.session {
+                Some(session) => session.clone(),
+                None => self.session.insert(self.start().await?).clone(),
+            };
+            let total = total.map_or("*".to_string(), |total| total.to_string());
+            let range = match chunk.len() as u64 {
+                0 => format!("bytes */{}", total),
+                len => format!("bytes {}-{}/{}", self.sent, self.sent + len - 1, total),
+            };
+            self.sent += chunk.len() as u64;
+            let response = self
+                .client
+                .put(session)
+                .header("Content-Range", range)
+                .body(chunk)
+                .await
+                .map_err(to_io)?;
+            // the chunks before the last one are answered with 308 Resume Incomplete
+            match response.status() {
+                StatusCode::PermanentRedirect => Ok(()),
+                status => check_status(status),
+            }
+        }
+        /// Cancels the resumable upload
+        fn abort(&mut self) {
+            if let Some(session) = self.session.take() {
+                let request = self.client.delete(session);
+                smol::spawn(async move {
+                    let _ = request.await;
+                })
+                .detach();
+            }
+        }
+    }
+
+    #[surf::utils::async_trait]
+    impl StorageWriter for GcsWriter {
+        async fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+            self.buffer.extend_from_slice(buf);
+            while self.buffer.len() >= CHUNK_SIZE {
+                // the chunks before the last one are multiples of 256KB
+                let rest = self.buffer.split_off(CHUNK_SIZE);
+                let chunk = mem::replace(&mut self.buffer, rest);
+                self.send_chunk(chunk, None).await?;
+            }
+            Ok(())
+        }
+        async fn finish(mut self: Box<Self>) -> io::Result<()> {
+            let last = mem::take(&mut self.buffer);
+            if self.session.is_none() {
+                let response = self.client.put(self.url.clone()).body(last);
+                return check_status(response.await.map_err(to_io)?.status());
+            }
+            let total = self.sent + last.len() as u64;
+            self.send_chunk(last, Some(total)).await?;
+            self.session = None;
+            Ok(())
+        }
+    }
+
+    impl Drop for GcsWriter {
+        fn drop(&mut self) {
+            self.abort();
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Token bucket limiting the bytes per second of the downloads sharing it
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    rate: f64,
+    /// Available bytes, negative when the readers are ahead of the rate, and the last refill
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            // a second of burst
+            bucket: Mutex::new((bytes_per_sec as f64, Instant::now())),
+        }
+    }
+    /// Takes the bytes read from the bucket, returns the time until the rate allows them
+    fn reserve(&self, bytes: usize) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, refilled) = &mut *bucket;
+        let now = Instant::now();
+        *tokens =
+            (*tokens + now.duration_since(*refilled).as_secs_f64() * self.rate).min(self.rate);
+        *refilled = now;
+        *tokens -= bytes as f64;
+        Duration::from_secs_f64((-*tokens).max(0.0) / self.rate)
+    }
+}
+
+/// Takes the bytes read from every limit, waiting until the slowest one allows them
+pub(crate) async fn consume(limits: &[Option<&Throttle>], bytes: usize) {
+    let wait = limits
+        .iter()
+        .flatten()
+        .map(|limit| limit.reserve(bytes))
+        .max()
+        .unwrap_or_default();
+    if !wait.is_zero() {
+        smol::Timer::after(wait).await;
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fmt, future::Future, time::Duration};
+
+use crate::DwldError;
+
+/// Timeouts of a download, unset values don't expire
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLTimeouts {
+    /// Time until the headers of the response arrive, including the connection
+    pub connect: Option<Duration>,
+    /// Time waiting for every chunk of the body
+    pub read: Option<Duration>,
+    /// Time of the whole download
+    pub total: Option<Duration>,
+}
+
+impl DLTimeouts {
+    /// Uses the timeouts of `defaults` for the unset values
+    pub fn or(self, defaults: DLTimeouts) -> Self {
+        Self {
+            connect: self.connect.or(defaults.connect),
+            read: self.read.or(defaults.read),
+            total: self.total.or(defaults.total),
+        }
+    }
+}
+
+/// Step of the download that timed out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DLTimeoutKind {
+    Connect,
+    Read,
+    Total,
+}
+
+impl fmt::Display for DLTimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DLTimeoutKind::Connect => f.write_str("waiting for the response"),
+            DLTimeoutKind::Read => f.write_str("reading the body"),
+            DLTimeoutKind::Total => f.write_str("downloading the file"),
+        }
+    }
+}
+
+/// Runs the future failing with [DwldError::Timeout] if it takes longer than `duration`
+pub(crate) async fn timeout<T>(
+    duration: Option<Duration>,
+    kind: DLTimeoutKind,
+    future: impl Future<Output = T>,
+) -> Result<T, DwldError> {
+    match duration {
+        Some(duration) => {
+            smol::future::or(async { Ok(future.await) }, async {
+                smol::Timer::after(duration).await;
+                Err(DwldError::Timeout(kind))
+            })
+            .await
+        }
+        None => Ok(future.await),
+    }
+}
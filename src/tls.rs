@@ -0,0 +1,120 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use rustls::internal::pemfile;
+
+/// TLS settings of the connections, e.g. to trust the private CA of internal artifact
+/// servers or to authenticate the client with a certificate (mTLS)
+#[derive(Clone)]
+pub struct DLTlsConfig {
+    config: rustls::ClientConfig,
+}
+
+impl Default for DLTlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DLTlsConfig {
+    /// Settings trusting the public root certificates, like the default ones
+    pub fn new() -> Self {
+        let mut config = rustls::ClientConfig::new();
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        Self { config }
+    }
+    /// Settings trusting only the root certificates added to them
+    pub fn without_public_roots() -> Self {
+        Self {
+            config: rustls::ClientConfig::new(),
+        }
+    }
+    /// Trusts the root certificates of the PEM data, e.g. of a private CA
+    pub fn with_root_certificates(mut self, pem: &[u8]) -> Result<Self, DLTlsError> {
+        match self.config.root_store.add_pem_file(&mut &pem[..]) {
+            Ok((added, _)) if added > 0 => Ok(self),
+            _ => Err(DLTlsError::Certificate),
+        }
+    }
+    /// Authenticates the client with the PEM certificate chain and private key, sent to
+    /// the servers asking for a client certificate. PKCS#8 and RSA keys are supported
+    pub fn with_client_certificate(
+        mut self,
+        certificate: &[u8],
+        key: &[u8],
+    ) -> Result<Self, DLTlsError> {
+        let chain = pemfile::certs(&mut &certificate[..])
+            .ok()
+            .filter(|chain| !chain.is_empty())
+            .ok_or(DLTlsError::Certificate)?;
+        let key = pemfile::pkcs8_private_keys(&mut &key[..])
+            .ok()
+            .and_then(|keys| keys.into_iter().next())
+            .or_else(|| {
+                let keys = pemfile::rsa_private_keys(&mut &key[..]).ok()?;
+                keys.into_iter().next()
+            })
+            .ok_or(DLTlsError::PrivateKey)?;
+        self.config
+            .set_single_client_cert(chain, key)
+            .map_err(DLTlsError::ClientCertificate)?;
+        Ok(self)
+    }
+    /// Disables the verification of the server certificates, see
+    /// [crate::Downloader::danger_accept_invalid_certs]
+    pub(crate) fn accepting_invalid_certs(mut self) -> Self {
+        let verifier = Arc::new(AcceptAnyCertificate);
+        self.config.dangerous().set_certificate_verifier(verifier);
+        self
+    }
+    pub(crate) fn client_config(&self) -> Arc<rustls::ClientConfig> {
+        Arc::new(self.config.clone())
+    }
+}
+
+/// Verifier trusting every server certificate, even expired, self-signed or for another
+/// host. INSECURE, the connections can be intercepted
+struct AcceptAnyCertificate;
+
+impl rustls::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &rustls::RootCertStore,
+        _: &[rustls::Certificate],
+        _: webpki::DNSNameRef<'_>,
+        _: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// Error building the TLS settings
+#[derive(Debug)]
+pub enum DLTlsError {
+    /// The PEM data doesn't have any valid certificate
+    Certificate,
+    /// The PEM data doesn't have any private key
+    PrivateKey,
+    /// The client certificate doesn't match its key
+    ClientCertificate(rustls::TLSError),
+}
+
+impl fmt::Display for DLTlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Certificate => write!(f, "No valid certificate in the PEM data"),
+            Self::PrivateKey => write!(f, "No private key in the PEM data"),
+            Self::ClientCertificate(e) => write!(f, "Invalid client certificate: {}", e),
+        }
+    }
+}
+
+impl Error for DLTlsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::ClientCertificate(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Tracing of the requests and the downloads, compiled out without the `tracing` feature
+
+/// Emits an event at the level, e.g. `trace_event!(DEBUG, status = 200, "response")`
+macro_rules! trace_event {
+    ($level:ident, $($event:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::event!(tracing::Level::$level, $($event)*);
+    }};
+}
+
+/// Runs the future inside a span, e.g. `instrumented!(future, "download", path = %path)`
+macro_rules! instrumented {
+    ($future:expr, $($span:tt)*) => {{
+        // the fields are taken before the future moves them
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!($($span)*);
+        // boxed, the instrumented futures are moved around and overflow the stack of the
+        // threads of the debug builds
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument(Box::pin($future), span);
+        #[cfg(not(feature = "tracing"))]
+        let future = $future;
+        future
+    }};
+}
@@ -0,0 +1,67 @@
+use std::{io, sync::Arc};
+
+/// Streaming transformation of the response body, applied before writing it to the file
+pub trait DLTransformer: Send {
+    /// Transforms a chunk of the body, returns the bytes to write
+    fn transform(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>>;
+    /// Called at the end of the body, returns the remaining bytes to write
+    fn finish(&mut self) -> io::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
+
+impl<F: FnMut(&[u8]) -> io::Result<Vec<u8>> + Send> DLTransformer for F {
+    fn transform(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        self(chunk)
+    }
+}
+
+/// Creates a new transformer for every download of the file
+pub type DLTransformFactory = Arc<dyn Fn() -> Box<dyn DLTransformer> + Send + Sync>;
+
+/// Data checked by the hashes of a transformed file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum HashStage {
+    /// The body as sent by the server
+    Raw,
+    /// The transformed data written to the file
+    #[default]
+    Transformed,
+}
+
+/// Decompresses a gzip body while it's downloaded
+#[cfg(feature = "gzip")]
+pub struct Gunzip {
+    decoder: flate2::write::GzDecoder<Vec<u8>>,
+}
+
+#[cfg(feature = "gzip")]
+impl Gunzip {
+    pub fn new() -> Self {
+        Self {
+            decoder: flate2::write::GzDecoder::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Default for Gunzip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl DLTransformer for Gunzip {
+    fn transform(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        use std::io::Write;
+        self.decoder.write_all(chunk)?;
+        Ok(std::mem::take(self.decoder.get_mut()))
+    }
+    fn finish(&mut self) -> io::Result<Vec<u8>> {
+        self.decoder.try_finish()?;
+        Ok(std::mem::take(self.decoder.get_mut()))
+    }
+}
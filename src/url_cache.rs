@@ -0,0 +1,223 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+use surf::{
+    Response,
+    http::{cache::Expires, conditional::LastModified},
+};
+
+/// Cache of the responses by URL, following the `Cache-Control` and `Expires` headers.
+///
+/// Useful for manifest-like files, which change without changing their URL
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub struct DLUrlCache {
+    pub path: PathBuf,
+}
+
+impl DLUrlCache {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+    /// Removes the cached response of the URL
+    pub fn remove(&self, url: &str) -> io::Result<()> {
+        let _ = fs::remove_file(self.meta_path(url));
+        match fs::remove_file(self.body_path(url)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+    fn key(url: &str) -> String {
+        hex::encode(Sha256::digest(url.as_bytes()))
+    }
+    fn body_path(&self, url: &str) -> PathBuf {
+        self.path.join(Self::key(url))
+    }
+    fn meta_path(&self, url: &str) -> PathBuf {
+        self.path.join(format!("{}.meta", Self::key(url)))
+    }
+    /// Cached response of the URL
+    pub(crate) fn lookup(&self, url: &str) -> Option<CacheEntry> {
+        let body = self.body_path(url);
+        if !body.is_file() {
+            return None;
+        }
+        let meta = fs::read_to_string(self.meta_path(url)).ok()?;
+        let mut entry = CacheEntry {
+            body,
+            expires: None,
+            etag: None,
+            last_modified: None,
+            must_revalidate: false,
+        };
+        for line in meta.lines() {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "expires" => {
+                    entry.expires = value
+                        .parse()
+                        .ok()
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                }
+                "etag" => entry.etag = Some(value.to_string()),
+                "last-modified" => entry.last_modified = Some(value.to_string()),
+                "must-revalidate" => entry.must_revalidate = true,
+                _ => {}
+            }
+        }
+        Some(entry)
+    }
+    /// Stores the downloaded file as the response of the URL
+    pub(crate) fn store(&self, url: &str, policy: &CachePolicy, file: &Path) -> io::Result<()> {
+        if policy.no_store {
+            return self.remove(url);
+        }
+        crate::storage::create_dir_all(&self.path)?;
+        let body = self.body_path(url);
+        let tmp = body.with_extension("tmp");
+        fs::copy(file, &tmp)?;
+        crate::storage::rename(&tmp, &body)?;
+        self.write_meta(url, policy)
+    }
+    /// Stores the data as the response of the URL
+    pub(crate) fn store_data(
+        &self,
+        url: &str,
+        policy: &CachePolicy,
+        data: &[u8],
+    ) -> io::Result<()> {
+        if policy.no_store {
+            return self.remove(url);
+        }
+        crate::storage::create_dir_all(&self.path)?;
+        let body = self.body_path(url);
+        let tmp = body.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        crate::storage::rename(&tmp, &body)?;
+        self.write_meta(url, policy)
+    }
+    /// Updates the freshness of a response revalidated by the server
+    pub(crate) fn refresh(
+        &self,
+        url: &str,
+        entry: &CacheEntry,
+        policy: &CachePolicy,
+    ) -> io::Result<()> {
+        if policy.no_store {
+            return self.remove(url);
+        }
+        // a 304 response may not repeat the validators
+        let policy = CachePolicy {
+            etag: policy.etag.clone().or_else(|| entry.etag.clone()),
+            last_modified: policy
+                .last_modified
+                .clone()
+                .or_else(|| entry.last_modified.clone()),
+            ..policy.clone()
+        };
+        self.write_meta(url, &policy)
+    }
+    fn write_meta(&self, url: &str, policy: &CachePolicy) -> io::Result<()> {
+        let mut meta = Vec::new();
+        // responses with no-cache are stored but always revalidated
+        if let Some(expires) = policy.expires.filter(|_| !policy.no_cache) {
+            let secs = expires.duration_since(UNIX_EPOCH).unwrap_or_default();
+            writeln!(meta, "expires {}", secs.as_secs())?;
+        }
+        if let Some(etag) = &policy.etag {
+            writeln!(meta, "etag {}", etag)?;
+        }
+        if let Some(last_modified) = &policy.last_modified {
+            writeln!(meta, "last-modified {}", last_modified)?;
+        }
+        if policy.must_revalidate {
+            writeln!(meta, "must-revalidate")?;
+        }
+        fs::write(self.meta_path(url), meta)
+    }
+}
+
+/// Response stored in the URL cache
+pub(crate) struct CacheEntry {
+    /// File with the body of the response
+    pub body: PathBuf,
+    /// Until when the response can be used without asking the server
+    pub expires: Option<SystemTime>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// The response can't be used stale when the server is unreachable
+    pub must_revalidate: bool,
+}
+
+impl CacheEntry {
+    /// Checks if the response can be used without asking the server
+    pub fn is_fresh(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| expires > SystemTime::now())
+    }
+}
+
+/// Caching rules of a response
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CachePolicy {
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub must_revalidate: bool,
+    pub expires: Option<SystemTime>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Time of the `Last-Modified` header
+    pub modified: Option<SystemTime>,
+}
+
+impl CachePolicy {
+    pub fn from_response(response: &Response) -> Self {
+        let mut policy = CachePolicy {
+            etag: response.header("ETag").map(|v| v.as_str().to_string()),
+            last_modified: response
+                .header("Last-Modified")
+                .map(|v| v.as_str().to_string()),
+            modified: LastModified::from_headers(response)
+                .ok()
+                .flatten()
+                .map(|modified| modified.modified()),
+            ..Default::default()
+        };
+        let mut max_age = None;
+        for value in response.header("Cache-Control").into_iter().flatten() {
+            for directive in value.as_str().split(',') {
+                let directive = directive.trim().to_ascii_lowercase();
+                let (name, arg) = directive.split_once('=').unwrap_or((&directive, ""));
+                match name {
+                    "no-store" => policy.no_store = true,
+                    "no-cache" => policy.no_cache = true,
+                    "must-revalidate" | "proxy-revalidate" => policy.must_revalidate = true,
+                    // unparseable ages make the response stale
+                    "max-age" => max_age = Some(arg.trim_matches('"').parse().unwrap_or(0)),
+                    _ => {}
+                }
+            }
+        }
+        // max-age takes precedence over Expires, invalid Expires values are in the past
+        policy.expires = match max_age {
+            Some(secs) => Some(SystemTime::now() + Duration::from_secs(secs)),
+            None if response.header("Expires").is_some() => Some(
+                Expires::from_headers(response)
+                    .ok()
+                    .flatten()
+                    .map(|expires| expires.expiration())
+                    .unwrap_or(UNIX_EPOCH),
+            ),
+            None => None,
+        };
+        policy
+    }
+}
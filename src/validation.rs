@@ -0,0 +1,67 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::DLFile;
+
+/// What to do when several files declare the same destination path
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum DuplicatePolicy {
+    /// Fail the validation if the files differ
+    #[default]
+    Error,
+    /// Download only the first file declared
+    KeepFirst,
+    /// Download only the last file declared
+    KeepLast,
+}
+
+/// Normalized destination of a file, so `a/./b` and `a/b` are the same target
+pub(crate) fn target(path: &str) -> PathBuf {
+    Path::new(path).components().collect()
+}
+
+/// Checks if two files with the same destination write the same content
+pub(crate) fn same_content(a: &DLFile, b: &DLFile) -> bool {
+    a.url == b.url && a.hashes == b.hashes
+}
+
+/// Removes the duplicated destinations of the files, applying the policy on conflicts.
+///
+/// Files declared twice with the same URL and hashes are always downloaded once.
+pub(crate) fn resolve_duplicates(
+    files: &[DLFile],
+    policy: DuplicatePolicy,
+) -> Result<Vec<&DLFile>, String> {
+    let mut selected: Vec<Option<&DLFile>> = Vec::with_capacity(files.len());
+    let mut targets: HashMap<PathBuf, usize> = HashMap::new();
+    let mut conflicts = Vec::new();
+
+    for file in files {
+        let Some(&index) = targets.get(&target(&file.path)) else {
+            targets.insert(target(&file.path), selected.len());
+            selected.push(Some(file));
+            continue;
+        };
+        let previous = selected[index].expect("selected file");
+        if same_content(previous, file) {
+            continue;
+        }
+        match policy {
+            DuplicatePolicy::Error => conflicts.push(file.path.clone()),
+            DuplicatePolicy::KeepFirst => {}
+            DuplicatePolicy::KeepLast => selected[index] = Some(file),
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(format!(
+            "Several files with different content have the same destination: {}",
+            conflicts.join(", ")
+        ));
+    }
+    Ok(selected.into_iter().flatten().collect())
+}
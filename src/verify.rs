@@ -0,0 +1,30 @@
+use std::io::{self, Read};
+
+use crate::DLFile;
+
+/// Custom integrity check of a downloaded file, e.g. a signed digest or a digest computed
+/// by an HSM
+pub trait Verifier: Send + Sync {
+    /// Checks the data of the file, read from its beginning, returns if it's valid. The
+    /// errors fail the download, an invalid file fails like a hash mismatch
+    fn verify(&self, file: &DLFile, data: &mut dyn Read) -> io::Result<bool>;
+}
+
+impl<F: Fn(&DLFile, &mut dyn Read) -> io::Result<bool> + Send + Sync> Verifier for F {
+    fn verify(&self, file: &DLFile, data: &mut dyn Read) -> io::Result<bool> {
+        self(file, data)
+    }
+}
+
+/// How the verifier of a file is combined with its hashes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum VerifierMode {
+    /// The file is checked by its hashes and then by the verifier
+    #[default]
+    Additional,
+    /// The file is only checked by the verifier, the hashes are still the keys of the CAS
+    /// storage and of the destinations kept by [DLFile::with_skip_if_valid]
+    Replace,
+}
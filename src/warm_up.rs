@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+
+use surf::Client;
+
+use crate::{
+    DLFile, DLTimeouts, mirror,
+    timeout::{self, DLTimeoutKind},
+};
+
+/// Opens up to `connections` connections to every origin of the files before downloading
+/// them, so the first requests don't wait for the DNS and the TLS handshakes. The
+/// connections are kept in the pool of the client; the failures are ignored, the
+/// downloads report them
+pub(crate) async fn warm_up(
+    client: &Client,
+    files: &[&DLFile],
+    connections: usize,
+    timeouts: DLTimeouts,
+) {
+    // the first URL of every origin, with the files needing a connection to it
+    let mut origins: HashMap<String, (&str, &DLFile, usize)> = HashMap::new();
+    let urls = files.iter().flat_map(|file| {
+        let parts = file.parts.iter().map(|part| part.url.as_str());
+        std::iter::once(file.url.as_str())
+            .chain(parts)
+            .map(move |url| (url, *file))
+    });
+    for (url, file) in urls {
+        if let Some(origin) = mirror::origin(url) {
+            origins.entry(origin).or_insert((url, file, 0)).2 += 1;
+        }
+    }
+    trace_event!(DEBUG, origins = origins.len(), "warming up");
+
+    let requests = origins.into_values().flat_map(|(url, file, files)| {
+        (0..files.min(connections)).map(move |_| async move {
+            // the responses to HEAD leave the connection ready for the next request
+            let request = crate::add_headers(client.head(url), &file.headers);
+            let _ = timeout::timeout(timeouts.connect, DLTimeoutKind::Connect, request).await;
+        })
+    });
+    futures::future::join_all(requests).await;
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "encryption")]
+
+use std::{fs, io::Write, path::PathBuf};
+
+use dwldutil::{
+    cas::{DLLinkMode, DLStorage},
+    encryption::DLEncryptionKey,
+};
+
+const HASH: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+const DATA: &[u8] = b"hello";
+
+/// Empty directory for the files of a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dwldutil-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn encrypted_run_replaces_links_to_the_blob() {
+    let dir = temp_dir("cas-encryption");
+    let objects = dir.join("objects").to_string_lossy().into_owned();
+    let first = dir.join("first").to_string_lossy().into_owned();
+    let second = dir.join("second").to_string_lossy().into_owned();
+
+    // an unencrypted session links the destinations to the plain blob
+    let plain = DLStorage::new(&objects).with_link_mode(DLLinkMode::Symlink);
+    plain
+        .new_file(HASH, &first)
+        .unwrap()
+        .write_all(DATA)
+        .unwrap();
+    assert!(fs::symlink_metadata(&first).unwrap().is_symlink());
+
+    // the encrypted session seals the blob, leaving a plain copy in the destination
+    let encrypted = DLStorage::new(&objects).with_encryption(DLEncryptionKey::new([7; 32]));
+    encrypted.seal(HASH, &first).unwrap();
+    assert!(!fs::symlink_metadata(&first).unwrap().is_symlink());
+    assert_eq!(fs::read(&first).unwrap(), DATA);
+    assert_ne!(fs::read(plain.path(HASH)).unwrap(), DATA);
+
+    // a stale link to the sealed blob is replaced by the decrypted file
+    plain.symlink(HASH, &second).unwrap();
+    encrypted.materialize(HASH, &second).unwrap();
+    assert!(!fs::symlink_metadata(&second).unwrap().is_symlink());
+    assert_eq!(fs::read(&second).unwrap(), DATA);
+
+    // the blob is still intact
+    let third = dir.join("third").to_string_lossy().into_owned();
+    encrypted.materialize(HASH, &third).unwrap();
+    assert_eq!(fs::read(&third).unwrap(), DATA);
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpListener,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use dwldutil::{
+    DLFile, DLHashes, DLStatus, Downloader, DwldError,
+    indicator::{IndicateSignal, Indicator, IndicatorFactory, Silent},
+};
+
+const EMPTY_SHA1: &str = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+const TINY_SHA1: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+
+/// Serves `body` to every request, returning the URL of the server
+fn serve(body: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(body);
+        }
+    });
+    format!("http://{}/file", addr)
+}
+
+/// Empty directory for the files of a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dwldutil-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[derive(Default, Clone)]
+struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+}
+struct RecorderChild {
+    events: Arc<Mutex<Vec<String>>>,
+}
+impl IndicatorFactory for Recorder {
+    fn create_task(&self, _name: &str, size: u64) -> impl Indicator {
+        self.events.lock().unwrap().push(format!("size {}", size));
+        RecorderChild {
+            events: self.events.clone(),
+        }
+    }
+}
+impl Indicator for RecorderChild {
+    fn effect(&mut self, position: u64) {
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("effect {}", position));
+    }
+    fn signal(&mut self, signal: IndicateSignal) {
+        if let IndicateSignal::Success() = signal {
+            self.events.lock().unwrap().push("success".to_string());
+        }
+    }
+}
+
+#[test]
+fn downloads_empty_file() {
+    let dir = temp_dir("empty");
+    let path = dir.join("empty.bin");
+    let file = DLFile::new()
+        .with_url(&serve(b""))
+        .with_path(&path)
+        .with_hashes(DLHashes::new().sha1(EMPTY_SHA1));
+    Downloader::<Silent>::new()
+        .with_files(vec![file])
+        .start()
+        .unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"");
+}
+
+#[test]
+fn downloads_tiny_file() {
+    let dir = temp_dir("tiny");
+    let path = dir.join("tiny.txt");
+    let file = DLFile::new()
+        .with_url(&serve(b"hello"))
+        .with_path(&path)
+        .with_size(5)
+        .with_hashes(DLHashes::new().sha1(TINY_SHA1));
+    Downloader::<Silent>::new()
+        .with_files(vec![file])
+        .start()
+        .unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"hello");
+}
+
+#[test]
+fn verifies_empty_file() {
+    let dir = temp_dir("empty-mismatch");
+    let file = DLFile::new()
+        .with_url(&serve(b""))
+        .with_path(dir.join("empty.bin"))
+        .with_hashes(DLHashes::new().sha1(TINY_SHA1));
+    let result = Downloader::<Silent>::new().with_files(vec![file]).start();
+    match result {
+        Err(DwldError::Batch { errors, .. }) => {
+            assert!(matches!(errors[0].1, DwldError::HashMismatch { .. }))
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn skips_valid_empty_destination() {
+    let dir = temp_dir("empty-valid");
+    let path = dir.join("empty.bin");
+    fs::write(&path, b"").unwrap();
+    let file = DLFile::new()
+        .with_url("http://127.0.0.1:9/unreachable")
+        .with_path(&path)
+        .with_hashes(DLHashes::new().sha1(EMPTY_SHA1))
+        .with_skip_if_valid(true);
+    let status = smol::block_on(file.download(Silent.create_task("", 0), Default::default()));
+    assert!(matches!(status, Ok(DLStatus::Skipped(_))));
+}
+
+#[cfg(feature = "cas")]
+#[test]
+fn stores_empty_blob_in_cas() {
+    use dwldutil::cas::DLStorage;
+
+    let dir = temp_dir("empty-cas");
+    let storage = DLStorage::new(dir.join("cas").to_str().unwrap());
+    let file = DLFile::new()
+        .with_url(&serve(b""))
+        .with_hashes(DLHashes::new().sha1(EMPTY_SHA1))
+        .with_cas(storage.clone());
+
+    let first = file.clone().with_path(dir.join("first.bin"));
+    let status = smol::block_on(first.download(Silent.create_task("", 0), Default::default()));
+    assert!(matches!(status, Ok(DLStatus::Downloaded)));
+    assert_eq!(storage.find(EMPTY_SHA1), Some(storage.path(EMPTY_SHA1)));
+
+    let second = file.with_path(dir.join("second.bin"));
+    let status = smol::block_on(second.download(Silent.create_task("", 0), Default::default()));
+    assert!(matches!(status, Ok(DLStatus::Cached)));
+    assert_eq!(fs::read(dir.join("second.bin")).unwrap(), b"");
+}
+
+#[test]
+fn completes_progress_of_empty_file() {
+    let dir = temp_dir("empty-progress");
+    let recorder = Recorder::default();
+    let file = DLFile::new()
+        .with_url(&serve(b""))
+        .with_path(dir.join("empty.bin"));
+    Downloader::new()
+        .with_indicator(recorder.clone())
+        .with_files(vec![file])
+        .start()
+        .unwrap();
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.first().map(String::as_str), Some("size 0"));
+    assert_eq!(events.last().map(String::as_str), Some("success"));
+    assert!(events.iter().any(|event| event == "effect 0"));
+}
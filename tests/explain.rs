@@ -0,0 +1,179 @@
+#![cfg(feature = "cas")]
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use dwldutil::{DLFile, DLHashes, Downloader, OverwritePolicy, cas::DLStorage, indicator::Silent};
+
+const HASH: &str = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+const DATA: &[u8] = b"hello";
+
+/// Empty directory for the files of a test
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dwldutil-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Batch with a file for every source, the paths are relative to `dir`
+fn batch(dir: &Path) -> Downloader<Silent> {
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let storage = DLStorage::new(&path("objects"));
+    storage
+        .new_file(HASH, &path("blob"))
+        .unwrap()
+        .write_all(DATA)
+        .unwrap();
+    fs::write(path("existing.bin"), b"old").unwrap();
+    fs::write(path("valid.bin"), DATA).unwrap();
+
+    Downloader::<Silent>::new()
+        .with_connect_timeout(Duration::from_secs(10))
+        .with_overwrite_policy(OverwritePolicy::Overwrite)
+        .with_files(vec![
+            DLFile::new()
+                .with_url("https://example.com/cached.bin")
+                .with_path(path("cached.bin"))
+                .with_hashes(DLHashes::new().sha1(HASH))
+                .with_cas(storage),
+            DLFile::new()
+                .with_url("https://example.com/existing.bin")
+                .with_path(path("existing.bin"))
+                .with_overwrite_policy(OverwritePolicy::Skip)
+                .with_priority(5),
+            DLFile::new()
+                .with_url("https://example.com/valid.bin")
+                .with_path(path("valid.bin"))
+                .with_hashes(DLHashes::new().sha1(HASH))
+                .with_skip_if_valid(true),
+            DLFile::new()
+                .with_url("https://example.com/fresh.bin")
+                .with_path(path("fresh.bin"))
+                .with_read_timeout(Duration::from_secs(3))
+                .with_header("Authorization", "secret")
+                .with_metadata("kind", "model")
+                .with_priority(9),
+        ])
+}
+
+#[test]
+fn explains_the_batch_in_start_order() {
+    let dir = temp_dir("explain");
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let explanation = batch(&dir).explain().unwrap();
+
+    // the higher priorities first, the rest in the order they were added
+    let expected = format!(
+        "1. {} <- https://example.com/fresh.bin: network\n\
+         2. {} <- https://example.com/existing.bin: skip, Destination already exists\n\
+         3. {} <- https://example.com/cached.bin: CAS storage\n\
+         4. {} <- https://example.com/valid.bin: skip, Destination already valid\n",
+        path("fresh.bin"),
+        path("existing.bin"),
+        path("cached.bin"),
+        path("valid.bin"),
+    );
+    assert_eq!(explanation.to_string(), expected);
+
+    // the settings of the file replace the ones of the downloader
+    let fresh = &explanation.files[0].settings;
+    assert_eq!(fresh.timeouts.connect, Some(Duration::from_secs(10)));
+    assert_eq!(fresh.timeouts.read, Some(Duration::from_secs(3)));
+    assert_eq!(fresh.headers, vec!["Authorization".to_string()]);
+    assert_eq!(
+        explanation.files[1].settings.overwrite_policy,
+        OverwritePolicy::Skip
+    );
+    assert_eq!(
+        explanation.files[2].settings.overwrite_policy,
+        OverwritePolicy::Overwrite
+    );
+}
+
+/// Settings of the batch, with the ones of the file in `overrides`
+#[cfg(feature = "serde")]
+fn settings(overrides: serde_json::Value) -> serde_json::Value {
+    let mut settings = serde_json::json!({
+        "size": 0,
+        "hashes": [],
+        "mirrors": [],
+        "timeouts": { "connect": { "secs": 10, "nanos": 0 }, "read": null, "total": null },
+        "overwrite_policy": "overwrite",
+        "partial_policy": "delete",
+        "retries": false,
+        "segments": 1,
+        "atomic": false,
+        "resume": false,
+        "conditional": false,
+        "skip_if_valid": false,
+        "preverified": false,
+        "priority": 0,
+        "group": null,
+        "temp_dir": null,
+        "quarantine_dir": null,
+        "headers": [],
+        "cas": false,
+        "url_cache": false,
+    });
+    for (key, value) in overrides.as_object().unwrap() {
+        settings[key] = value.clone();
+    }
+    settings
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serializes_the_explanation() {
+    use serde_json::json;
+
+    let dir = temp_dir("explain-serde");
+    let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+    let explanation = batch(&dir).explain().unwrap();
+
+    let expected = json!({
+        "files": [
+            {
+                "path": path("fresh.bin"),
+                "url": "https://example.com/fresh.bin",
+                "metadata": { "kind": "model" },
+                "source": "network",
+                "settings": settings(json!({
+                    "timeouts": {
+                        "connect": { "secs": 10, "nanos": 0 },
+                        "read": { "secs": 3, "nanos": 0 },
+                        "total": null,
+                    },
+                    "priority": 9,
+                    "headers": ["Authorization"],
+                })),
+            },
+            {
+                "path": path("existing.bin"),
+                "url": "https://example.com/existing.bin",
+                "metadata": {},
+                "source": { "skip": "Destination already exists" },
+                "settings": settings(json!({ "overwrite_policy": "skip", "priority": 5 })),
+            },
+            {
+                "path": path("cached.bin"),
+                "url": "https://example.com/cached.bin",
+                "metadata": {},
+                "source": "cas",
+                "settings": settings(json!({ "hashes": ["sha1"], "cas": true })),
+            },
+            {
+                "path": path("valid.bin"),
+                "url": "https://example.com/valid.bin",
+                "metadata": {},
+                "source": { "skip": "Destination already valid" },
+                "settings": settings(json!({ "hashes": ["sha1"], "skip_if_valid": true })),
+            },
+        ]
+    });
+    assert_eq!(serde_json::to_value(&explanation).unwrap(), expected);
+}